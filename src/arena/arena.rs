@@ -1,20 +1,54 @@
-use crate::card::Card;
+use crate::card::{Card, CardDetails, CardId};
+use crate::gem::Gem;
+use crate::gems::Gems;
+use crate::nobles::{Noble, NobleId, MAX_NOBLES};
 use crate::game_logic::*;
 use crate::player::*;
 use crate::JSONable;
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
-use std::time::Duration;
-use tokio::sync::RwLock;
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{oneshot, RwLock};
+use tokio::task::JoinHandle;
 use super::*;
 
 use crate::arena::protocol::*;
 use crate::arena::replay::*;
 use crate::arena::clock::*;
 
+use derive_more::{Display, Error};
 use log::{debug, error, info, trace};
+use rand::rngs::StdRng;
+use rand::{thread_rng, Rng, SeedableRng};
 use warp::Filter;
 
+#[derive(Debug, Display, Error)]
+pub enum ActionError {
+    #[display(fmt = "The game is already over")]
+    GameOver,
+    #[display(fmt = "Action is not legal in the current game state")]
+    IllegalAction,
+}
+
+#[derive(Debug, Display, Error, PartialEq, Eq)]
+pub enum RewindError {
+    #[display(fmt = "Cannot rewind a game that has been finalized")]
+    GameFinalized,
+    #[display(fmt = "Cannot rewind more moves than have been played")]
+    NotEnoughMoves,
+}
+
+/// The state of the game after a single action is applied with `Arena::step`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepResult {
+    pub game_over: bool,
+    pub current_player_num: usize,
+    pub info: SmallClientInfo,
+}
+
 /// Builder for creating an arena,
 /// allows clients to flexibly include sane defaults or override them 
 /// with given parameters
@@ -27,6 +61,9 @@ pub struct ArenaBuilder {
     port : u16,
     send_to_web: bool,
     api_key: Option<String>,
+    randomize_first_player: bool,
+    first_player_seed: Option<u64>,
+    embed_cards: bool,
 }
 
 
@@ -41,6 +78,9 @@ impl ArenaBuilder {
             send_to_web: false,
             static_files: "splendor".to_string(),
             api_key: None,
+            randomize_first_player: false,
+            first_player_seed: None,
+            embed_cards: false,
         }
     }
 
@@ -86,11 +126,57 @@ impl ArenaBuilder {
         self
     }
 
+    /// Pick the seat that moves first at random instead of always seat 0,
+    /// so the binary launched first has no advantage across many games
+    pub fn randomize_first_player(mut self, randomize: bool) -> Self {
+        self.randomize_first_player = randomize;
+        self
+    }
+
+    /// Pick the seat that moves first at random, reproducibly from the given seed
+    pub fn randomize_first_player_seeded(mut self, seed: u64) -> Self {
+        self.randomize_first_player = true;
+        self.first_player_seed = Some(seed);
+        self
+    }
+
+    /// Send clients the full details of the face up and reserved cards
+    /// along with their ids, see `ClientInfo::with_embedded_cards`
+    pub fn embed_cards(mut self, embed: bool) -> Self {
+        self.embed_cards = embed;
+        self
+    }
+
     pub fn build(self) -> Arena {
         let card_lookup = Arc::new(Card::all());
         let num_players = self.binaries.len();
-        let game = Game::new(num_players as u8, card_lookup);
+        let mut game = Game::new(num_players as u8, card_lookup);
+        if self.randomize_first_player {
+            let first_player = match self.first_player_seed {
+                Some(seed) => StdRng::seed_from_u64(seed).gen_range(0..num_players),
+                None => thread_rng().gen_range(0..num_players),
+            };
+            game.set_first_player(first_player)
+                .expect("A new game has not started yet");
+        }
+        self.build_with_game(game)
+    }
+
+    /// Build an arena that only serves a finished game for viewing,
+    /// no binaries are launched and the replay is available immediately
+    pub fn build_with_replay(self, replay: Replay<Finalized>) -> Arena {
+        let final_game = replay.final_game();
+        let num_players = final_game.players().len();
+        let mut arena = self.build_with_game(final_game);
+        arena.player_names = (1..=num_players).map(|i| format!("Player {}", i)).collect();
+        arena.replay = Either::Finalized(Arc::new(RwLock::new(replay)));
+        arena
+    }
+
+    fn build_with_game(self, game: Game) -> Arena {
+        let num_players = game.players().len();
         let clients = self.binaries;
+        let player_names = clients.clone();
         let python_interpreter = self.python_interpreter;
         let initial_time = self.initial_time;
         let increment = self.increment;
@@ -98,18 +184,35 @@ impl ArenaBuilder {
         let port = self.port;
         let send_to_web = self.send_to_web;
         let api_key = self.api_key;
+        let embed_cards = self.embed_cards;
 
         Arena {
             game: game.clone(),
             replay: Either::Initialized(Replay::new(game)),
             clients,
             game_started: false,
+            started_at: None,
+            ended_at: None,
+            fallbacks: Vec::new(),
+            ready: vec![false; num_players],
+            connected: vec![false; num_players],
+            sessions: vec![0; num_players],
+            next_session: 0,
+            next_seat: 0,
+            seat_owners: (0..num_players).collect(),
+            usernames: vec![None; num_players],
+            player_names,
+            client_info: OnceLock::new(),
             clock: Clock::new(num_players, initial_time, increment),
+            clock_snapshots: Vec::new(),
             python_interpreter : python_interpreter.to_owned(),
             static_files: static_files.to_owned(),
             port,
             send_to_web,
             api_key,
+            game_id: None,
+            embed_cards,
+            request_id: 0,
         }
     }
 }
@@ -121,7 +224,23 @@ pub struct Arena {
     game: Game,  // The Splendor game state
     pub clients: Vec<String>, // The binaries or python files to be run as clients
     clock: Clock, // The clock for keeping track of each player's time
+    clock_snapshots: Vec<(usize, Clock)>, // The clock at the start of each turn, keyed by the
+                                          // history length, so a rewind can restore it
     game_started: bool, // Whether the game has started
+    started_at: Option<Instant>, // When the game started
+    ended_at: Option<Instant>, // When the game ended
+    fallbacks: Vec<usize>, // The history length at each move played for a client whose
+                           // message was rejected
+    ready: Vec<bool>, // Whether each player has signaled that it is ready to play
+    connected: Vec<bool>, // Whether each player's client currently has an open connection
+    sessions: Vec<u64>, // The connection currently serving each seat, see `connect_seats`
+    next_session: u64, // The session number given to the next connection
+    next_seat: usize, // The player number given to the next client that connects
+    seat_owners: Vec<usize>, // The client controlling each seat, named by the first seat it took
+    usernames: Vec<Option<String>>, // The names each player's client connected with
+    player_names: Vec<String>, // The names each seat is reported under in the results
+    client_info: OnceLock<ClientInfo>, // The client info for the current game state,
+                                       // cleared whenever the state changes
     replay: Either<Replay<Initialized>, FinalizedReplay>, // A representation of the game including
                                                           // the ability to walk through all
                                                           // previous moves
@@ -131,6 +250,10 @@ pub struct Arena {
     port : u16,           // The port to run the local web server on
     send_to_web: bool,  // Whether to send the game state to the global server
     api_key: Option<String>, // The api key to use for sending the game state to the global server
+    game_id: Option<String>, // The id the global server assigned to this game, used to reconnect
+    embed_cards: bool, // Whether clients are sent the full details of the cards they can see
+    request_id: u64, // Increases whenever the game state changes, clients answer with the
+                     // id of the state they were asked to act in
 }
 
 
@@ -140,16 +263,52 @@ impl Arena {
     }
 
     pub fn small_client_info(&self) -> SmallClientInfo{
-        let client_info = self.client_info();
         SmallClientInfo {
-            board: client_info.board,
-            players: client_info.players,
-            current_player_num: client_info.current_player_num,
+            board: BoardSnapshot::from_game(&self.game),
+            players: self.public_players(),
+            current_player_num: self.game.current_player_num(),
+        }
+    }
+
+    /// The public info of every player, labeled with their usernames
+    fn public_players(&self) -> Vec<PlayerPublicInfo> {
+        self.game
+            .players()
+            .iter()
+            .zip(self.usernames.iter())
+            .map(|(player, username)| PlayerPublicInfo {
+                username: username.clone(),
+                ..player.to_public()
+            })
+            .collect()
+    }
+
+    pub fn set_username(&mut self, player_num: usize, username: &str) {
+        if let Some(slot) = self.usernames.get_mut(player_num) {
+            *slot = Some(username.to_owned());
         }
+        self.client_info.take();
     }
 
+    pub fn username(&self, player_num: usize) -> Option<String> {
+        self.usernames.get(player_num).cloned().flatten()
+    }
+
+    /// How a player is labeled in the server's logs, their username if they gave one
+    pub fn log_label(&self, player_num: usize) -> String {
+        self.username(player_num).unwrap_or_else(|| player_num.to_string())
+    }
+
+    /// Get the client info for the current game state, which is only
+    /// rebuilt after the game state changes
     pub fn client_info(&self) -> ClientInfo {
-        let players = self.game.players().iter().map(|p| p.to_public()).collect();
+        self.client_info
+            .get_or_init(|| self.build_client_info())
+            .clone()
+    }
+
+    fn build_client_info(&self) -> ClientInfo {
+        let players = self.public_players();
         let legal_actions = self
             .game
             .get_legal_actions()
@@ -157,14 +316,24 @@ impl Arena {
 
         let time_endpoint_url = format!("http://127.0.0.1:{}/time", self.port);
 
-        ClientInfo {
+        let info = ClientInfo {
             board: Board::from_game(&self.game),
             history: self.game.history(),
             players,
             current_player: self.game.current_player(),
             current_player_num: self.game.current_player_num(),
+            phase: self.game.phase(),
+            noble_count: self.game.noble_count(),
+            reservations_remaining: self.game.current_player().reservations_remaining(),
             legal_actions,
             time_endpoint_url, 
+            cards: None,
+            request_id: self.request_id,
+        };
+        if self.embed_cards {
+            info.with_embedded_cards()
+        } else {
+            info
         }
     }
 
@@ -181,6 +350,60 @@ impl Arena {
         }
     }
 
+    /// Undo the last `n` substantive actions (anything but Pass and Continue)
+    /// by replaying the game from its initial deal. The turn and clock go back to
+    /// the start of the turn whose action was undone, refunding the time spent since,
+    /// and fallback moves that were undone are no longer counted
+    pub fn rewind(&mut self, n: usize) -> Result<(), RewindError> {
+        let initial_game = match &self.replay {
+            Either::Initialized(replay) => replay.initial_game().clone(),
+            Either::Finalized(_) => return Err(RewindError::GameFinalized),
+        };
+        let history = self.game.history().history;
+        let substantive: Vec<usize> = history
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, action))| !action.is_no_op())
+            .map(|(index, _)| index)
+            .collect();
+        if n > substantive.len() {
+            return Err(RewindError::NotEnoughMoves);
+        }
+        let keep = if n == 0 {
+            history.len()
+        } else {
+            substantive[substantive.len() - n]
+        };
+
+        let mut game = initial_game;
+        game.advance_history_with(&GameHistory::from(history[..keep].to_vec()));
+        self.game = game;
+        self.client_info.take();
+        self.request_id += 1;
+        self.ended_at = None;
+        self.fallbacks.retain(|&at| at < keep);
+        self.clock_snapshots.retain(|(at, _)| *at <= keep);
+        if let Some((_, clock)) = self.clock_snapshots.last() {
+            self.clock = clock.clone();
+        }
+        if self.game_started {
+            self.clock.restart_for(self.game.current_player_num());
+        }
+        Ok(())
+    }
+
+    /// A finalized arena serving the game recorded in an action log, see
+    /// `Replay::save_action_log`. Fails if any recorded action was not legal
+    /// or the log is for a different number of players
+    pub fn from_action_log(path: &Path, num_players: usize) -> Result<Arena, ActionLogError> {
+        let replay = Replay::load_action_log(path)?;
+        let found = replay.final_game().players().len();
+        if found != num_players {
+            return Err(ActionLogError::PlayerCount(found, num_players));
+        }
+        Ok(ArenaBuilder::new().build_with_replay(replay))
+    }
+
     pub fn get_replay(&self) -> Option<FinalizedReplay> {
         match &self.replay {
             Either::Finalized(replay) => Some(replay.clone()),
@@ -191,12 +414,18 @@ impl Arena {
     /// Play an action in the game. If the action is to continue, the clock will
     /// be updated to the next player
     pub fn play_action(&mut self, action : Action) {
+        self.client_info.take();
+        self.request_id += 1;
         self.game.play_action(action.clone());
+        if self.ended_at.is_none() && self.is_game_over() {
+            self.ended_at = Some(Instant::now());
+        }
         match action {
             Action::Continue => {
                 self.clock.end();
                 self.clock.next_player();
                 self.clock.start();
+                self.snapshot_clock();
             }
             _ => {}
         }
@@ -206,6 +435,45 @@ impl Arena {
         self.game.get_legal_actions()
     }
 
+    /// The id of the current game state, which the current player's
+    /// action must answer
+    pub fn request_id(&self) -> u64 {
+        self.request_id
+    }
+
+    /// See `Game::only_forced_move`
+    pub fn only_forced_move(&self) -> Option<Action> {
+        self.game.only_forced_move()
+    }
+
+    /// See `Game::suggested_noble`
+    pub fn suggested_noble(&self) -> Option<NobleId> {
+        self.game.suggested_noble()
+    }
+
+    /// Synchronously validate and apply a single action for the current player
+    /// without any networking, starting the game if it hasn't started yet
+    ///
+    /// Unlike the websocket protocol, forced actions (such as Pass and Continue)
+    /// are not played automatically and must be stepped through as well
+    pub fn step(&mut self, action: Action) -> Result<StepResult, ActionError> {
+        let legal_actions = self.get_legal_actions().ok_or(ActionError::GameOver)?;
+        if !legal_actions.contains(&action) {
+            return Err(ActionError::IllegalAction);
+        }
+
+        if !self.game_started {
+            self.start_game();
+        }
+        self.play_action(action);
+
+        Ok(StepResult {
+            game_over: self.is_game_over(),
+            current_player_num: self.game.current_player_num(),
+            info: self.small_client_info(),
+        })
+    }
+
     pub fn current_player_num(&self) -> Option<usize> {
         if self.game_started {
             Some(self.game.current_player_num())
@@ -218,6 +486,28 @@ impl Arena {
         self.game.get_winner()
     }
 
+    pub fn outcome(&self) -> GameOutcome {
+        self.game.outcome()
+    }
+
+    pub fn game_results(&self) -> GameResults {
+        GameResults {
+            outcome: self.game.outcome(),
+            player_names: self.player_names.clone(),
+        }
+    }
+
+    /// Name each seat for reporting (e.g. in tournaments),
+    /// seats default to the binary or script that plays them
+    pub fn set_player_names(&mut self, names: Vec<String>) {
+        debug_assert!(names.len() == self.player_names.len());
+        self.player_names = names;
+    }
+
+    pub fn player_names(&self) -> &Vec<String> {
+        &self.player_names
+    }
+
     pub fn board(&self) -> Board {
         Board::from_game(&self.game)
     }
@@ -226,6 +516,18 @@ impl Arena {
         self.game.players()
     }
 
+    /// The settings the game is played with, sent to every client on connect
+    pub fn game_config(&self) -> GameConfig {
+        GameConfig {
+            num_players: self.game.players().len(),
+            initial_time: self.clock.initial_time,
+            increment: self.clock.increment,
+            win_threshold: WIN_THRESHOLD,
+            card_set_hash: Card::set_hash(&self.game.card_lookup()),
+            seats: Vec::new(),
+        }
+    }
+
     pub fn is_timed_out(&self) -> bool {
         self.clock.time_remaining() <= Duration::from_secs(0)
     }
@@ -234,9 +536,157 @@ impl Arena {
         self.clock.time_remaining()
     }
 
+    /// See `Clock::time_remaining_for`
+    pub fn time_remaining_for(&self, player_num: usize) -> Duration {
+        self.clock.time_remaining_for(player_num)
+    }
+
+    /// Grant a player extra time on their clock, see `Clock::add_time`
+    pub fn add_time(&mut self, player_num: usize, extra: Duration) -> Result<(), ClockError> {
+        self.client_info.take();
+        self.clock.add_time(player_num, extra)
+    }
+
+    /// Mark a player as ready to begin the game, returns true if this
+    /// was the last player the game was waiting on
+    pub fn mark_ready(&mut self, player_num: usize) -> bool {
+        if self.game_started || player_num >= self.ready.len() {
+            return false;
+        }
+        let was_ready = self.all_ready();
+        self.ready[player_num] = true;
+        !was_ready && self.all_ready()
+    }
+
+    /// Hand out player numbers to clients in the order they connect,
+    /// None once every seat is taken
+    pub fn take_seat(&mut self) -> Option<usize> {
+        self.take_seats(1).map(|seats| seats[0])
+    }
+
+    /// Hand out `count` consecutive player numbers to a single client,
+    /// which is identified by the first of them. A client asking for more
+    /// seats than are left gets the rest, None once every seat is taken
+    pub fn take_seats(&mut self, count: usize) -> Option<Vec<usize>> {
+        let seats_left = self.seat_owners.len().saturating_sub(self.next_seat);
+        if seats_left == 0 {
+            return None;
+        }
+        let count = count.clamp(1, seats_left);
+        let seats: Vec<usize> = (self.next_seat..self.next_seat + count).collect();
+        for &seat in &seats {
+            if let Some(owner) = self.seat_owners.get_mut(seat) {
+                *owner = seats[0];
+            }
+        }
+        self.next_seat += seats.len();
+        Some(seats)
+    }
+
+    /// The client that plays for the given seat
+    pub fn seat_owner(&self, player_num: usize) -> usize {
+        self.seat_owners.get(player_num).copied().unwrap_or(player_num)
+    }
+
+    /// All seats played by the given client
+    pub fn seats_of(&self, client_id: usize) -> Vec<usize> {
+        self.seat_owners
+            .iter()
+            .enumerate()
+            .filter(|(_, &owner)| owner == client_id)
+            .map(|(seat, _)| seat)
+            .collect()
+    }
+
+    /// The seats of a client that connected before, for it to play them again
+    /// after its connection dropped. None if no client took the given seat
+    /// or it is not the seat the client is identified by
+    pub fn reclaim_seats(&self, client_id: usize) -> Option<Vec<usize>> {
+        if client_id >= self.next_seat || self.seat_owner(client_id) != client_id {
+            return None;
+        }
+        Some(self.seats_of(client_id))
+    }
+
+    /// Mark the seats as connected to a new connection and return its session
+    /// number. A client that reconnects gets a new session, which tells
+    /// whatever served its old connection to stop
+    pub fn connect_seats(&mut self, seats: &[usize]) -> u64 {
+        self.next_session += 1;
+        for &seat in seats {
+            self.mark_connected(seat);
+            if let Some(session) = self.sessions.get_mut(seat) {
+                *session = self.next_session;
+            }
+        }
+        self.next_session
+    }
+
+    /// Whether the given session still serves the seats of the client
+    pub fn is_current_session(&self, client_id: usize, session: u64) -> bool {
+        self.sessions.get(client_id) == Some(&session)
+    }
+
+    pub fn mark_connected(&mut self, player_num: usize) {
+        if let Some(connected) = self.connected.get_mut(player_num) {
+            *connected = true;
+        }
+    }
+
+    pub fn mark_disconnected(&mut self, player_num: usize) {
+        if let Some(connected) = self.connected.get_mut(player_num) {
+            *connected = false;
+        }
+    }
+
+    /// The players whose clients currently have an open connection,
+    /// as opposed to every seat the arena allows
+    pub fn connected_clients(&self) -> Vec<usize> {
+        self.connected
+            .iter()
+            .enumerate()
+            .filter(|(_, &connected)| connected)
+            .map(|(player_num, _)| player_num)
+            .collect()
+    }
+
+    /// Whether every seat is filled by a player that is ready to play
+    pub fn all_ready(&self) -> bool {
+        self.ready.iter().all(|&ready| ready)
+    }
+
     pub fn start_game(&mut self) {
+        self.client_info.take();
         self.game_started = true;
-        self.clock.start();
+        self.started_at = Some(Instant::now());
+        self.clock.restart_for(self.game.current_player_num());
+        self.snapshot_clock();
+    }
+
+    /// Remember the clock at the start of the current turn, see `rewind`
+    fn snapshot_clock(&mut self) {
+        let at = self.game.num_actions();
+        self.clock_snapshots.push((at, self.clock.clone()));
+    }
+
+    /// Count a move played on behalf of a client whose message was rejected
+    pub fn record_fallback(&mut self) {
+        self.fallbacks.push(self.game.num_actions());
+    }
+
+    pub fn metrics(&self) -> GameMetrics {
+        let duration = match (self.started_at, self.ended_at) {
+            (Some(start), Some(end)) => end - start,
+            (Some(start), None) => start.elapsed(),
+            _ => Duration::from_secs(0),
+        };
+        GameMetrics {
+            total_moves: self.game.history().num_actions(),
+            duration,
+            time_used: self.clock.time_used().clone(),
+            timeouts: self.clock.timeouts(),
+            fallbacks: self.fallbacks.len(),
+        }
     }
 }
 
@@ -244,7 +694,55 @@ impl Arena {
     pub fn api_key(&self) -> Option<String> {
         self.api_key.clone()
     }
+
+    /// The id stourney.com assigned to this game once it was initialized,
+    /// None if the game has not been sent to the global server
+    pub fn game_id(&self) -> Option<&str> {
+        self.game_id.as_deref()
+    }
+
+    pub fn set_game_id(&mut self, id: String) {
+        self.game_id = Some(id);
+    }
+
+    /// Run the arena until the process is killed
     pub async fn launch(self) {
+        self.launch_until(std::future::pending()).await
+    }
+
+    /// Run the arena on a background task, returning the task along with
+    /// a trigger that gracefully shuts down the server when sent
+    pub fn spawn(self) -> (JoinHandle<()>, oneshot::Sender<()>) {
+        let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+        let handle = tokio::spawn(self.launch_until(async move {
+            shutdown_rx.await.ok();
+        }));
+        (handle, shutdown_tx)
+    }
+
+    /// Serve only the replay of a finished game (see `ArenaBuilder::build_with_replay`)
+    /// until the process is killed, no game websocket is opened
+    pub async fn launch_replay(self) {
+        self.launch_replay_until(std::future::pending()).await
+    }
+
+    /// Serve only the replay of a finished game until the given shutdown signal resolves
+    pub async fn launch_replay_until(self, shutdown: impl Future<Output = ()> + Send + 'static) {
+        let port = self.port;
+        let static_files_loc = self.static_files.clone();
+        let arena = Arc::new(RwLock::new(self));
+
+        let splendor = warp::path("splendor").and(warp::fs::dir(static_files_loc.clone()));
+        let static_files = warp::path("static_files").and(warp::fs::dir(static_files_loc));
+        let routes = replay_routes(arena).or(splendor).or(static_files);
+
+        debug!("Serving replay on port {}", port);
+        let (_, server) = warp::serve(routes).bind_with_graceful_shutdown(([127, 0, 0, 1], port), shutdown);
+        server.await;
+    }
+
+    /// Run the arena until the given shutdown signal resolves
+    pub async fn launch_until(self, shutdown: impl Future<Output = ()> + Send + 'static) {
         let init_binaries = self.clients.clone();
         let python_interpreter = self.python_interpreter.clone();
         let port = self.port;
@@ -263,71 +761,20 @@ impl Arena {
         // Turn our "clients" state into a new Filter...
         let clients = warp::any().map(move || clients.clone());
 
-        let replay_post = warp::post()
-            .and(warp::path("replay"));
-
-        let replay_get = warp::get()
-            .and(warp::path("replay"));
-
-        let replay_next = replay_post
-            .and(warp::path("next"))
-            .and(arena_filter.clone())
-            .and_then(replay::next_move);
-
-        let replay_prev = replay_post 
-            .and(warp::path("previous"))
-            .and(arena_filter.clone())
-            .and_then(replay::previous_move);
-
-        let replay_goto = replay_post 
-            .and(warp::path("goto"))
-            .and(replay::json_body())
-            .and(arena_filter.clone())
-            .and_then(replay::go_to_move);
-
-        let replay_board_nobles = replay_get 
-            .and(warp::path("nobles"))
-            .and(arena_filter.clone())
-            .and_then(replay::board_nobles);
-
-        let replay_board_cards = replay_get 
-            .and(warp::path("cards"))
-            .and(arena_filter.clone())
-            .and_then(replay::board_cards);
+        let replay = replay_routes(arena_clone.clone());
 
-        let replay_board_decks = replay_get 
-            .and(warp::path("decks"))
-            .and(arena_filter.clone())
-            .and_then(replay::board_decks);
+        let time = clock::clock_routes(arena_clone.clone());
 
-        let replay_board_bank = replay_get 
-            .and(warp::path("bank"))
-            .and(arena_filter.clone())
-            .and_then(replay::board_bank);
 
-        let replay_board_players = replay_get
-            .and(warp::path("players"))
-            .and(arena_filter.clone())
-            .and_then(replay::board_players);
-
-        let replay = replay_next
-            .or(replay_prev)
-            .or(replay_goto)
-            .or(replay_board_nobles)
-            .or(replay_board_cards)
-            .or(replay_board_decks)
-            .or(replay_board_bank)
-            .or(replay_board_players);
-
-        let time = warp::get()
-            .and(warp::path("time"))
+        let metrics = warp::get()
+            .and(warp::path("metrics"))
             .and(arena_filter.clone())
-            .and_then(clock::current_time_remaining);
-
+            .and_then(current_metrics);
 
         let log = warp::path("log")
             .and(warp::ws())
-            .map(|ws: warp::ws::Ws| ws.on_upgrade(move |socket| log_stream_connected(socket)));
+            .and(arena_filter.clone())
+            .map(|ws: warp::ws::Ws, arena| ws.on_upgrade(move |socket| log_stream_connected(socket, arena)));
 
         let splendor = warp::path("splendor").and(warp::fs::dir(static_files_loc.clone()));
         let static_files = warp::path("static_files").and(warp::fs::dir(static_files_loc));
@@ -376,20 +823,128 @@ impl Arena {
         let web_stream_filter = warp::any().map(move || web_stream.clone());
         let game = warp::path("game")
             .and(warp::ws())
+            .and(warp::query::<ConnectParams>())
             .and(clients)
             .and(arena_filter.clone())
             .and(web_stream_filter)
-            .map(|ws: warp::ws::Ws, clients, arena, web_stream| {
-                ws.on_upgrade(move |socket| user_connected(socket, clients, arena, web_stream))
+            .map(|ws: warp::ws::Ws, params, clients, arena, web_stream| {
+                ws.on_upgrade(move |socket| user_connected(socket, params, clients, arena, web_stream))
             });
 
-        let routes = game.or(log).or(replay).or(time).or(splendor).or(static_files);
+        let routes = game.or(log).or(replay).or(time).or(metrics).or(splendor).or(static_files);
         // Start the server on localhost at the specified port
-        warp::serve(routes).run(([127, 0, 0, 1], port)).await;
+        let (_, server) = warp::serve(routes).bind_with_graceful_shutdown(([127, 0, 0, 1], port), shutdown);
+        server.await;
     }
 }
 
-pub struct GameResults {}
+/// The endpoints for walking through the replay of a finished game
+fn replay_routes(
+    arena: GlobalArena,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let arena_filter = warp::any().map(move || arena.clone());
+
+    let replay_post = warp::post()
+        .and(warp::path("replay"));
+
+    let replay_get = warp::get()
+        .and(warp::path("replay"));
+
+    let replay_next = replay_post
+        .and(warp::path("next"))
+        .and(arena_filter.clone())
+        .and_then(replay::next_move);
+
+    let replay_prev = replay_post 
+        .and(warp::path("previous"))
+        .and(arena_filter.clone())
+        .and_then(replay::previous_move);
+
+    let replay_goto = replay_post 
+        .and(warp::path("goto"))
+        .and(replay::json_body())
+        .and(arena_filter.clone())
+        .and_then(replay::go_to_move);
+
+    let replay_board_nobles = replay_get 
+        .and(warp::path("nobles"))
+        .and(arena_filter.clone())
+        .and_then(replay::board_nobles);
+
+    let replay_board_cards = replay_get 
+        .and(warp::path("cards"))
+        .and(arena_filter.clone())
+        .and_then(replay::board_cards);
+
+    let replay_board_decks = replay_get 
+        .and(warp::path("decks"))
+        .and(arena_filter.clone())
+        .and_then(replay::board_decks);
+
+    let replay_board_bank = replay_get 
+        .and(warp::path("bank"))
+        .and(arena_filter.clone())
+        .and_then(replay::board_bank);
+
+    let replay_board_players = replay_get
+        .and(warp::path("players"))
+        .and(arena_filter.clone())
+        .and_then(replay::board_players);
+
+    let replay_board_current = replay_get
+        .and(warp::path("current"))
+        .and(arena_filter.clone())
+        .and_then(replay::board_current);
+
+    let replay_ws = warp::path("replay")
+        .and(warp::path("ws"))
+        .and(warp::ws())
+        .and(arena_filter.clone())
+        .map(|ws: warp::ws::Ws, arena| {
+            ws.on_upgrade(move |socket| replay::replay_connected(socket, arena))
+        });
+
+    replay_ws
+        .or(replay_next)
+        .or(replay_prev)
+        .or(replay_goto)
+        .or(replay_board_nobles)
+        .or(replay_board_cards)
+        .or(replay_board_decks)
+        .or(replay_board_bank)
+        .or(replay_board_players)
+        .or(replay_board_current)
+}
+
+/// Statistics about a single game for monitoring the arena
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameMetrics {
+    /// The number of actions played, including forced ones
+    pub total_moves: usize,
+    /// Wall clock time since the game started, until it ended if it is over
+    pub duration: Duration,
+    /// The time each player spent on their completed moves
+    pub time_used: Vec<Duration>,
+    /// The number of players that ran out of time
+    pub timeouts: usize,
+    /// The number of moves played for clients whose message was rejected
+    pub fallbacks: usize,
+}
+
+pub async fn current_metrics(arena: GlobalArena) -> Result<impl warp::Reply, warp::Rejection> {
+    let metrics = arena.read().await.metrics();
+    Ok(warp::reply::json(&metrics))
+}
+
+/// The final results of a game, given to each client once the game is over
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameResults {
+    pub outcome: GameOutcome,
+    /// The name of each seat, indexed by player number
+    #[serde(default)]
+    pub player_names: Vec<String>,
+}
+
 /// A struct given to each client that contains all public information and private
 /// information known only to that client.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -399,18 +954,1477 @@ pub struct ClientInfo {
     pub players: Vec<PlayerPublicInfo>,
     pub current_player: Player,
     pub current_player_num: usize,
+    #[serde(default)]
+    pub phase: Phase,
+    /// The number of nobles that have not been attracted yet
+    #[serde(default)]
+    pub noble_count: usize,
+    /// The number of cards the current player can still reserve
+    #[serde(default)]
+    pub reservations_remaining: usize,
     pub legal_actions: Vec<Action>,
     pub time_endpoint_url: String,
+    /// The full details of the cards the player can see, only sent
+    /// by arenas built with `ArenaBuilder::embed_cards`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cards: Option<EmbeddedCards>,
+    /// Identifies the game state the player is asked to act in,
+    /// it must be sent back along with the action
+    #[serde(default)]
+    pub request_id: u64,
+}
+
+/// The cards a `ClientInfo` refers to by id, resolved so clients
+/// do not need their own copy of the card table
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EmbeddedCards {
+    /// The face up cards by tier, in the order of `Board::available_cards`
+    pub board: Vec<Vec<CardDetails>>,
+    /// The reserved cards of each player in seat order. The current player's
+    /// include the ones reserved face down, the other players' only the
+    /// ones reserved face up
+    pub reserved: Vec<Vec<CardDetails>>,
+}
+
+impl ClientInfo {
+    /// The info that the player to act in the given game would receive
+    pub fn from_game(game: &Game, time_endpoint_url: String) -> ClientInfo {
+        ClientInfo {
+            board: Board::from_game(game),
+            history: game.history(),
+            players: game.players().iter().map(|p| p.to_public()).collect(),
+            current_player: game.current_player(),
+            current_player_num: game.current_player_num(),
+            phase: game.phase(),
+            noble_count: game.noble_count(),
+            reservations_remaining: game.current_player().reservations_remaining(),
+            legal_actions: game.get_legal_actions().unwrap_or_default(),
+            time_endpoint_url,
+            cards: None,
+            request_id: 0,
+        }
+    }
+
+    /// The same info with the face up and reserved cards it refers to
+    /// embedded in `cards`
+    pub fn with_embedded_cards(mut self) -> ClientInfo {
+        let details = |ids: &[CardId]| -> Vec<CardDetails> {
+            ids.iter().filter_map(|&id| Card::from_id(id)).map(CardDetails::from).collect()
+        };
+        let board = self.board.available_cards.iter().map(|tier| details(tier)).collect();
+        let reserved = self
+            .players
+            .iter()
+            .enumerate()
+            .map(|(player_num, player)| {
+                if player_num == self.current_player_num {
+                    details(&self.current_player.all_reserved())
+                } else {
+                    details(&player.public_reserved)
+                }
+            })
+            .collect();
+        self.cards = Some(EmbeddedCards { board, reserved });
+        self
+    }
+
+    /// The developments of each player, in seat order
+    pub fn developments_summary(&self) -> Vec<Gems> {
+        self.players.iter().map(|p| p.developments.to_gems()).collect()
+    }
+
+    /// The whole observable state as one vector of numbers, meant as the
+    /// observation for training agents. The layout is stable, with colors
+    /// always in the order of `Gem::ALL`:
+    ///
+    /// * the bank (6 entries)
+    /// * for each player, in seat order: their gems (6), developments (5)
+    ///   and points (1)
+    /// * the requirements (5) of up to `MAX_NOBLES` nobles, in board order
+    /// * for each of the 4 face up slots of the 3 tiers: the card's cost (5),
+    ///   points (1) and the gem it produces, one-hot (5)
+    ///
+    /// Nobles and cards that are missing, including a slot left empty once
+    /// its deck ran out, are filled with zeros, so the length
+    /// only depends on the number of players: `6 + 12 * players + 157`
+    pub fn to_feature_vector(&self) -> Vec<f32> {
+        let mut features = Vec::new();
+        features.extend(Gem::ALL.iter().map(|&gem| self.board.gems[gem] as f32));
+
+        for player in &self.players {
+            features.extend(Gem::ALL.iter().map(|&gem| player.gems[gem] as f32));
+            features.extend(Gem::ALL_EXCEPT_GOLD.iter().map(|&gem| player.developments[gem] as f32));
+            features.push(player.points as f32);
+        }
+
+        for slot in 0..MAX_NOBLES {
+            match self.board.nobles.get(slot) {
+                Some(&id) => {
+                    let requirements = *Noble::from_id(id).requirements();
+                    features.extend(Gem::ALL_EXCEPT_GOLD.iter().map(|&gem| requirements[gem] as f32));
+                }
+                None => features.extend([0.0; 5]),
+            }
+        }
+
+        let slots = self.board.slot_layout();
+        for tier in 0..3 {
+            for slot in 0..4 {
+                let card = slots
+                    .get(tier)
+                    .and_then(|cards| cards.get(slot))
+                    .and_then(|&id| id)
+                    .and_then(Card::from_id);
+                match card {
+                    Some(card) => {
+                        let cost = card.cost();
+                        features.extend(Gem::ALL_EXCEPT_GOLD.iter().map(|&gem| cost[gem] as f32));
+                        features.push(card.points() as f32);
+                        features.extend(
+                            Gem::ALL_EXCEPT_GOLD
+                                .iter()
+                                .map(|&gem| if card.gem() == gem { 1.0 } else { 0.0 }),
+                        );
+                    }
+                    None => features.extend([0.0; 11]),
+                }
+            }
+        }
+        features
+    }
+
+    /// The legal actions grouped by their kind, kinds without
+    /// any legal actions are left out
+    pub fn actions_by_kind(&self) -> HashMap<ActionKind, Vec<Action>> {
+        let mut grouped: HashMap<ActionKind, Vec<Action>> = HashMap::new();
+        for action in &self.legal_actions {
+            grouped.entry(action.kind()).or_default().push(action.clone());
+        }
+        grouped
+    }
+
+    /// Reconstruct the game from the point of view of the client that is
+    /// to act, see `Game::from_view`
+    pub fn to_game(&self) -> Game {
+        Game::from_view(
+            &self.board,
+            &self.players,
+            self.current_player_num,
+            &self.current_player,
+            self.phase,
+            &self.history,
+        )
+    }
+
+    /// Play an action on a local reconstruction of the game (see `ClientInfo::to_game`)
+    /// and return the info that the next player to act would receive
+    pub fn simulate(&self, action: Action) -> Result<ClientInfo, ActionError> {
+        if self.legal_actions.is_empty() {
+            return Err(ActionError::GameOver);
+        }
+        if !self.legal_actions.contains(&action) {
+            return Err(ActionError::IllegalAction);
+        }
+
+        let mut game = self.to_game();
+        game.play_action(action);
+
+        let mut info = ClientInfo::from_game(&game, self.time_endpoint_url.clone());
+        for (player, known) in info.players.iter_mut().zip(self.players.iter()) {
+            player.username = known.username.clone();
+        }
+        Ok(info)
+    }
 }
 
 /// A struct given to each client that contains all public information and private
 /// information known only to that client.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SmallClientInfo {
-    pub board: Board,
+    pub board: BoardSnapshot,
     pub players: Vec<PlayerPublicInfo>,
     pub current_player_num: usize,
 }
 
 
 impl JSONable for ClientInfo {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Prefer purchasing cards, then taking gems, so the game
+    /// always progresses towards a winner
+    fn greedy_action(actions: &[Action]) -> Action {
+        let purchase = actions.iter().find(|a| matches!(a, Action::Purchase { .. }));
+        let take = actions
+            .iter()
+            .find(|a| matches!(a, Action::TakeDistinct(_) | Action::TakeDouble(_)));
+        purchase.or(take).unwrap_or(&actions[0]).clone()
+    }
+
+    #[test]
+    fn test_step_full_game() {
+        let mut arena = ArenaBuilder::new()
+            .binaries(vec!["bot_a".to_string(), "bot_b".to_string()])
+            .build();
+
+        let mut steps = 0;
+        loop {
+            let actions = arena.get_legal_actions().expect("Game ended early");
+            let result = arena.step(greedy_action(&actions)).unwrap();
+            assert_eq!(result.current_player_num, result.info.current_player_num);
+            steps += 1;
+            if result.game_over {
+                break;
+            }
+            assert!(steps < 10_000, "Game did not finish");
+        }
+
+        assert!(arena.is_game_over());
+        assert!(matches!(arena.step(Action::Continue), Err(ActionError::GameOver)));
+        assert!(matches!(arena.outcome(), GameOutcome::Winner(_) | GameOutcome::Draw));
+    }
+
+    #[test]
+    fn test_simulate_take_gems() {
+        let arena = ArenaBuilder::new()
+            .binaries(vec!["a".to_string(), "b".to_string()])
+            .build();
+        let info = arena.client_info();
+
+        let take = info
+            .legal_actions
+            .iter()
+            .find(|a| matches!(a, Action::TakeDistinct(_)))
+            .expect("Should be able to take gems at the start")
+            .clone();
+        let taken = match &take {
+            Action::TakeDistinct(colors) => Gems::from_set(colors),
+            _ => unreachable!(),
+        };
+
+        let next = info.simulate(take).unwrap();
+        assert_eq!(next.players[0].gems, taken);
+        assert_eq!(next.board.gems, info.board.gems - taken);
+        assert_eq!(next.board.deck_counts, info.board.deck_counts);
+        assert_eq!(next.current_player_num, 0);
+        assert_eq!(next.phase, Phase::NobleAction);
+
+        // The arena itself is untouched
+        assert_eq!(arena.client_info().board.gems, info.board.gems);
+        assert!(matches!(info.simulate(Action::Continue), Err(ActionError::IllegalAction)));
+    }
+
+    #[test]
+    fn test_actions_by_kind_opening() {
+        let arena = ArenaBuilder::new()
+            .binaries(vec!["a".to_string(), "b".to_string()])
+            .build();
+        let grouped = arena.client_info().actions_by_kind();
+
+        assert_eq!(grouped[&ActionKind::TakeDouble].len(), 5);
+        assert_eq!(grouped[&ActionKind::TakeDistinct].len(), 10);
+        assert_eq!(grouped[&ActionKind::Reserve].len(), 12);
+        assert_eq!(grouped[&ActionKind::ReserveHidden].len(), 3);
+        assert!(!grouped.contains_key(&ActionKind::Purchase));
+        assert_eq!(grouped.values().map(|a| a.len()).sum::<usize>(), 30);
+    }
+
+    #[test]
+    fn test_game_from_client_info_matches_arena() {
+        let mut arena = ArenaBuilder::new()
+            .binaries(vec!["a".to_string(), "b".to_string(), "c".to_string()])
+            .build();
+        for _ in 0..12 {
+            let actions = arena.get_legal_actions().unwrap();
+            let reserve = actions
+                .iter()
+                .find(|a| matches!(a, Action::ReserveHidden(_)))
+                .cloned();
+            arena.step(reserve.unwrap_or_else(|| greedy_action(&actions))).unwrap();
+        }
+
+        let info = arena.client_info();
+        let game = info.to_game();
+        assert_eq!(game.deck_counts(), info.board.deck_counts);
+        for (player, public) in game.players().iter().zip(info.players.iter()) {
+            assert_eq!(player.num_reserved_cards(), public.num_reserved);
+            assert_eq!(player.gems(), &public.gems);
+        }
+
+        let rebuilt = ClientInfo::from_game(&game, info.time_endpoint_url.clone());
+        assert_eq!(rebuilt.phase, info.phase);
+        assert_eq!(rebuilt.legal_actions.len(), info.legal_actions.len());
+        assert!(info.legal_actions.iter().all(|a| rebuilt.legal_actions.contains(a)));
+    }
+
+    #[tokio::test]
+    async fn test_spawn_and_shutdown() {
+        let arena = ArenaBuilder::new()
+            .binaries(vec!["a".to_string(), "b".to_string()])
+            .port(3167)
+            .build();
+
+        let (handle, shutdown) = arena.spawn();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!handle.is_finished());
+
+        shutdown.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), handle)
+            .await
+            .expect("Server did not shut down")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_metrics_after_scripted_game() {
+        let arena = ArenaBuilder::new()
+            .binaries(vec!["bot_a".to_string(), "bot_b".to_string()])
+            .initial_time(Duration::from_secs(0))
+            .build();
+        let arena = Arc::new(RwLock::new(arena));
+
+        // Player 0 has no time at all, so finishing their turn times them out
+        {
+            let mut arena = arena.write().await;
+            let take = arena.get_legal_actions().unwrap()[0].clone();
+            arena.step(take).unwrap();
+            std::thread::sleep(Duration::from_millis(2));
+            arena.step(Action::Pass).unwrap();
+            arena.step(Action::Continue).unwrap();
+            arena.record_fallback();
+        }
+
+        let route = warp::path("metrics")
+            .and(warp::any().map(move || arena.clone()))
+            .and_then(current_metrics);
+        let response = warp::test::request()
+            .method("GET")
+            .path("/metrics")
+            .reply(&route)
+            .await;
+        let metrics: GameMetrics = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(metrics.total_moves, 3);
+        assert_eq!(metrics.timeouts, 1);
+        assert_eq!(metrics.fallbacks, 1);
+        assert_eq!(metrics.time_used, vec![Duration::from_secs(0); 2]);
+        assert!(metrics.duration >= Duration::from_millis(2));
+    }
+
+    #[test]
+    fn test_rewind_one_move() {
+        let mut arena = ArenaBuilder::new()
+            .binaries(vec!["bot_a".to_string(), "bot_b".to_string()])
+            .build();
+        let opening = arena.get_legal_actions().unwrap();
+
+        let take = greedy_action(&opening);
+        arena.step(take).unwrap();
+        arena.step(Action::Pass).unwrap();
+        arena.step(Action::Continue).unwrap();
+        assert_eq!(arena.current_player_num(), Some(1));
+        assert_ne!(arena.get_legal_actions().unwrap(), opening);
+
+        arena.rewind(1).unwrap();
+        assert_eq!(arena.current_player_num(), Some(0));
+        assert_eq!(arena.get_legal_actions().unwrap(), opening);
+        assert_eq!(arena.client_info().history.num_actions(), 0);
+        assert_eq!(arena.rewind(1), Err(RewindError::NotEnoughMoves));
+
+        while !arena.is_game_over() {
+            let actions = arena.get_legal_actions().unwrap();
+            arena.step(greedy_action(&actions)).unwrap();
+        }
+        arena.finalize_game();
+        assert_eq!(arena.rewind(1), Err(RewindError::GameFinalized));
+    }
+
+    #[test]
+    fn test_rewind_restores_clock_and_fallbacks() {
+        let mut arena = ArenaBuilder::new()
+            .binaries(vec!["bot_a".to_string(), "bot_b".to_string()])
+            .initial_time(Duration::from_secs(10))
+            .increment(Duration::from_secs(5))
+            .build();
+        arena.start_game();
+
+        // A fallback move for player 0 earns them the increment
+        arena.record_fallback();
+        let take = greedy_action(&arena.get_legal_actions().unwrap());
+        arena.step(take).unwrap();
+        arena.step(Action::Pass).unwrap();
+        arena.step(Action::Continue).unwrap();
+        assert!(arena.time_remaining_for(0) > Duration::from_secs(14));
+        assert_eq!(arena.metrics().fallbacks, 1);
+
+        arena.rewind(1).unwrap();
+        assert!(arena.time_remaining_for(0) <= Duration::from_secs(10));
+        assert_eq!(arena.time_remaining_for(1), Duration::from_secs(10));
+        assert_eq!(arena.metrics().fallbacks, 0);
+    }
+
+    #[test]
+    fn test_player_names_in_results() {
+        let mut arena = ArenaBuilder::new()
+            .binaries(vec!["bot_a".to_string(), "bot_b".to_string()])
+            .build();
+        assert_eq!(arena.game_results().player_names, vec!["bot_a", "bot_b"]);
+
+        arena.set_player_names(vec!["alice".to_string(), "bob".to_string()]);
+        while !arena.is_game_over() {
+            let actions = arena.get_legal_actions().unwrap();
+            arena.step(greedy_action(&actions)).unwrap();
+        }
+
+        let results = arena.game_results();
+        assert_eq!(results.player_names, vec!["alice", "bob"]);
+        let results = serde_json::from_str::<GameResults>(&serde_json::to_string(&results).unwrap()).unwrap();
+        assert_eq!(results.player_names, vec!["alice", "bob"]);
+    }
+
+    #[tokio::test]
+    async fn test_serve_saved_replay() {
+        let mut arena = ArenaBuilder::new()
+            .binaries(vec!["bot_a".to_string(), "bot_b".to_string()])
+            .build();
+        while !arena.is_game_over() {
+            let actions = arena.get_legal_actions().unwrap();
+            arena.step(greedy_action(&actions)).unwrap();
+        }
+        arena.finalize_game();
+        let replay = arena.get_replay().unwrap();
+        let path = std::env::temp_dir().join("splendor_test_serve_saved_replay.json");
+        replay.read().await.save(&path).unwrap();
+
+        let replay = Replay::load(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(replay.final_game().players(), arena.players());
+
+        let arena = ArenaBuilder::new().build_with_replay(replay);
+        let arena = Arc::new(RwLock::new(arena));
+        let response = warp::test::request()
+            .method("GET")
+            .path("/replay/cards")
+            .reply(&replay_routes(arena))
+            .await;
+        assert_eq!(response.status(), 200);
+
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        let tiers = body["success"]["cards"].as_array().expect("No cards in replay");
+        assert_eq!(tiers.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_action_log_round_trip() {
+        let mut arena = ArenaBuilder::new()
+            .binaries(vec!["bot_a".to_string(), "bot_b".to_string()])
+            .build();
+        while !arena.is_game_over() {
+            let actions = arena.get_legal_actions().unwrap();
+            arena.step(greedy_action(&actions)).unwrap();
+        }
+        arena.finalize_game();
+        let replay = arena.get_replay().unwrap();
+        let path = std::env::temp_dir().join("splendor_test_action_log_round_trip.jsonl");
+        replay.read().await.save_action_log(&path).unwrap();
+
+        let lines = std::fs::read_to_string(&path).unwrap().lines().count();
+        assert_eq!(lines, arena.game.history().num_actions() + 1);
+
+        let restored = Arena::from_action_log(&path, 2).unwrap();
+        assert!(restored.is_game_over());
+        assert_eq!(restored.outcome(), arena.outcome());
+        assert_eq!(restored.players(), arena.players());
+        assert_eq!(
+            serde_json::to_value(restored.board()).unwrap(),
+            serde_json::to_value(arena.board()).unwrap()
+        );
+        assert_eq!(restored.game.history().history, arena.game.history().history);
+        let restored_replay = restored.get_replay().expect("The restored game is finalized");
+        assert_eq!(restored_replay.read().await.num_moves(), replay.read().await.num_moves());
+
+        assert!(matches!(
+            Arena::from_action_log(&path, 3),
+            Err(ActionLogError::PlayerCount(2, 3))
+        ));
+
+        // Tampering with an action is caught while replaying the log
+        let log = std::fs::read_to_string(&path).unwrap();
+        let mut tampered: Vec<String> = log.lines().map(String::from).collect();
+        tampered[1] = serde_json::to_string(&(1, Action::Pass)).unwrap();
+        std::fs::write(&path, tampered.join("\n")).unwrap();
+        let result = Arena::from_action_log(&path, 2);
+        std::fs::remove_file(&path).ok();
+        match result {
+            Err(ActionLogError::Invalid(anomaly)) => assert_eq!(anomaly.index, 0),
+            Err(e) => panic!("Expected an invalid action, got {}", e),
+            Ok(_) => panic!("Expected an invalid action"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_sent_on_connect() {
+        use futures_util::StreamExt;
+
+        let port = 3168;
+        let arena = ArenaBuilder::new()
+            .binaries(vec!["missing_bot_a".to_string(), "missing_bot_b".to_string()])
+            .port(port)
+            .build();
+        let expected = arena.small_client_info();
+        let (handle, shutdown) = arena.spawn();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // Both clients connect while it is player 0's turn,
+        // yet each one sees the board right away
+        let url = format!("ws://127.0.0.1:{}/game", port);
+        let (mut first, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        let (mut second, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        for socket in [&mut first, &mut second] {
+            let msg = tokio::time::timeout(Duration::from_secs(2), socket.next())
+                .await
+                .expect("No config received")
+                .unwrap()
+                .unwrap();
+            match serde_json::from_str(msg.to_text().unwrap()).unwrap() {
+                ServerMessage::Config(config) => assert_eq!(config.num_players, 2),
+                other => panic!("Expected the config first, got {:?}", other),
+            }
+
+            let msg = tokio::time::timeout(Duration::from_secs(2), socket.next())
+                .await
+                .expect("No snapshot received")
+                .unwrap()
+                .unwrap();
+            let snapshot: SmallClientInfo = serde_json::from_str(msg.to_text().unwrap()).unwrap();
+            assert_eq!(snapshot.current_player_num, 0);
+            assert_eq!(snapshot.board.available_cards, expected.board.available_cards);
+            assert!(crate::client::parse_server_message(msg.to_text().unwrap()).is_none());
+        }
+
+        shutdown.send(()).unwrap();
+        drop((first, second));
+        tokio::time::timeout(Duration::from_secs(5), handle).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_incompatible_version_rejected() {
+        use futures_util::StreamExt;
+
+        let port = 3172;
+        let arena = ArenaBuilder::new()
+            .binaries(vec!["missing_bot_a".to_string(), "missing_bot_b".to_string()])
+            .port(port)
+            .build();
+        let (handle, shutdown) = arena.spawn();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let url = format!("ws://127.0.0.1:{}/game", port);
+        let (mut outdated, _) = tokio_tungstenite::connect_async(format!("{}?version=999", url))
+            .await
+            .unwrap();
+        let msg = tokio::time::timeout(Duration::from_secs(2), outdated.next())
+            .await
+            .expect("No error received")
+            .unwrap()
+            .unwrap();
+        match serde_json::from_str(msg.to_text().unwrap()).unwrap() {
+            ServerMessage::Error(error) => {
+                assert!(error.contains("Incompatible protocol version 999"), "{}", error)
+            }
+            other => panic!("Expected an error, got {:?}", other),
+        }
+
+        // Clients speaking the current version are let in as usual
+        let current = format!("{}?version={}", url, crate::constants::PROTOCOL_VERSION);
+        let (mut current, _) = tokio_tungstenite::connect_async(current).await.unwrap();
+        let msg = current.next().await.unwrap().unwrap();
+        let config = serde_json::from_str::<ServerMessage>(msg.to_text().unwrap());
+        assert!(matches!(config, Ok(ServerMessage::Config(_))));
+        let msg = current.next().await.unwrap().unwrap();
+        assert!(serde_json::from_str::<SmallClientInfo>(msg.to_text().unwrap()).is_ok());
+
+        shutdown.send(()).unwrap();
+        drop((outdated, current));
+        tokio::time::timeout(Duration::from_secs(5), handle).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_garbage_message_reported_without_dropping_connection() {
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::Message;
+
+        let port = 3176;
+        let arena = ArenaBuilder::new()
+            .binaries(vec!["missing_bot_a".to_string(), "missing_bot_b".to_string()])
+            .port(port)
+            .build();
+        let (handle, shutdown) = arena.spawn();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let url = format!("ws://127.0.0.1:{}/game?seats=2", port);
+        let (mut client, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        // Skip the config and snapshot
+        for _ in 0..2 {
+            tokio::time::timeout(Duration::from_secs(2), client.next())
+                .await
+                .expect("No message received");
+        }
+
+        let expect_parse_error = |msg: String| match serde_json::from_str(&msg).unwrap() {
+            ServerMessage::Error(error) => {
+                assert!(error.starts_with("Unable to parse message"), "{}", error)
+            }
+            other => panic!("Expected an error, got {:?}", other),
+        };
+
+        // Before the game starts
+        client.send(Message::text("garbage")).await.unwrap();
+        let msg = tokio::time::timeout(Duration::from_secs(2), client.next())
+            .await
+            .expect("No error received")
+            .unwrap()
+            .unwrap();
+        expect_parse_error(msg.to_text().unwrap().to_owned());
+
+        let ready = serde_json::to_string(&ClientMessage::Ready).unwrap();
+        client.send(Message::text(ready)).await.unwrap();
+        let msg = client.next().await.unwrap().unwrap();
+        let info = crate::client::parse_server_message(msg.to_text().unwrap()).expect("Expected a turn");
+
+        // During a turn, which the client keeps
+        client.send(Message::text("{\"Action\": 12")).await.unwrap();
+        let msg = client.next().await.unwrap().unwrap();
+        expect_parse_error(msg.to_text().unwrap().to_owned());
+
+        let action = ClientMessage::Action {
+            action: info.legal_actions[0].clone(),
+            request_id: info.request_id,
+        };
+        client.send(Message::text(serde_json::to_string(&action).unwrap())).await.unwrap();
+        let msg = client.next().await.unwrap().unwrap();
+        let next = crate::client::parse_server_message(msg.to_text().unwrap()).expect("Expected a turn");
+        assert!(next.history.num_actions() > info.history.num_actions());
+
+        shutdown.send(()).unwrap();
+        drop(client);
+        tokio::time::timeout(Duration::from_secs(5), handle).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_connecting_after_game_over_rejected() {
+        use futures_util::StreamExt;
+
+        let port = 3175;
+        let mut arena = ArenaBuilder::new()
+            .binaries(vec!["missing_bot_a".to_string(), "missing_bot_b".to_string()])
+            .port(port)
+            .build();
+        while let Some(actions) = arena.get_legal_actions() {
+            arena.step(greedy_action(&actions)).unwrap();
+        }
+        let (handle, shutdown) = arena.spawn();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let url = format!("ws://127.0.0.1:{}/game", port);
+        let (mut late, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        let msg = tokio::time::timeout(Duration::from_secs(2), late.next())
+            .await
+            .expect("No error received")
+            .unwrap()
+            .unwrap();
+        match serde_json::from_str(msg.to_text().unwrap()).unwrap() {
+            ServerMessage::Error(error) => assert_eq!(error, "The game is already over"),
+            other => panic!("Expected an error, got {:?}", other),
+        }
+
+        // The connection is closed rather than left waiting for a turn
+        let closed = tokio::time::timeout(Duration::from_secs(2), late.next())
+            .await
+            .expect("The connection was left open");
+        assert!(matches!(closed, None | Some(Ok(tokio_tungstenite::tungstenite::Message::Close(_)))));
+
+        shutdown.send(()).unwrap();
+        drop(late);
+        tokio::time::timeout(Duration::from_secs(5), handle).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_illegal_action_reports_error() {
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::Message;
+
+        let port = 3170;
+        let arena = ArenaBuilder::new()
+            .binaries(vec!["missing_bot_a".to_string(), "missing_bot_b".to_string()])
+            .port(port)
+            .build();
+        let (handle, shutdown) = arena.spawn();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let url = format!("ws://127.0.0.1:{}/game", port);
+        let (mut first, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        // Skip the config and the snapshot
+        first.next().await.unwrap().unwrap();
+        first.next().await.unwrap().unwrap();
+        let (mut second, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        second.next().await.unwrap().unwrap();
+        second.next().await.unwrap().unwrap();
+
+        let ready = serde_json::to_string(&ClientMessage::Ready).unwrap();
+        for socket in [&mut first, &mut second] {
+            socket.send(Message::Text(ready.clone())).await.unwrap();
+        }
+
+        // The first player to connect is asked for an action once everyone is ready
+        let next_text = |msg: Option<Result<Message, _>>| msg.unwrap().unwrap().into_text().unwrap();
+        let msg = tokio::time::timeout(Duration::from_secs(2), first.next())
+            .await
+            .expect("No game state received");
+        let info: ClientInfo = serde_json::from_str(&next_text(msg)).unwrap();
+        assert_eq!(info.current_player_num, 0);
+
+        let illegal = ClientMessage::Action {
+            action: Action::AttractNoble(99),
+            request_id: info.request_id,
+        };
+        let illegal = serde_json::to_string(&illegal).unwrap();
+        first.send(Message::Text(illegal)).await.unwrap();
+        let msg = tokio::time::timeout(Duration::from_secs(2), first.next())
+            .await
+            .expect("No error received");
+        match serde_json::from_str::<ServerMessage>(&next_text(msg)).unwrap() {
+            ServerMessage::Error(error) => assert!(error.contains("Illegal action")),
+            other => panic!("Expected an error, got {:?}", other),
+        }
+
+        shutdown.send(()).unwrap();
+        drop((first, second));
+        tokio::time::timeout(Duration::from_secs(5), handle).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_late_action_rejected_after_fallback() {
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::Message;
+
+        let port = 3177;
+        let arena = ArenaBuilder::new()
+            .binaries(vec!["missing_bot_a".to_string(), "missing_bot_b".to_string()])
+            .initial_time(Duration::from_millis(200))
+            .port(port)
+            .build();
+        let (handle, shutdown) = arena.spawn();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let url = format!("ws://127.0.0.1:{}/game?seats=2", port);
+        let (mut client, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        // Skip the config and the snapshot
+        client.next().await.unwrap().unwrap();
+        client.next().await.unwrap().unwrap();
+        let ready = serde_json::to_string(&ClientMessage::Ready).unwrap();
+        client.send(Message::Text(ready)).await.unwrap();
+
+        // Skips the errors in between turns
+        async fn next_turn(
+            client: &mut (impl StreamExt<Item = Result<Message, tokio_tungstenite::tungstenite::Error>> + Unpin),
+        ) -> ClientInfo {
+            loop {
+                let msg = tokio::time::timeout(Duration::from_secs(2), client.next())
+                    .await
+                    .expect("No game state received");
+                let msg = msg.unwrap().unwrap().into_text().unwrap();
+                if let Some(info) = crate::client::parse_server_message(&msg) {
+                    return info;
+                }
+            }
+        }
+
+        // Seat 0 runs out of time, so a fallback move is played for it
+        let late = next_turn(&mut client).await;
+        assert_eq!(late.current_player_num, 0);
+        let info = next_turn(&mut client).await;
+        assert_eq!(info.current_player_num, 1);
+        assert!(info.request_id > late.request_id);
+
+        // The answer to seat 0's turn finally arrives, and is not played for seat 1
+        let stale = ClientMessage::Action {
+            action: greedy_action(&late.legal_actions),
+            request_id: late.request_id,
+        };
+        client.send(Message::Text(serde_json::to_string(&stale).unwrap())).await.unwrap();
+        let msg = tokio::time::timeout(Duration::from_secs(2), client.next())
+            .await
+            .expect("No error received");
+        match serde_json::from_str::<ServerMessage>(&msg.unwrap().unwrap().into_text().unwrap()).unwrap() {
+            ServerMessage::Error(error) => assert!(error.contains("no longer current"), "{}", error),
+            other => panic!("Expected an error, got {:?}", other),
+        }
+
+        // Seat 1 still gets to answer its own turn
+        let action = ClientMessage::Action {
+            action: greedy_action(&info.legal_actions),
+            request_id: info.request_id,
+        };
+        client.send(Message::Text(serde_json::to_string(&action).unwrap())).await.unwrap();
+        let next = next_turn(&mut client).await;
+        assert_eq!(next.current_player_num, 0);
+        let seat_1_actions: Vec<Action> = next
+            .history
+            .iter()
+            .filter(|(player_num, action)| *player_num == 1 && !action.is_no_op())
+            .map(|(_, action)| action.clone())
+            .collect();
+        assert_eq!(seat_1_actions, vec![greedy_action(&info.legal_actions)]);
+
+        shutdown.send(()).unwrap();
+        drop(client);
+        tokio::time::timeout(Duration::from_secs(5), handle).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_one_client_plays_two_seats() {
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::Message;
+
+        let port = 3171;
+        let arena = ArenaBuilder::new()
+            .binaries(vec![
+                "missing_bot_a".to_string(),
+                "missing_bot_b".to_string(),
+                "missing_bot_c".to_string(),
+            ])
+            .port(port)
+            .build();
+        let (handle, shutdown) = arena.spawn();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let url = format!("ws://127.0.0.1:{}/game", port);
+        let (mut both, _) = tokio_tungstenite::connect_async(format!("{}?seats=2", url))
+            .await
+            .unwrap();
+        // Skip the config and the snapshot
+        both.next().await.unwrap().unwrap();
+        both.next().await.unwrap().unwrap();
+        let (mut last, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        last.next().await.unwrap().unwrap();
+        last.next().await.unwrap().unwrap();
+
+        let ready = serde_json::to_string(&ClientMessage::Ready).unwrap();
+        for socket in [&mut both, &mut last] {
+            socket.send(Message::Text(ready.clone())).await.unwrap();
+        }
+
+        // The same connection is asked to play for seat 0 and then seat 1
+        let next_info = |msg: Option<Result<Message, _>>| -> ClientInfo {
+            serde_json::from_str(&msg.unwrap().unwrap().into_text().unwrap()).unwrap()
+        };
+        for seat in 0..2 {
+            let msg = tokio::time::timeout(Duration::from_secs(2), both.next())
+                .await
+                .expect("No game state received");
+            let info = next_info(msg);
+            assert_eq!(info.current_player_num, seat);
+            let action = ClientMessage::Action {
+                action: greedy_action(&info.legal_actions),
+                request_id: info.request_id,
+            };
+            let action = serde_json::to_string(&action).unwrap();
+            both.send(Message::Text(action)).await.unwrap();
+        }
+
+        let msg = tokio::time::timeout(Duration::from_secs(2), last.next())
+            .await
+            .expect("No game state received");
+        let info = next_info(msg);
+        assert_eq!(info.current_player_num, 2);
+        assert_eq!(info.history.group_by_player().len(), 2);
+
+        shutdown.send(()).unwrap();
+        drop((both, last));
+        tokio::time::timeout(Duration::from_secs(5), handle).await.unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_take_seats_bounded_by_seats_left() {
+        let mut arena = ArenaBuilder::new()
+            .binaries(vec!["a".to_string(), "b".to_string(), "c".to_string()])
+            .build();
+        assert_eq!(arena.take_seat(), Some(0));
+        assert_eq!(arena.take_seats(usize::MAX), Some(vec![1, 2]));
+        assert_eq!(arena.seats_of(1), vec![1, 2]);
+        assert_eq!(arena.take_seats(1), None);
+        assert_eq!(arena.take_seat(), None);
+    }
+
+    #[tokio::test]
+    async fn test_client_rejected_once_seats_are_taken() {
+        use futures_util::StreamExt;
+
+        let port = 3178;
+        let arena = ArenaBuilder::new()
+            .binaries(vec!["missing_bot_a".to_string(), "missing_bot_b".to_string()])
+            .port(port)
+            .build();
+        let (handle, shutdown) = arena.spawn();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // Asking for more seats than the game has takes the ones that are left
+        let url = format!("ws://127.0.0.1:{}/game", port);
+        let (mut greedy, _) = tokio_tungstenite::connect_async(format!("{}?seats=1000", url))
+            .await
+            .unwrap();
+        let msg = greedy.next().await.unwrap().unwrap();
+        let config = serde_json::from_str::<ServerMessage>(msg.to_text().unwrap());
+        assert!(matches!(config, Ok(ServerMessage::Config(_))));
+
+        let (mut late, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        let msg = tokio::time::timeout(Duration::from_secs(2), late.next())
+            .await
+            .expect("No error received")
+            .unwrap()
+            .unwrap();
+        match serde_json::from_str(msg.to_text().unwrap()).unwrap() {
+            ServerMessage::Error(error) => assert!(error.contains("seat"), "{}", error),
+            other => panic!("Expected an error, got {:?}", other),
+        }
+
+        shutdown.send(()).unwrap();
+        drop((greedy, late));
+        tokio::time::timeout(Duration::from_secs(5), handle).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_seat_played_for_after_connection_drops() {
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::Message;
+
+        let port = 3180;
+        let arena = ArenaBuilder::new()
+            .binaries(vec!["missing_bot_a".to_string(), "missing_bot_b".to_string()])
+            .initial_time(Duration::from_millis(300))
+            .port(port)
+            .build();
+        let (handle, shutdown) = arena.spawn();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let url = format!("ws://127.0.0.1:{}/game", port);
+        let (mut first, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        let (mut second, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        let ready = serde_json::to_string(&ClientMessage::Ready).unwrap();
+        for socket in [&mut first, &mut second] {
+            socket.send(Message::Text(ready.clone())).await.unwrap();
+        }
+
+        // Seat 0 is asked for its move and its client vanishes for good
+        loop {
+            let msg = first.next().await.unwrap().unwrap();
+            if serde_json::from_str::<ClientInfo>(msg.to_text().unwrap()).is_ok() {
+                break;
+            }
+        }
+        drop(first);
+
+        // Its turn runs out and a move is played for it, so the game goes on
+        let info = loop {
+            let msg = tokio::time::timeout(Duration::from_secs(2), second.next())
+                .await
+                .expect("The game stalled on the dropped seat")
+                .unwrap()
+                .unwrap();
+            if let Ok(info) = serde_json::from_str::<ClientInfo>(msg.to_text().unwrap()) {
+                break info;
+            }
+        };
+        assert_eq!(info.current_player_num, 1);
+
+        shutdown.send(()).unwrap();
+        drop(second);
+        tokio::time::timeout(Duration::from_secs(5), handle).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replay_over_websocket() {
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::Message;
+
+        let port = 3169;
+        let mut arena = ArenaBuilder::new()
+            .binaries(vec!["missing_bot_a".to_string(), "missing_bot_b".to_string()])
+            .port(port)
+            .build();
+        while !arena.is_game_over() {
+            let actions = arena.get_legal_actions().unwrap();
+            arena.step(greedy_action(&actions)).unwrap();
+        }
+        arena.finalize_game();
+        let (handle, shutdown) = arena.spawn();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let url = format!("ws://127.0.0.1:{}/replay/ws", port);
+        let (mut socket, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        let commands = [
+            (r#""next""#, 1),
+            (r#""next""#, 2),
+            (r#"{"goto":{"move_index":5}}"#, 5),
+            (r#""previous""#, 4),
+            (r#""current""#, 4),
+        ];
+        for (command, move_index) in commands {
+            socket.send(Message::Text(command.to_string())).await.unwrap();
+            let msg = tokio::time::timeout(Duration::from_secs(2), socket.next())
+                .await
+                .expect("No replay state received")
+                .unwrap()
+                .unwrap();
+            let reply: serde_json::Value = serde_json::from_str(msg.to_text().unwrap()).unwrap();
+            let state = &reply["success"]["state"];
+            assert_eq!(state["move_index"], move_index);
+            assert_eq!(state["players"].as_array().unwrap().len(), 2);
+            assert_eq!(state["cards"].as_array().unwrap().len(), 3);
+            assert_eq!(state["current"]["playerIndex"], (move_index + 1) % 2);
+        }
+
+        socket.send(Message::Text("\"bogus\"".to_string())).await.unwrap();
+        let msg = socket.next().await.unwrap().unwrap();
+        let reply: serde_json::Value = serde_json::from_str(msg.to_text().unwrap()).unwrap();
+        assert!(reply["error"].is_string());
+
+        shutdown.send(()).unwrap();
+        drop(socket);
+        tokio::time::timeout(Duration::from_secs(5), handle).await.unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_connected_clients() {
+        let mut arena = ArenaBuilder::new()
+            .binaries(vec!["a".to_string(), "b".to_string()])
+            .build();
+        assert!(arena.connected_clients().is_empty());
+
+        arena.mark_connected(1);
+        assert_eq!(arena.connected_clients(), vec![1]);
+
+        // Seats outside the arena are ignored
+        arena.mark_connected(5);
+        assert_eq!(arena.connected_clients(), vec![1]);
+
+        arena.mark_disconnected(1);
+        assert!(arena.connected_clients().is_empty());
+    }
+
+    #[test]
+    fn test_game_waits_for_all_ready() {
+        let mut arena = ArenaBuilder::new()
+            .binaries(vec!["a".to_string(), "b".to_string(), "c".to_string()])
+            .build();
+
+        assert!(!arena.mark_ready(0));
+        assert!(!arena.mark_ready(2));
+        assert!(!arena.mark_ready(0));
+        assert!(!arena.all_ready());
+
+        assert!(arena.mark_ready(1));
+        assert!(arena.all_ready());
+        assert!(!arena.mark_ready(1));
+    }
+
+    #[test]
+    fn test_username_in_public_info() {
+        let mut arena = ArenaBuilder::new()
+            .binaries(vec!["a".to_string(), "b".to_string()])
+            .build();
+        arena.set_username(1, "alice");
+
+        let players = arena.small_client_info().players;
+        assert_eq!(players[0].username, None);
+        assert_eq!(players[1].username, Some("alice".to_string()));
+        assert_eq!(arena.username(1), Some("alice".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_ready_and_username_over_websocket() {
+        let arena: GlobalArena = Arc::new(RwLock::new(
+            ArenaBuilder::new()
+                .binaries(vec!["a".to_string(), "b".to_string()])
+                .build(),
+        ));
+        let clients = Clients::default();
+        let game = {
+            let arena = arena.clone();
+            warp::path("game")
+                .and(warp::ws())
+                .and(warp::query::<ConnectParams>())
+                .map(move |ws: warp::ws::Ws, params| {
+                    let (clients, arena) = (clients.clone(), arena.clone());
+                    ws.on_upgrade(move |socket| user_connected(socket, params, clients, arena, None))
+                })
+        };
+
+        let mut alice = warp::test::ws()
+            .path("/game?username=alice")
+            .handshake(game.clone())
+            .await
+            .unwrap();
+        let mut anonymous = warp::test::ws().path("/game").handshake(game).await.unwrap();
+        // Skip the config and the snapshot
+        for client in [&mut alice, &mut anonymous] {
+            client.recv().await.unwrap();
+            client.recv().await.unwrap();
+        }
+        {
+            let arena = arena.read().await;
+            assert_eq!(arena.username(0), Some("alice".to_string()));
+            assert_eq!(arena.log_label(0), "alice");
+            assert_eq!(arena.log_label(1), "1");
+        }
+
+        // The game waits until every client is ready
+        let ready = serde_json::to_string(&ClientMessage::Ready).unwrap();
+        alice.send_text(ready.clone()).await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!arena.read().await.all_ready());
+        assert_eq!(arena.read().await.current_player_num(), None);
+
+        anonymous.send_text(ready).await;
+        let msg = tokio::time::timeout(Duration::from_secs(2), alice.recv())
+            .await
+            .expect("The game did not start")
+            .unwrap();
+        let info: ClientInfo = serde_json::from_str(msg.to_str().unwrap()).unwrap();
+        assert_eq!(info.current_player_num, 0);
+        assert_eq!(info.players[0].username, Some("alice".to_string()));
+        assert_eq!(info.players[1].username, None);
+        assert!(arena.read().await.all_ready());
+    }
+
+    #[test]
+    fn test_client_info_cached_until_action() {
+        let mut arena = ArenaBuilder::new()
+            .binaries(vec!["a".to_string(), "b".to_string()])
+            .build();
+
+        let first = arena.client_info();
+        let second = arena.client_info();
+        assert_eq!(first.to_json(), second.to_json());
+
+        arena.play_action(Action::ReserveHidden(0));
+        let after = arena.client_info();
+        assert_eq!(after.history.num_actions(), 1);
+        assert_eq!(after.current_player.num_reserved_cards(), 1);
+        assert_ne!(first.to_json(), after.to_json());
+
+        arena.set_username(0, "alice");
+        assert_eq!(arena.client_info().players[0].username, Some("alice".to_string()));
+
+        arena.start_game();
+        assert!(arena.client_info.get().is_none());
+        arena.client_info();
+        arena.add_time(0, Duration::from_secs(1)).unwrap();
+        assert!(arena.client_info.get().is_none());
+    }
+
+    #[test]
+    fn test_advance_plays_forced_actions() {
+        let mut arena = ArenaBuilder::new()
+            .binaries(vec!["a".to_string(), "b".to_string(), "c".to_string()])
+            .build();
+        arena.take_seats(2).unwrap();
+        arena.take_seat().unwrap();
+        arena.start_game();
+
+        let take = arena
+            .get_legal_actions()
+            .unwrap()
+            .into_iter()
+            .find(|a| matches!(a, Action::TakeDistinct(_)))
+            .unwrap();
+        let request_id = arena.request_id();
+        assert!(validate_action(&take, request_id, 1, &arena).is_err());
+        assert!(matches!(
+            validate_action(&take, request_id + 1, 0, &arena),
+            Err(InvalidActionError::StaleRequest(_))
+        ));
+        validate_action(&take, request_id, 0, &arena).unwrap();
+        arena.play_action(take);
+
+        // Pass and Continue are forced, so the turn ends with player 1 to act
+        let update = advance(&mut arena, true);
+        assert_eq!(update.web_updates.len(), 3);
+        let (client_id, info) = update.next_turn.expect("The game is not over");
+        assert_eq!(client_id, 0);
+        assert_eq!(info.current_player_num, 1);
+        assert_eq!(info.history.num_actions(), 3);
+        assert_eq!(info.players[0].gems.total(), 3);
+        assert_eq!(info.to_json(), arena.client_info().to_json());
+
+        let update = advance(&mut arena, false);
+        assert!(update.web_updates.is_empty());
+        assert_eq!(update.next_turn.unwrap().1.history.num_actions(), 3);
+    }
+
+    #[test]
+    fn test_overpaying_purchase_rejected() {
+        let mut arena = ArenaBuilder::new()
+            .binaries(vec!["a".to_string(), "b".to_string()])
+            .build();
+        arena.take_seat().unwrap();
+        arena.take_seat().unwrap();
+        arena.start_game();
+
+        // Hoard gems until a card can be bought with some to spare
+        let (purchase, overpayment) = loop {
+            let actions = arena.get_legal_actions().expect("Game ended early");
+            let held = *arena.game.current_player().gems();
+            let spare = actions.iter().find_map(|action| match action {
+                Action::Purchase { card, payment } => Gem::ALL
+                    .into_iter()
+                    .find(|&gem| held[gem] > payment[gem])
+                    .map(|gem| {
+                        let overpayment = Action::Purchase {
+                            card: *card,
+                            payment: *payment + Gems::one(gem),
+                        };
+                        (action.clone(), overpayment)
+                    }),
+                _ => None,
+            });
+            if let Some(found) = spare {
+                break found;
+            }
+            let take = actions
+                .iter()
+                .find(|a| matches!(a, Action::TakeDistinct(_) | Action::TakeDouble(_)));
+            arena.play_action(take.unwrap_or(&actions[0]).clone());
+        };
+
+        let player = arena.current_player_num().unwrap();
+        validate_action(&purchase, arena.request_id(), player, &arena).unwrap();
+        assert!(matches!(
+            validate_action(&overpayment, arena.request_id(), player, &arena),
+            Err(InvalidActionError::Illegal(_))
+        ));
+    }
+
+    /// Play a seeded random game both on a bare `Game` and through the arena,
+    /// the way the game server does (forced moves auto played by `advance`),
+    /// and check that both end in the same state
+    fn cross_check(seed: u64, num_players: usize) {
+        use rand::seq::SliceRandom;
+
+        let mut rng = StdRng::seed_from_u64(seed);
+        let mut game = Game::new_seeded(num_players as u8, Arc::new(Card::all()), seed);
+        while !game.is_terminal() {
+            let action = match game.only_forced_move() {
+                Some(action) => action,
+                None => game.random_legal_action(&mut rng).unwrap(),
+            };
+            game.play_action(action);
+        }
+
+        let mut rng = StdRng::seed_from_u64(seed);
+        let binaries = (0..num_players).map(|i| format!("bot_{}", i)).collect();
+        let initial_game = Game::new_seeded(num_players as u8, Arc::new(Card::all()), seed);
+        let mut arena = ArenaBuilder::new().binaries(binaries).build_with_game(initial_game);
+        arena.take_seats(num_players).unwrap();
+        arena.start_game();
+        while let Some((_, info)) = advance(&mut arena, false).next_turn {
+            let action = info.legal_actions.choose(&mut rng).unwrap().clone();
+            validate_action(&action, info.request_id, info.current_player_num, &arena).unwrap();
+            arena.play_action(action);
+        }
+
+        let board = |game: &Game| serde_json::to_value(Board::from_game(game)).unwrap();
+        assert_eq!(serde_json::to_value(arena.board()).unwrap(), board(&game), "seed {}", seed);
+        assert_eq!(arena.players(), game.players(), "seed {}", seed);
+        assert_eq!(arena.outcome(), game.outcome(), "seed {}", seed);
+
+        let replay = arena.get_replay().expect("The game was not finalized");
+        let replayed = replay.blocking_read().final_game();
+        assert_eq!(replayed.history().history, game.history().history, "seed {}", seed);
+        assert_eq!(board(&replayed), board(&game), "seed {}", seed);
+    }
+
+    #[test]
+    fn test_arena_matches_game_logic() {
+        for seed in 0..6 {
+            cross_check(seed, 2 + seed as usize % 3);
+        }
+    }
+
+    #[test]
+    fn test_seeded_first_player() {
+        let build = |seed| {
+            ArenaBuilder::new()
+                .binaries(vec!["a".to_string(), "b".to_string(), "c".to_string()])
+                .randomize_first_player_seeded(seed)
+                .build()
+        };
+        let first_players: Vec<usize> = (0..8)
+            .map(|seed| build(seed).client_info().current_player_num)
+            .collect();
+        for seed in 0..8 {
+            assert_eq!(build(seed).client_info().current_player_num, first_players[seed as usize]);
+        }
+        assert!(first_players.iter().any(|&first| first != 0));
+
+        // Turns go around from the first player, and the game only ends
+        // once the player before them finishes the round
+        let seed = first_players.iter().position(|&first| first != 0).unwrap() as u64;
+        let mut arena = build(seed);
+        let first = first_players[seed as usize];
+        arena.start_game();
+        assert_eq!(arena.current_player_num(), Some(first));
+        assert!(arena.time_remaining() > Duration::from_secs(59));
+
+        let mut expected = first;
+        while !arena.is_game_over() {
+            assert_eq!(arena.current_player_num(), Some(expected));
+            let actions = arena.get_legal_actions().unwrap();
+            let action = greedy_action(&actions);
+            if action == Action::Continue {
+                expected = (expected + 1) % 3;
+            }
+            arena.step(action).unwrap();
+        }
+        assert!(matches!(arena.outcome(), GameOutcome::Winner(_)));
+        assert_eq!(arena.current_player_num(), Some((first + 2) % 3));
+    }
+
+    #[test]
+    fn test_step_rejects_illegal_action() {
+        let mut arena = ArenaBuilder::new()
+            .binaries(vec!["bot_a".to_string(), "bot_b".to_string()])
+            .build();
+
+        let result = arena.step(Action::Continue);
+        assert!(matches!(result, Err(ActionError::IllegalAction)));
+        assert_eq!(arena.current_player_num(), None);
+    }
+
+    #[tokio::test]
+    async fn test_initialization_stores_game_id() {
+        use crate::models::{ArenaRequest, GlobalServerResponse, Initialized};
+        use futures_util::{SinkExt, StreamExt};
+        use tokio_tungstenite::tungstenite::protocol::Message;
+
+        // Stand in for stourney.com, acknowledging the initial game state
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:3173").await.unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+            let msg = socket.next().await.unwrap().unwrap();
+            let request: ArenaRequest = serde_json::from_str(msg.to_text().unwrap()).unwrap();
+            assert!(matches!(request, ArenaRequest::InitializeGame { .. }));
+
+            let response = GlobalServerResponse::Initialized(Initialized::Success {
+                id: "game-42".to_owned(),
+                url: "https://stourney.com/game/game-42".to_owned(),
+            });
+            let response = serde_json::to_string(&response).unwrap();
+            socket.send(Message::text(response)).await.unwrap();
+        });
+
+        let arena = ArenaBuilder::new()
+            .binaries(vec!["bot_a".to_string(), "bot_b".to_string()])
+            .build();
+        assert_eq!(arena.game_id(), None);
+        let arena = Arc::new(RwLock::new(arena));
+
+        let (websocket, _) = tokio_tungstenite::connect_async("ws://127.0.0.1:3173").await.unwrap();
+        let (outgoing, mut incoming) = websocket.split();
+        let outgoing = Arc::new(RwLock::new(outgoing));
+
+        let url = web::push_initial_game(outgoing, &mut incoming, arena.clone()).await;
+        assert_eq!(url.as_deref(), Some("https://stourney.com/game/game-42"));
+        assert_eq!(arena.read().await.game_id(), Some("game-42"));
+        server.await.unwrap();
+    }
+
+    #[test]
+    fn test_embedded_cards_hide_blind_reserves() {
+        let mut arena = ArenaBuilder::new()
+            .binaries(vec!["bot_a".to_string(), "bot_b".to_string()])
+            .embed_cards(true)
+            .build();
+        let mut play = |arena: &mut Arena, action: Action| {
+            arena.step(action).unwrap();
+            while let Some(forced) = arena.only_forced_move() {
+                arena.step(forced).unwrap();
+            }
+        };
+        let face_up = arena.board().available_cards[1][0];
+        play(&mut arena, Action::ReserveHidden(0));
+        play(&mut arena, Action::Reserve(face_up));
+
+        // Player 0 sees both reservations
+        let blind = arena.players()[0].all_reserved()[0];
+        let info = arena.client_info();
+        assert_eq!(info.current_player_num, 0);
+        let cards = info.cards.clone().expect("Cards were not embedded");
+        for (tier, details) in info.board.available_cards.iter().zip(&cards.board) {
+            let ids: Vec<CardId> = details.iter().map(|card| card.id).collect();
+            assert_eq!(&ids, tier);
+        }
+        assert_eq!(cards.reserved[0], vec![CardDetails::from(Card::from_id(blind).unwrap())]);
+        assert_eq!(cards.reserved[1], vec![CardDetails::from(Card::from_id(face_up).unwrap())]);
+        assert_eq!(cards.reserved[1][0].tier, 2);
+
+        // Player 1 only sees their own card, not player 0's blind reservation
+        play(&mut arena, Action::TakeDouble(Gem::Ruby));
+        let cards = arena.client_info().cards.expect("Cards were not embedded");
+        assert!(cards.reserved[0].is_empty());
+        assert_eq!(cards.reserved[1].len(), 1);
+
+        // Left out unless the arena asks for it
+        let game = Game::new(2, Arc::new(Card::all()));
+        assert!(ClientInfo::from_game(&game, String::new()).cards.is_none());
+    }
+
+    #[test]
+    fn test_feature_vector_opening_position() {
+        let game = Game::new(2, Arc::new(Card::all()));
+        let info = ClientInfo::from_game(&game, String::new());
+        let features = info.to_feature_vector();
+        assert_eq!(features.len(), 6 + 12 * 2 + 5 * MAX_NOBLES + 12 * 11);
+
+        // The bank holds 4 of each color and 5 gold with two players
+        assert_eq!(features[..6], [4.0, 4.0, 4.0, 4.0, 4.0, 5.0]);
+        // Neither player holds anything yet
+        assert!(features[6..30].iter().all(|&f| f == 0.0));
+        // Only 3 nobles are dealt, the other slots are empty
+        let noble = Noble::from_id(info.board.nobles[0]);
+        let requirements: Vec<f32> =
+            Gem::ALL_EXCEPT_GOLD.iter().map(|&gem| noble.requirements()[gem] as f32).collect();
+        assert_eq!(features[30..35], requirements[..]);
+        assert!(features[45..55].iter().all(|&f| f == 0.0));
+
+        let card = Card::from_id(info.board.available_cards[0][0]).unwrap();
+        assert_eq!(features[55 + 5], card.points() as f32);
+        assert_eq!(features[55 + 6 + card.gem() as usize], 1.0);
+    }
+
+    #[test]
+    fn test_feature_vector_keeps_empty_slots_in_place() {
+        // Empty the tier 3 deck so a card taken from it is not replaced
+        let game = Game::new(2, Arc::new(Card::all()));
+        let mut json = serde_json::to_value(&game).unwrap();
+        json["decks"][2] = serde_json::json!([]);
+        let mut game: Game = serde_json::from_value(json).unwrap();
+        let tier = game.cards()[2].clone();
+        let before = ClientInfo::from_game(&game, String::new()).to_feature_vector();
+
+        game.play_action(Action::Reserve(tier[1]));
+        let after = ClientInfo::from_game(&game, String::new()).to_feature_vector();
+        assert_eq!(after.len(), before.len());
+
+        // Tier 3 starts after the bank, players, nobles and the 8 slots of tiers 1 and 2
+        let tier_3 = 6 + 12 * 2 + 5 * MAX_NOBLES + 8 * 11;
+        let slot = |features: &[f32], slot: usize| features[tier_3 + 11 * slot..tier_3 + 11 * (slot + 1)].to_vec();
+        assert_eq!(slot(&after, 0), slot(&before, 0));
+        assert!(slot(&after, 1).iter().all(|&f| f == 0.0));
+        assert_eq!(slot(&after, 2), slot(&before, 2));
+        assert_eq!(slot(&after, 3), slot(&before, 3));
+    }
+}
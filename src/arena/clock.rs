@@ -1,8 +1,18 @@
 use std::time::Duration;
 use std::time::SystemTime;
 use super::*;
+use derive_more::{Display, Error};
+use warp::http::StatusCode;
 use warp::{Filter, Rejection, Reply};
 
+#[derive(Debug, Display, Error, PartialEq, Eq)]
+pub enum ClockError {
+    #[display(fmt = "There is no player {}", _0)]
+    UnknownPlayer(#[error(not(source))] usize),
+    #[display(fmt = "Player {} has already timed out", _0)]
+    TimedOut(#[error(not(source))] usize),
+}
+
 /// Keeps track of the amount of time each player has left
 #[derive(Debug, Clone)]
 pub struct Clock {
@@ -12,6 +22,7 @@ pub struct Clock {
     current_timestamp: SystemTime,
     current_player: Option<usize>,
     timed_out: Vec<bool>,
+    time_used: Vec<Duration>,
 }
 
 
@@ -24,6 +35,7 @@ impl Clock {
             current_timestamp: SystemTime::now(),
             current_player: None,
             timed_out: vec![false; num_players],
+            time_used: vec![Duration::from_secs(0); num_players],
         }
     }
 
@@ -38,50 +50,91 @@ impl Clock {
         });
     }
 
+    // Hand the turn to the given player and start their clock,
+    // time already spent by any player is not refunded
+    pub fn restart_for(&mut self, player: usize) {
+        self.current_player = Some(player);
+        self.start();
+    }
+
     // Start the clock for the current player
     // If there is no current player, automatically set the current player to 0
     pub fn start(&mut self) {
         if  self.current_player.is_none() {
             self.current_player = Some(0);
         }
-        let current_player = self.current_player.unwrap();
         self.current_timestamp = SystemTime::now();
-        self.total_time[current_player] += self.increment;
     }
 
     // Returns the time remaining for the current player
     // If there is no current player, return 0
     pub fn time_remaining(&self) -> Duration {
-        if self.current_player.is_none() {
-            return Duration::from_secs(0);
+        match self.current_player {
+            Some(current_player) => self.time_remaining_for(current_player),
+            None => Duration::from_secs(0),
         }
-        let current_player = self.current_player.unwrap();
-        if self.timed_out[current_player] {
+    }
+
+    /// Returns the time remaining for the given player, counting
+    /// the time spent so far if it is their move
+    pub fn time_remaining_for(&self, player: usize) -> Duration {
+        if self.timed_out.get(player).copied().unwrap_or(true) {
             return Duration::from_secs(0);
         }
-        if self.total_time[current_player] < self.current_timestamp.elapsed().unwrap() {
-            return Duration::from_secs(0);
+        if self.current_player != Some(player) {
+            return self.total_time[player];
+        }
+        let elapsed = self.current_timestamp.elapsed().unwrap_or_default();
+        self.total_time[player].saturating_sub(elapsed)
+    }
+
+    /// Grant a player extra time, e.g. to make up for a technical fault.
+    /// If it is their move, the time already spent on it is still charged
+    /// when the move ends. Players that have timed out stay timed out
+    pub fn add_time(&mut self, player: usize, extra: Duration) -> Result<(), ClockError> {
+        match self.timed_out.get(player) {
+            None => Err(ClockError::UnknownPlayer(player)),
+            Some(true) => Err(ClockError::TimedOut(player)),
+            Some(false) => {
+                self.total_time[player] += extra;
+                Ok(())
+            }
         }
-        self.total_time[current_player] - self.current_timestamp.elapsed().unwrap()
     }
 
     // End the clock for the current player
     // If there is no current player, do nothing
     pub fn end(&mut self) {
+        let elapsed = self.current_timestamp.elapsed().unwrap_or_default();
+        self.end_after(elapsed);
+    }
+
+    // Charge the current player for a move that took `elapsed`,
+    // the increment is only credited once the move is completed in time
+    fn end_after(&mut self, elapsed: Duration) {
         if self.current_player.is_none() {
             return;
         }
-        let elapsed = self.current_timestamp.elapsed().unwrap();
         let current_player = self.current_player.unwrap();
-        if self.total_time[current_player] < Duration::from_secs(0) {
-            self.total_time[current_player] = Duration::from_secs(0);
-        } else if self.total_time[current_player] < elapsed {
+        self.time_used[current_player] += elapsed.min(self.total_time[current_player]);
+        if self.timed_out[current_player] || elapsed > self.total_time[current_player] {
             self.timed_out[current_player] = true;
             self.total_time[current_player] = Duration::from_secs(0);
         } else {
             self.total_time[current_player] -= elapsed;
+            self.total_time[current_player] += self.increment;
         }
     }
+
+    /// The time each player has spent on their completed moves
+    pub fn time_used(&self) -> &Vec<Duration> {
+        &self.time_used
+    }
+
+    /// The number of players that have run out of time
+    pub fn timeouts(&self) -> usize {
+        self.timed_out.iter().filter(|&&timed_out| timed_out).count()
+    }
 }
 
 #[derive(Debug, Serialize)]
@@ -96,3 +149,148 @@ pub async fn current_time_remaining(arena: GlobalArena) -> Result<impl Reply, Re
         time_remaining,
     }))
 }
+
+/// A request from the operator to grant a player extra time,
+/// authenticated with the arena's api key
+#[derive(Debug, Deserialize)]
+pub struct AddTimeRequest {
+    pub secret: String,
+    pub player: usize,
+    pub extra: Duration,
+}
+
+#[derive(Debug, Serialize)]
+struct AddTimeError {
+    error: String,
+}
+
+/// Grant a player extra time, replies with the time they now have remaining.
+/// Only allowed when the arena was given an api key
+pub async fn add_time(request: AddTimeRequest, arena: GlobalArena) -> Result<impl Reply, Rejection> {
+    let mut arena = arena.write().await;
+    if arena.api_key().as_deref() != Some(request.secret.as_str()) {
+        let error = AddTimeError { error: "Invalid api key".to_owned() };
+        return Ok(warp::reply::with_status(warp::reply::json(&error), StatusCode::FORBIDDEN));
+    }
+    match arena.add_time(request.player, request.extra) {
+        Ok(()) => {
+            let time_remaining = arena.time_remaining_for(request.player);
+            Ok(warp::reply::with_status(warp::reply::json(&Response { time_remaining }), StatusCode::OK))
+        }
+        Err(e) => {
+            let error = AddTimeError { error: e.to_string() };
+            Ok(warp::reply::with_status(warp::reply::json(&error), StatusCode::BAD_REQUEST))
+        }
+    }
+}
+
+/// The endpoints for reading and adjusting the clock
+pub fn clock_routes(
+    arena: GlobalArena,
+) -> impl Filter<Extract = (impl warp::Reply,), Error = warp::Rejection> + Clone {
+    let arena_filter = warp::any().map(move || arena.clone());
+
+    let time = warp::get()
+        .and(warp::path("time"))
+        .and(arena_filter.clone())
+        .and_then(current_time_remaining);
+
+    let add = warp::post()
+        .and(warp::path!("clock" / "add"))
+        .and(warp::body::json())
+        .and(arena_filter)
+        .and_then(add_time);
+
+    time.or(add)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_increment_credited_after_move() {
+        let mut clock = Clock::new(2, Duration::from_secs(1), Duration::from_millis(100));
+        clock.start();
+        assert!(clock.time_remaining() <= Duration::from_secs(1));
+
+        clock.end_after(Duration::from_millis(300));
+        assert_eq!(clock.total_time[0], Duration::from_millis(800));
+        assert_eq!(clock.time_used()[0], Duration::from_millis(300));
+        assert_eq!(clock.total_time[1], Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_move_exactly_exhausts_clock() {
+        let mut clock = Clock::new(2, Duration::from_secs(1), Duration::from_millis(100));
+        clock.start();
+        clock.end_after(Duration::from_secs(1));
+        assert!(!clock.timed_out[0]);
+        assert_eq!(clock.total_time[0], Duration::from_millis(100));
+    }
+
+    #[test]
+    fn test_move_exceeds_clock() {
+        let mut clock = Clock::new(2, Duration::from_secs(1), Duration::from_millis(100));
+        clock.start();
+        clock.end_after(Duration::from_secs(1) + Duration::from_millis(1));
+        assert!(clock.timed_out[0]);
+        assert_eq!(clock.timeouts(), 1);
+        assert_eq!(clock.total_time[0], Duration::from_secs(0));
+        assert_eq!(clock.time_remaining(), Duration::from_secs(0));
+
+        // Timing out is permanent, later moves earn no increment
+        clock.end_after(Duration::from_secs(0));
+        assert_eq!(clock.total_time[0], Duration::from_secs(0));
+        assert_eq!(clock.add_time(0, Duration::from_secs(5)), Err(ClockError::TimedOut(0)));
+    }
+
+    #[test]
+    fn test_add_time_mid_move() {
+        let mut clock = Clock::new(2, Duration::from_secs(1), Duration::from_millis(100));
+        clock.start();
+        let before = clock.time_remaining();
+        clock.add_time(0, Duration::from_secs(5)).unwrap();
+        let after = clock.time_remaining();
+        assert!(after > before + Duration::from_millis(4900));
+        assert!(after <= Duration::from_secs(6));
+
+        // The move is still charged in full when it ends
+        clock.end_after(Duration::from_millis(300));
+        assert_eq!(clock.total_time[0], Duration::from_millis(5800));
+        assert_eq!(clock.time_used()[0], Duration::from_millis(300));
+
+        // Waiting players are credited without starting their clock
+        clock.add_time(1, Duration::from_secs(2)).unwrap();
+        assert_eq!(clock.time_remaining_for(1), Duration::from_secs(3));
+        assert_eq!(clock.add_time(2, Duration::from_secs(1)), Err(ClockError::UnknownPlayer(2)));
+    }
+
+    #[tokio::test]
+    async fn test_add_time_route_requires_api_key() {
+        let arena = ArenaBuilder::new()
+            .binaries(vec!["a".to_string(), "b".to_string()])
+            .initial_time(Duration::from_secs(10))
+            .send_to_web(false, "secret")
+            .build();
+        let route = clock_routes(Arc::new(RwLock::new(arena)));
+
+        let request = |secret: &str| {
+            warp::test::request()
+                .method("POST")
+                .path("/clock/add")
+                .json(&serde_json::json!({
+                    "secret": secret,
+                    "player": 1,
+                    "extra": { "secs": 5, "nanos": 0 },
+                }))
+        };
+        let response = request("wrong").reply(&route).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = request("secret").reply(&route).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(body["time_remaining"]["secs"], 15);
+    }
+}
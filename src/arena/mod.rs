@@ -11,9 +11,11 @@ pub mod protocol;
 pub mod replay;
 pub mod arena;
 pub mod clock;
+pub mod pool;
 
 pub use protocol::*;
 pub use arena::*;
+pub use pool::*;
 use replay::*;
 use clock::*;
 
@@ -0,0 +1,118 @@
+use super::*;
+use derive_more::{Display, Error};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Debug, Display, Error, PartialEq, Eq)]
+pub enum PoolError {
+    #[display(fmt = "The pool already holds its maximum of {} arenas", _0)]
+    Full(#[error(not(source))] usize),
+}
+
+/// A collection of arenas running side by side, each identified by
+/// the id it was given when added. Finished games are only dropped
+/// when `remove_finished` is called
+pub struct ArenaPool {
+    arenas: RwLock<HashMap<usize, GlobalArena>>,
+    next_id: AtomicUsize,
+    max_concurrent: usize,
+}
+
+impl ArenaPool {
+    pub fn new(max_concurrent: usize) -> Self {
+        ArenaPool {
+            arenas: RwLock::new(HashMap::new()),
+            next_id: AtomicUsize::new(0),
+            max_concurrent,
+        }
+    }
+
+    /// Add an arena to the pool, returning its id, or an error
+    /// if the pool already holds `max_concurrent` arenas
+    pub async fn add_arena(&self, arena: Arena) -> Result<usize, PoolError> {
+        let mut arenas = self.arenas.write().await;
+        if arenas.len() >= self.max_concurrent {
+            return Err(PoolError::Full(self.max_concurrent));
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        arenas.insert(id, Arc::new(RwLock::new(arena)));
+        Ok(id)
+    }
+
+    pub async fn get(&self, id: usize) -> Option<GlobalArena> {
+        self.arenas.read().await.get(&id).cloned()
+    }
+
+    pub async fn len(&self) -> usize {
+        self.arenas.read().await.len()
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.arenas.read().await.is_empty()
+    }
+
+    /// Drop every arena whose game is over, returning the ids that were removed
+    pub async fn remove_finished(&self) -> Vec<usize> {
+        let mut arenas = self.arenas.write().await;
+        let mut finished = Vec::new();
+        for (&id, arena) in arenas.iter() {
+            if arena.read().await.is_game_over() {
+                finished.push(id);
+            }
+        }
+        for id in &finished {
+            arenas.remove(id);
+        }
+        finished.sort_unstable();
+        finished
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_arena() -> Arena {
+        ArenaBuilder::new()
+            .binaries(vec!["bot_a".to_string(), "bot_b".to_string()])
+            .build()
+    }
+
+    #[tokio::test]
+    async fn test_full_pool_rejects_arenas() {
+        let pool = ArenaPool::new(2);
+        pool.add_arena(new_arena()).await.unwrap();
+        pool.add_arena(new_arena()).await.unwrap();
+        assert_eq!(pool.add_arena(new_arena()).await, Err(PoolError::Full(2)));
+        assert_eq!(pool.len().await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_remove_finished_evicts_game_over() {
+        let pool = ArenaPool::new(2);
+        let finished = pool.add_arena(new_arena()).await.unwrap();
+        let running = pool.add_arena(new_arena()).await.unwrap();
+
+        let arena = pool.get(finished).await.unwrap();
+        {
+            let mut arena = arena.write().await;
+            while !arena.is_game_over() {
+                let actions = arena.get_legal_actions().unwrap();
+                let purchase = actions.iter().find(|a| matches!(a, Action::Purchase { .. }));
+                let take = actions
+                    .iter()
+                    .find(|a| matches!(a, Action::TakeDistinct(_) | Action::TakeDouble(_)));
+                let action = purchase.or(take).unwrap_or(&actions[0]).clone();
+                arena.step(action).unwrap();
+            }
+        }
+
+        assert_eq!(pool.remove_finished().await, vec![finished]);
+        assert!(pool.get(finished).await.is_none());
+        assert!(pool.get(running).await.is_some());
+
+        // The freed slot can be reused
+        pool.add_arena(new_arena()).await.unwrap();
+        assert_eq!(pool.len().await, 2);
+    }
+}
@@ -2,6 +2,8 @@
 // from the clients, and send the game state back to the clients after each move
 
 use super::*;
+use crate::constants::PROTOCOL_VERSION;
+use crate::models::ArenaRequest;
 use std::collections::HashMap;
 use std::sync::{
     atomic::{AtomicUsize, Ordering},
@@ -9,7 +11,7 @@ use std::sync::{
 };
 
 use derive_more::{Display, Error};
-use futures_util::{stream::SplitSink, SinkExt, StreamExt, TryFutureExt};
+use futures_util::{stream::SplitSink, stream::SplitStream, SinkExt, StreamExt, TryFutureExt};
 use tokio::sync::{mpsc, RwLock};
 use warp::ws::{Message, WebSocket};
 use warp::Filter;
@@ -25,7 +27,7 @@ type StdError = Box<dyn std::error::Error>;
 
 const TIMEOUT: Duration = Duration::from_secs(4);
 
-static CLIENT_ID: AtomicUsize = AtomicUsize::new(0);
+static LAST_CLIENT_ID: AtomicUsize = AtomicUsize::new(0);
 static TURN_COUNTER: AtomicUsize = AtomicUsize::new(0);
 static LAST_PLAYER: AtomicUsize = AtomicUsize::new(5);
 
@@ -36,16 +38,77 @@ pub enum ParseError {
     Unknown,
     #[display(fmt = "Cannot convert client message to string")]
     CannotConvertToString,
-    #[display(fmt = "Cannot convert string to client message")]
-    CannotConvertToClientMessage,
+    #[display(fmt = "Cannot convert string to client message: {}", _0)]
+    CannotConvertToClientMessage(#[error(not(source))] String),
     #[display(fmt = "Message too long to display")]
     MessageTooLong,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ClientMessage {
-    Action(Action),
+    /// An action answering the `ClientInfo` with the given `request_id`
+    Action { action: Action, request_id: u64 },
     Log(String),
+    /// Signals that the client has finished initializing
+    /// and the game may start
+    Ready,
+}
+
+/// Messages from the server that are not a game state
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ServerMessage {
+    /// The client's last message was rejected, sent before
+    /// any fallback move is played on its behalf
+    Error(String),
+    /// The settings of the game, always the first message
+    /// sent to a client once it is connected
+    Config(GameConfig),
+}
+
+/// The settings a game is played with, fixed before the first move
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GameConfig {
+    pub num_players: usize,
+    /// The time each player starts with
+    pub initial_time: Duration,
+    /// The time credited to a player after each completed move
+    pub increment: Duration,
+    /// The points a player needs to trigger the end of the game
+    pub win_threshold: u8,
+    /// The `Card::set_hash` of the cards the game is played with
+    pub card_set_hash: u64,
+    /// The seats the receiving client plays for, the first of them
+    /// identifies the client when it reconnects (see `ConnectParams::seat`)
+    #[serde(default)]
+    pub seats: Vec<usize>,
+}
+
+#[derive(Debug, Display, Error)]
+pub enum InvalidActionError {
+    #[display(fmt = "Player {} is timed out", _0)]
+    TimedOut(#[error(not(source))] usize),
+    #[display(fmt = "The game is over, no actions are legal")]
+    GameOver,
+    #[display(fmt = "Illegal action: {:?}", _0)]
+    Illegal(#[error(not(source))] Action),
+    #[display(fmt = "Not player {}'s turn", _0)]
+    WrongTurn(#[error(not(source))] usize),
+    #[display(fmt = "The action answers request {}, which is no longer current", _0)]
+    StaleRequest(#[error(not(source))] u64),
+}
+
+/// Options a client can give as query parameters when connecting to the game
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ConnectParams {
+    pub username: Option<String>,
+    /// The number of seats the client plays for, defaults to one
+    pub seats: Option<usize>,
+    /// The `PROTOCOL_VERSION` the client was built with,
+    /// clients that leave it out are assumed to be compatible
+    pub version: Option<u32>,
+    /// The seat identifying a client whose connection dropped,
+    /// it is given back the seats it played for instead of new ones
+    pub seat: Option<usize>,
 }
 
 fn parse_message(message_text: &Message) -> Result<ClientMessage, ParseError> {
@@ -53,46 +116,63 @@ fn parse_message(message_text: &Message) -> Result<ClientMessage, ParseError> {
         .to_str()
         .map_err(|_| ParseError::CannotConvertToString)?;
     let client_msg: ClientMessage =
-        serde_json::from_str(message_str).map_err(|_| ParseError::CannotConvertToClientMessage)?;
+        serde_json::from_str(message_str).map_err(|e| ParseError::CannotConvertToClientMessage(e.to_string()))?;
     Ok(client_msg)
 }
 
-pub async fn validate_action(action: &Action, player_id: usize, arena: GlobalArena) -> bool {
+pub fn validate_action(
+    action: &Action,
+    request_id: u64,
+    player_id: usize,
+    arena: &Arena,
+) -> Result<(), InvalidActionError> {
+    // -> Answers the current game state, not a turn that has already ended
+    // (e.g. one a fallback move was played for)
+    if request_id != arena.request_id() {
+        info!(
+            "Player {} answered request {} late, the current request is {}",
+            player_id,
+            request_id,
+            arena.request_id()
+        );
+        return Err(InvalidActionError::StaleRequest(request_id));
+    }
+
     // -> The current player is not timed out  
-    if arena.read().await.is_timed_out(){
+    if arena.is_timed_out(){
         error!("Player {} is timed out!", player_id);
-        return false;
+        return Err(InvalidActionError::TimedOut(player_id));
     }
 
-    // -> Is a legal action
-    let actions = arena.read().await.get_legal_actions();
+    // -> Is a legal action, purchases must pay with exactly one of
+    // the enumerated payments, so over or underpaying is rejected
+    let actions = arena.get_legal_actions();
     if actions.is_none() {
         error!("No legal actions found!");
-        return false;
+        return Err(InvalidActionError::GameOver);
     }
 
     let actions = actions.unwrap();
     if !actions.contains(action) {
         error!("Illegal action: {:?}", action);
-        return false;
+        return Err(InvalidActionError::Illegal(action.clone()));
     }
 
     // -> Is the correct player's turn
-    if arena.read().await.current_player_num() != Some(player_id) {
+    if arena.current_player_num() != Some(player_id) {
         error!("Not player {}'s turn!", player_id);
-        return false;
+        return Err(InvalidActionError::WrongTurn(player_id));
     }
 
-
-    return true;
-
+    Ok(())
 }
 
-pub async fn log_stream_connected(socket: WebSocket) {
+pub async fn log_stream_connected(socket: WebSocket, arena: GlobalArena) {
     // TODO: This makes an assumption that
     // the client that last connected is the one that is logging
     // This may not be a good assumption
-    let id = CLIENT_ID.load(Ordering::Relaxed) - 1;
+    let id = LAST_CLIENT_ID.load(Ordering::Relaxed);
+    let label = arena.read().await.log_label(id);
 
     let (_tx, mut rx) = socket.split();
     while let Some(msg) = rx.next().await {
@@ -104,11 +184,11 @@ pub async fn log_stream_connected(socket: WebSocket) {
 
         let client_msg = parse_message(&msg);
         if let Err(e) = client_msg {
-            error!("error parsing message! {:?}", e);
-            break;
+            error!("error parsing log message from {}! {}", label, e);
+            continue;
         }
         match client_msg.unwrap() {
-            ClientMessage::Action(action) => {
+            ClientMessage::Action { action, .. } => {
                 error!("Actions sent to the wrong endpoint! {:?}", action);
                 break;
             }
@@ -116,19 +196,68 @@ pub async fn log_stream_connected(socket: WebSocket) {
                 println!(
                     "[Turn : {}] [Player {}]: {}",
                     TURN_COUNTER.load(Ordering::SeqCst),
-                    id,
+                    label,
                     log
                 );
             }
+            ClientMessage::Ready => {
+                error!("Ready sent to the wrong endpoint!");
+                break;
+            }
         }
     }
 }
 
 /// Setup a new client to play the game
-pub async fn user_connected(ws: WebSocket, clients: Clients, arena: GlobalArena, web_stream : Option<Outgoing>) {
-    let (client_tx, mut client_rx) = ws.split();
-    let my_id = CLIENT_ID.fetch_add(1, Ordering::Relaxed);
+pub async fn user_connected(ws: WebSocket, params: ConnectParams, clients: Clients, arena: GlobalArena, web_stream : Option<Outgoing>) {
+    let (mut client_tx, mut client_rx) = ws.split();
+    if let Some(version) = params.version.filter(|&version| version != PROTOCOL_VERSION) {
+        error!("Rejected a client speaking protocol version {}", version);
+        let error = format!(
+            "Incompatible protocol version {}, the game server speaks version {}",
+            version, PROTOCOL_VERSION
+        );
+        reject_client(client_tx, error).await;
+        return;
+    }
+    // There are no turns left to wait for
+    if arena.read().await.is_game_over() {
+        info!("Rejected a client connecting after the game ended");
+        reject_client(client_tx, "The game is already over".to_owned()).await;
+        return;
+    }
+    // A client is identified by the first seat it takes
+    let my_seats = match params.seat {
+        Some(seat) => match arena.read().await.reclaim_seats(seat) {
+            Some(seats) => seats,
+            None => {
+                info!("Rejected a client reconnecting to seat {}, which no client took", seat);
+                reject_client(client_tx, format!("Seat {} was never taken", seat)).await;
+                return;
+            }
+        },
+        None => match arena.write().await.take_seats(params.seats.unwrap_or(1)) {
+            Some(seats) => seats,
+            None => {
+                info!("Rejected a client connecting after every seat was taken");
+                reject_client(client_tx, "Every seat is already taken".to_owned()).await;
+                return;
+            }
+        },
+    };
+    let my_id = my_seats[0];
+    let reconnected = params.seat.is_some();
+    LAST_CLIENT_ID.store(my_id, Ordering::Relaxed);
+    // Replacing the sink of a reconnecting client closes its old connection
     clients.write().await.insert(my_id, client_tx);
+    let session = arena.write().await.connect_seats(&my_seats);
+    if let Some(username) = &params.username {
+        for &seat in &my_seats {
+            arena.write().await.set_username(seat, username);
+        }
+    }
+    send_config(my_id, &clients, &arena).await;
+    send_snapshot(my_id, &clients, &arena).await;
 
     let init_clients = clients.clone();
     let init_arena = arena.clone();
@@ -140,165 +269,376 @@ pub async fn user_connected(ws: WebSocket, clients: Clients, arena: GlobalArena,
     // Convert messages from the client into a stream of actions
     // So we play them in the game as soon as they come in
     tokio::spawn(async move {
+        // Wait for the client to finish initializing before it can take a seat
+        let ready = wait_for_ready(my_id, &mut client_rx, &clients).await;
+        if !ready {
+            info!("Player {} disconnected before it was ready", my_id);
+            user_disconnected(my_id, session, clients, arena).await;
+            return;
+        }
+
+        // All seats are filled and ready, start the game
+        let mut all_ready = false;
+        for &seat in &my_seats {
+            all_ready |= arena.write().await.mark_ready(seat);
+        }
+        if all_ready {
+            game_initialized(clients.clone(), arena.clone(), outgoing_clone.clone()).await;
+        }
+        // A client that reconnects mid turn missed the request for its move
+        if reconnected {
+            resend_turn(my_id, &my_seats, &clients, &arena).await;
+        }
+
+        let mut connected = true;
         loop {
-            // Wait until all players are connected
-            // and it is the current player's turn
-            while (
-                arena.read().await.current_player_num() != Some(my_id)
-                && !arena.read().await.is_game_over()
-            ) {
-                tokio::time::sleep(Duration::from_millis(10)).await;
-            }
+            // Wait until all players are connected and it is the turn of one
+            // of our seats, unless the client reconnected on a new connection
+            let seat = loop {
+                let arena = arena.read().await;
+                if arena.is_game_over() || !arena.is_current_session(my_id, session) {
+                    break None;
+                }
+                match arena.current_player_num() {
+                    Some(current) if my_seats.contains(&current) => break Some(current),
+                    _ => {
+                        drop(arena);
+                        tokio::time::sleep(Duration::from_millis(10)).await
+                    }
+                }
+            };
 
-            if arena.read().await.is_game_over() {
-                break;
-            }
+            let seat = match seat {
+                Some(seat) => seat,
+                None => break,
+            };
 
             // Give a little extra time to account for network + server latency
-            let time_remaining = arena.read().await.time_remaining();
+            let (request_id, time_remaining) = {
+                let arena = arena.read().await;
+                (arena.request_id(), arena.time_remaining())
+            };
             let time_remaining = time_remaining + Duration::from_millis(10);
 
-            match timeout(time_remaining, client_rx.next()).await {
+            // The connection dropped, the client has until the end of the turn
+            // to reconnect before a move is played for it
+            if !connected {
+                tokio::time::sleep(time_remaining).await;
+                let abandoned = {
+                    let arena = arena.read().await;
+                    arena.is_current_session(my_id, session) && arena.request_id() == request_id
+                };
+                if abandoned {
+                    play_default_action(seat, clients.clone(), arena.clone(), outgoing_clone.clone()).await;
+                }
+                continue;
+            }
+
+            let received = timeout(time_remaining, client_rx.next()).await;
+            // The client reconnected while we were waiting, its new connection plays on
+            if !arena.read().await.is_current_session(my_id, session) {
+                break;
+            }
+            match received {
 
-                Ok(Some(msg)) => {
+                Ok(Some(Ok(msg))) => {
                     trace!("Received message: {:?}", msg);
-                    if let Err(e) = msg {
-                        play_default_action(my_id, clients.clone(), arena.clone(), outgoing_clone.clone()).await;
-                        continue;
-                    }
-                    let msg = msg.unwrap();
 
+                    // The turn is not forfeited, the client may still answer
+                    // with a valid message before its time runs out
                     let client_msg = parse_message(&msg);
                     if let Err(e) = client_msg {
                         error!("error parsing message from json string! {:?}", e);
-                        play_default_action(my_id, clients.clone(), arena.clone(), outgoing_clone.clone()).await;
+                        send_error(my_id, &clients, format!("Unable to parse message: {}", e)).await;
                         continue;
                     }
 
                     match client_msg.unwrap() {
-                        ClientMessage::Action(action) => {
-                            if !validate_action(&action, my_id, arena.clone()).await {
-                                play_default_action(my_id, clients.clone(), arena.clone(), outgoing_clone.clone()).await;
-                                continue;
+                        ClientMessage::Action { action, request_id } => {
+                            // Validate, play and advance the game under a single lock
+                            let played = {
+                                let mut arena = arena.write().await;
+                                validate_action(&action, request_id, seat, &arena).map(|_| {
+                                    trace!("{} played {:?}", seat, action);
+                                    arena.play_action(action);
+                                    advance(&mut arena, outgoing_clone.is_some())
+                                })
+                            };
+                            match played {
+                                Ok(update) => send_turn_update(update, &clients, outgoing_clone.clone()).await,
+                                // A stale answer is dropped without costing the current turn
+                                Err(e @ InvalidActionError::StaleRequest(_)) => {
+                                    send_error(my_id, &clients, e.to_string()).await;
+                                }
+                                Err(e) => {
+                                    send_error(my_id, &clients, e.to_string()).await;
+                                    arena.write().await.record_fallback();
+                                    play_default_action(seat, clients.clone(), arena.clone(), outgoing_clone.clone()).await;
+                                }
                             }
-
-                            trace!("{} played {:?}", my_id, action);
-                            arena.write().await.play_action(action);
-                            action_played(clients.clone(), arena.clone(), outgoing_clone.clone()).await;
                         }
                         ClientMessage::Log(log) => {
                             error!("Logs sent to the wrong endpoint! {:?}", log);
                             continue;
                         }
+                        ClientMessage::Ready => {
+                            error!("Player {} is already ready!", my_id);
+                            continue;
+                        }
                     }
                 }
-                Ok(_) => panic!("unexpected None"),
+                // The connection is gone, but the seats stay in play
+                Ok(_) => {
+                    info!("Player {} lost its connection, waiting for it to reconnect", my_id);
+                    user_disconnected(my_id, session, clients.clone(), arena.clone()).await;
+                    connected = false;
+                }
                 Err(e) => {
-                    play_default_action(my_id, clients.clone(), arena.clone(), outgoing_clone.clone()).await;
+                    send_error(my_id, &clients, format!("Player {} timed out", seat)).await;
+                    play_default_action(seat, clients.clone(), arena.clone(), outgoing_clone.clone()).await;
                 }
             }
         }
         info!("Player {} disconnected", my_id);
-        user_disconnected(my_id, clients, arena).await;
+        user_disconnected(my_id, session, clients, arena).await;
     });
 
-    let num_players = init_arena.read().await.players().len();
     user_initialized(my_id, init_clients.clone(), init_arena.clone()).await;
+}
+
+/// Tell a client why it cannot join the game and close its connection
+async fn reject_client(mut client_tx: SplitSink<WebSocket, Message>, error: String) {
+    let message = serde_json::to_string(&ServerMessage::Error(error)).expect("Error converting error to string");
+    let _ = client_tx.send(Message::text(message)).await;
+    let _ = client_tx.close().await;
+}
+
+/// Tell a newly connected client the settings of the game and its seats
+async fn send_config(my_id: usize, clients: &Clients, arena: &GlobalArena) {
+    let config = {
+        let arena = arena.read().await;
+        GameConfig {
+            seats: arena.seats_of(my_id),
+            ..arena.game_config()
+        }
+    };
+    let config = ServerMessage::Config(config);
+    let config = serde_json::to_string(&config).expect("Error converting config to string");
+    if let Some(tx) = clients.write().await.get_mut(&my_id) {
+        if let Err(e) = tx.send(Message::text(config)).await {
+            error!("Failed to send config to player {}: {:?}", my_id, e);
+        }
+    }
+}
 
-    // All users are connected, start the game
-    if my_id == num_players - 1 {
-        game_initialized(init_clients, init_arena, outgoing.clone()).await;
+/// Send the current public state of the game to a newly connected client
+/// so it does not have to wait for its turn to see the board
+async fn send_snapshot(my_id: usize, clients: &Clients, arena: &GlobalArena) {
+    let snapshot = arena.read().await.small_client_info();
+    let snapshot = serde_json::to_string(&snapshot).expect("Error converting snapshot to string");
+    if let Some(tx) = clients.write().await.get_mut(&my_id) {
+        if let Err(e) = tx.send(Message::text(snapshot)).await {
+            error!("Failed to send snapshot to player {}: {:?}", my_id, e);
+        }
     }
 }
-pub async fn play_default_action(my_id : usize, clients: Clients, arena: GlobalArena, web_stream : Option<Outgoing>) {
-    if arena.read().await.is_game_over() {
-        return;
+
+/// Send the current game state again if it is the turn of one of the client's seats
+async fn resend_turn(my_id: usize, seats: &[usize], clients: &Clients, arena: &GlobalArena) {
+    let info = {
+        let arena = arena.read().await;
+        match arena.current_player_num() {
+            Some(current) if seats.contains(&current) && !arena.is_game_over() => arena.client_info(),
+            _ => return,
+        }
+    };
+    let info = serde_json::to_string(&info).expect("Error converting game state to string");
+    if let Some(tx) = clients.write().await.get_mut(&my_id) {
+        if let Err(e) = tx.send(Message::text(info)).await {
+            error!("Failed to resend game state to player {}: {:?}", my_id, e);
+        }
+    }
+}
+
+/// Tell a client why its last message was rejected
+async fn send_error(my_id: usize, clients: &Clients, error: String) {
+    let message = serde_json::to_string(&ServerMessage::Error(error)).expect("Error converting error to string");
+    if let Some(tx) = clients.write().await.get_mut(&my_id) {
+        if let Err(e) = tx.send(Message::text(message)).await {
+            error!("Failed to send error to player {}: {:?}", my_id, e);
+        }
     }
-    
-    println!("[Turn : {}] [Player {} (crashed/timed out)] Playing a random move...", TURN_COUNTER.load(Ordering::SeqCst), my_id);
-    let action = arena.read().await.get_legal_actions().unwrap()[0].clone();
-    arena.write().await.play_action(action);
-    action_played(clients.clone(), arena.clone(), web_stream.clone()).await;
+}
+
+/// Read messages from a newly connected client until it signals that it is ready,
+/// returns false if the client disconnected first
+async fn wait_for_ready(my_id: usize, client_rx: &mut SplitStream<WebSocket>, clients: &Clients) -> bool {
+    while let Some(msg) = client_rx.next().await {
+        let msg = match msg {
+            Ok(msg) => msg,
+            Err(e) => {
+                trace!("error receiving message! {:?}", e);
+                return false;
+            }
+        };
+        match parse_message(&msg) {
+            Ok(ClientMessage::Ready) => {
+                info!("{} is ready", my_id);
+                return true;
+            }
+            Ok(msg) => error!("Player {} sent {:?} before it was ready", my_id, msg),
+            Err(e) => {
+                error!("error parsing message from json string! {:?}", e);
+                send_error(my_id, clients, format!("Unable to parse message: {}", e)).await;
+            }
+        }
+    }
+    false
+}
+pub async fn play_default_action(my_id : usize, clients: Clients, arena: GlobalArena, web_stream : Option<Outgoing>) {
+    let update = {
+        let mut arena = arena.write().await;
+        if arena.is_game_over() {
+            return;
+        }
+
+        println!("[Turn : {}] [Player {} (crashed/timed out)] Playing a random move...", TURN_COUNTER.load(Ordering::SeqCst), my_id);
+        let action = match arena.suggested_noble() {
+            Some(noble) => Action::AttractNoble(noble),
+            None => arena.get_legal_actions().unwrap()[0].clone(),
+        };
+        arena.play_action(action);
+        advance(&mut arena, web_stream.is_some())
+    };
+    send_turn_update(update, &clients, web_stream).await;
 }
 
 pub async fn game_initialized(clients: Clients, arena: GlobalArena, web_stream : Option<Outgoing>) {
     info!("All users locked and loaded! Game starting!");
-    arena.write().await.start_game();
-    action_played(clients, arena, web_stream).await;
+    let update = {
+        let mut arena = arena.write().await;
+        arena.start_game();
+        advance(&mut arena, web_stream.is_some())
+    };
+    send_turn_update(update, &clients, web_stream).await;
 }
 
 pub async fn user_initialized(my_id: usize, clients: Clients, arena: GlobalArena) {
-    info!("{} connected", my_id);
+    match arena.read().await.username(my_id) {
+        Some(username) => info!("{} ({}) connected", my_id, username),
+        None => info!("{} connected", my_id),
+    }
 }
 
-pub async fn user_disconnected(my_id: usize, clients: Clients, arena: GlobalArena) {
+/// Forget the connection of the given session, unless the client
+/// has already reconnected on a new one
+pub async fn user_disconnected(my_id: usize, session: u64, clients: Clients, arena: GlobalArena) {
+    let mut arena = arena.write().await;
+    if !arena.is_current_session(my_id, session) {
+        return;
+    }
     clients.write().await.remove(&my_id);
+    for seat in arena.seats_of(my_id) {
+        arena.mark_disconnected(seat);
+    }
 }
 
-pub async fn action_played(clients: Clients, arena: GlobalArena, web_stream : Option<Outgoing>) {
-    //  An action was played, be sure to send the game state to the web server
-    //  if it is connected
-    let stream = web_stream.clone();
-    if  stream.is_some() {
-        web::push_game_update(stream.unwrap(), arena.clone()).await;
-    }
-    // Auto play for any given player if there is only 1 legal action
+/// Everything that changed while the arena was locked for a move,
+/// sent out with `send_turn_update` once the lock is released
+#[derive(Debug)]
+pub struct TurnUpdate {
+    /// Updates for the web server, one for the state after each action played
+    pub web_updates: Vec<ArenaRequest>,
+    /// The client that must act next and the game state to send it,
+    /// None if the game is over
+    pub next_turn: Option<(usize, ClientInfo)>,
+}
+
+/// Auto play for any player that has only 1 legal action and prepare the game state
+/// for whoever must act next, finalizing the game instead if it is over
+pub fn advance(arena: &mut Arena, report_to_web: bool) -> TurnUpdate {
+    let mut web_updates = Vec::new();
     loop {
+        // An action was played, be sure to send the game state to the web server
+        // if it is connected
+        if report_to_web {
+            web_updates.push(get_game_update(arena));
+        }
+
         // If the game is over, don't do anything else
-        if arena.read().await.is_game_over() {
+        if arena.is_game_over() {
             info!("Game over!");
-            let winner = arena.read().await.get_winner();
-            match winner {
-                Some(winner) => info!("Winner: Player {:?}", winner),
-                None => info!("No winner! Draw!"),
+            let results = arena.game_results();
+            match results.outcome {
+                GameOutcome::Winner(winner) => {
+                    info!("Winner: Player {:?} ({})", winner, results.player_names[winner])
+                }
+                _ => info!("No winner! Draw!"),
             }
-            arena.write().await.finalize_game();
+            arena.finalize_game();
 
-            return;
+            return TurnUpdate { web_updates, next_turn: None };
         }
 
-        let actions = arena
-            .read()
-            .await
-            .get_legal_actions()
-            .expect("Cannot get legal actions");
-        if actions.len() != 1 {
-            break;
-        }
-        let action = actions[0].clone();
+        let action = match arena.only_forced_move() {
+            Some(action) => action,
+            None => break,
+        };
         trace!("Auto played action: {:?}", action);
-        arena.write().await.play_action(action);
-        let stream = web_stream.clone();
-        // An action was played, be sure to send the game state to the web server
-        if stream.is_some() {
-            web::push_game_update(stream.unwrap(), arena.clone()).await;
+        arena.play_action(action);
+    }
+
+    // Determine which client to send the next game state to
+    let client_info = arena.client_info();
+    let client_id = arena.seat_owner(client_info.current_player_num);
+    TurnUpdate {
+        web_updates,
+        next_turn: Some((client_id, client_info)),
+    }
+}
+
+/// Advance the game after an action was played and send out the resulting updates
+pub async fn action_played(clients: Clients, arena: GlobalArena, web_stream : Option<Outgoing>) {
+    let update = advance(&mut *arena.write().await, web_stream.is_some());
+    send_turn_update(update, &clients, web_stream).await;
+}
+
+/// Push the game updates to the web server if it is connected,
+/// then send the game state to the client that must act next
+pub async fn send_turn_update(update: TurnUpdate, clients: &Clients, web_stream : Option<Outgoing>) {
+    if let Some(stream) = web_stream {
+        for game_update in update.web_updates {
+            web::push_game_update(stream.clone(), game_update).await;
         }
     }
 
-    let last_player = arena.read().await.current_player_num().expect("No current player, is the game started?");
+    let (client_id, client_info) = match update.next_turn {
+        Some(next_turn) => next_turn,
+        None => return,
+    };
+    let player_num = client_info.current_player_num;
 
-    if LAST_PLAYER.load(Ordering::SeqCst) != last_player {
+    if LAST_PLAYER.load(Ordering::SeqCst) != player_num {
         TURN_COUNTER.fetch_add(1, Ordering::SeqCst);
-        LAST_PLAYER.store(last_player, Ordering::SeqCst);
+        LAST_PLAYER.store(player_num, Ordering::SeqCst);
     }
 
     trace!("Sending game state to clients...");
-    // Determine which client to send the next game state to
-    let client_info = arena.read().await.client_info();
-    let player_num = client_info.current_player_num;
-
     // Wait up to TIMEOUT for the player to come online and make a move
-    if let None = clients.read().await.get(&player_num) {
+    if clients.read().await.get(&client_id).is_none() {
         tokio::time::sleep(TIMEOUT).await;
     }
 
     trace!("Sending game state to player {}", player_num);
-    if let Some(tx) = clients.write().await.get_mut(&player_num) {
+    if let Some(tx) = clients.write().await.get_mut(&client_id) {
         let info_str = serde_json::to_string(&client_info).unwrap();
         let info = Message::text(info_str);
-        tx.send(info).await.unwrap();
-        trace!("Sent game state!");
+        // A client that lost its connection is sent the game state when it reconnects
+        match tx.send(info).await {
+            Ok(_) => trace!("Sent game state!"),
+            Err(e) => error!("Failed to send game state to player {}: {:?}", player_num, e),
+        }
     } else {
-        panic!("no tx for client with id {}", player_num);
+        error!("No connection to send the game state to player {}", player_num);
     }
 }
@@ -48,16 +48,14 @@ pub async fn maintain_heartbeat(outgoing_stream : Outgoing) {
 pub fn push_reconnect() {
 }
 
-/// Pushes the current game state to the global server,
+/// Pushes a game update to the global server,
 /// TODO: wait for response confirming the update was successful
 /// TODO: if necessary, batch updates
 pub async fn push_game_update(
     outgoing_stream : Outgoing,
-    arena: GlobalArena,
+    game_update: ArenaRequest,
 ) {
     let mut outgoing_stream = outgoing_stream.write().await;
-    let arena = arena.read().await;
-    let game_update = get_game_update(&arena).await.expect("Failed to get game update");
     let message = serde_json::to_string(&game_update).expect("Failed to serialize game update");
     let message = Message::text(message);
     debug!("Sending game update to global server...");
@@ -65,11 +63,12 @@ pub async fn push_game_update(
 }
 
 
-pub async fn get_game_update(arena : &Arena) -> Result<ArenaRequest, ()> {
+/// The request that brings the global server up to date with the arena's game
+pub fn get_game_update(arena : &Arena) -> ArenaRequest {
     let game_state = arena.small_client_info();
     match arena.client_info().history.num_actions() {
         0 => {
-            return Ok(ArenaRequest::InitializeGame{ info: game_state })
+            ArenaRequest::InitializeGame{ info: game_state }
         }
         _ => {
             let num_moves = arena.client_info().history.num_moves();
@@ -77,16 +76,15 @@ pub async fn get_game_update(arena : &Arena) -> Result<ArenaRequest, ()> {
                 info: game_state,
                 update_num: num_moves as usize + 1,
             };
-            return Ok(ArenaRequest::GameUpdates(vec![game_update]))
+            ArenaRequest::GameUpdates(vec![game_update])
         }
     }
-
-    todo!();
 }
 
 /// Pushes an initial game state to the global server
-/// and waits for a response, returning the id of the game if the initialization
-/// was successful, and None otherwise
+/// and waits for a response, returning the url of the game if the initialization
+/// was successful, and None otherwise. The id the server assigned to the game
+/// is recorded on the arena so the game can later be reconnected
 ///
 /// Assumes no moves were made in the game yet
 pub async fn push_initial_game(
@@ -96,10 +94,11 @@ pub async fn push_initial_game(
 ) -> Option<String> {
     debug!("Pushing initial game state to global server...");
 
-    let arena = arena.read().await;
-    assert!(arena.client_info().history.num_moves() == 0);
-
-    let game_update = get_game_update(&arena).await.expect("Failed to get game update");
+    let game_update = {
+        let arena = arena.read().await;
+        assert!(arena.client_info().history.num_moves() == 0);
+        get_game_update(&arena)
+    };
     let game_update = serde_json::to_string(&game_update).expect("Failed to serialize game update");
     let message = Message::text(game_update);
 
@@ -137,6 +136,7 @@ pub async fn push_initial_game(
         match msg {
             GlobalServerResponse::Initialized(Initialized::Success{ id, url }) => {
                 debug!("Successfully initialized with stourney.com");
+                arena.write().await.set_game_id(id);
                 return Some(url)
             },
             GlobalServerResponse::Initialized(Initialized::Failure{ reason }) => {
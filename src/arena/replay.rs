@@ -1,11 +1,14 @@
 use super::*;
-use crate::card::CardId;
+use crate::card::{CardId, Cost};
 use crate::gem::Gem;
-use crate::nobles::Noble;
+use crate::nobles::{Noble, NobleId};
 use crate::gems::Gems;
 use log::trace;
-use std::collections::HashMap;
 use std::marker::PhantomData;
+use std::path::Path;
+use derive_more::{Display, Error};
+use futures_util::{SinkExt, StreamExt};
+use warp::ws::{Message, WebSocket};
 use warp::{Filter, Rejection, Reply};
 
 // Note: the following code results from me playing around with
@@ -64,6 +67,10 @@ impl Replay<Initialized> {
         }
     }
 
+    pub fn initial_game(&self) -> &Game {
+        &self.inner.initial_game
+    }
+
     pub fn finalize_with(self, history: GameHistory) -> Replay<Finalized> {
         Replay {
             inner: Finalized {
@@ -76,17 +83,181 @@ impl Replay<Initialized> {
     }
 }
 
+#[derive(Debug, Display, Error)]
+pub enum ReplayFileError {
+    #[display(fmt = "Unable to access the replay file: {}", _0)]
+    Io(std::io::Error),
+    #[display(fmt = "Unable to parse the replay file: {}", _0)]
+    Format(serde_json::Error),
+    #[display(fmt = "Replays of games with a custom card set are not supported")]
+    CustomCardSet,
+    #[display(fmt = "Noble {} of the replay file has invalid requirements", _0)]
+    InvalidNoble(#[error(not(source))] NobleId),
+}
+
+#[derive(Debug, Display, Error)]
+pub enum ActionLogError {
+    #[display(fmt = "Unable to access the action log: {}", _0)]
+    Io(std::io::Error),
+    #[display(fmt = "Unable to parse line {} of the action log: {}", _0, _1)]
+    Format(usize, serde_json::Error),
+    #[display(fmt = "The action log is empty, it must start with the initial game")]
+    Empty,
+    #[display(fmt = "The action log is for {} players, expected {}", _0, _1)]
+    PlayerCount(usize, usize),
+    #[display(fmt = "Action {} of the action log ({:?}) is not valid: {:?}", "_0.index", "_0.action", "_0.kind")]
+    Invalid(#[error(not(source))] Anomaly),
+    #[display(fmt = "Action logs only support games with the standard cards and nobles")]
+    CustomSet,
+}
+
+/// The on-disk format of a replay, everything else is rebuilt on load.
+/// Cards and nobles are serialized as their ids, so the nobles are saved
+/// in full next to the game and the card set is checked against its hash.
+/// Both are missing from replays saved before they were added
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedReplay {
+    initial_game: Game,
+    #[serde(default)]
+    nobles: Option<Vec<SavedNoble>>,
+    #[serde(default)]
+    card_set_hash: Option<u64>,
+    history: GameHistory,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedNoble {
+    id: NobleId,
+    points: u8,
+    requirements: Gems,
+}
+
+impl SavedNoble {
+    fn from(noble: &Noble) -> SavedNoble {
+        SavedNoble {
+            id: noble.id(),
+            points: noble.points(),
+            requirements: *noble.requirements(),
+        }
+    }
+
+    fn into_noble(self) -> Result<Noble, ReplayFileError> {
+        if self.requirements.gold != 0 || !self.requirements.legal() {
+            return Err(ReplayFileError::InvalidNoble(self.id));
+        }
+        Ok(Noble::from_requirements(self.points, self.id, self.requirements))
+    }
+}
+
+/// Whether the game is played with the standard cards, the only ones
+/// that can be looked up again from their ids
+fn has_standard_cards(game: &Game) -> bool {
+    Card::set_hash(&game.card_lookup()) == Card::set_hash(&Card::all())
+}
+
+/// Whether every noble of the game is one of the standard nobles
+fn has_standard_nobles(game: &Game) -> bool {
+    let standard = Noble::all();
+    game.nobles()
+        .iter()
+        .all(|noble| standard.get(noble.id() as usize) == Some(noble))
+}
+
 impl Replay<Finalized> {
-    pub fn next_move(&mut self) {
+    /// Write the replay to a file so it can be served later
+    pub fn save(&self, path: &Path) -> Result<(), ReplayFileError> {
+        if !has_standard_cards(&self.inner.initial_game) {
+            return Err(ReplayFileError::CustomCardSet);
+        }
+        let mut initial_game = self.inner.initial_game.clone();
+        let nobles = initial_game.replace_nobles(Vec::new());
+        let saved = SavedReplay {
+            initial_game,
+            nobles: Some(nobles.iter().map(SavedNoble::from).collect()),
+            card_set_hash: Some(Card::set_hash(&Card::all())),
+            history: self.inner.history.clone(),
+        };
+        let json = serde_json::to_string(&saved).map_err(ReplayFileError::Format)?;
+        std::fs::write(path, json).map_err(ReplayFileError::Io)
+    }
+
+    /// Read a replay previously written with `save`
+    pub fn load(path: &Path) -> Result<Replay<Finalized>, ReplayFileError> {
+        let json = std::fs::read_to_string(path).map_err(ReplayFileError::Io)?;
+        let saved: SavedReplay = serde_json::from_str(&json).map_err(ReplayFileError::Format)?;
+        if saved.card_set_hash.is_some_and(|hash| hash != Card::set_hash(&Card::all())) {
+            return Err(ReplayFileError::CustomCardSet);
+        }
+        let mut initial_game = saved.initial_game;
+        if let Some(nobles) = saved.nobles {
+            let nobles = nobles.into_iter().map(SavedNoble::into_noble).collect::<Result<_, _>>()?;
+            initial_game.replace_nobles(nobles);
+        }
+        Ok(Replay::new(initial_game).finalize_with(saved.history))
+    }
+
+    /// Write the replay as an action log (JSON lines): the initial game on the
+    /// first line, then one `[player_num, action]` pair per action played.
+    /// Only games with the standard cards and nobles can be written this way
+    pub fn save_action_log(&self, path: &Path) -> Result<(), ActionLogError> {
+        let initial_game = &self.inner.initial_game;
+        if !has_standard_cards(initial_game) || !has_standard_nobles(initial_game) {
+            return Err(ActionLogError::CustomSet);
+        }
+        let mut lines = Vec::with_capacity(self.inner.history.num_actions() + 1);
+        let initial = serde_json::to_string(&self.inner.initial_game).map_err(|e| ActionLogError::Format(1, e))?;
+        lines.push(initial);
+        for (index, entry) in self.inner.history.iter().enumerate() {
+            let line = serde_json::to_string(entry).map_err(|e| ActionLogError::Format(index + 2, e))?;
+            lines.push(line);
+        }
+        lines.push(String::new());
+        std::fs::write(path, lines.join("\n")).map_err(ActionLogError::Io)
+    }
+
+    /// Read an action log written with `save_action_log`, every action
+    /// is checked to be legal at the point it was played
+    pub fn load_action_log(path: &Path) -> Result<Replay<Finalized>, ActionLogError> {
+        let log = std::fs::read_to_string(path).map_err(ActionLogError::Io)?;
+        let mut lines = log.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+        let (_, initial) = lines.next().ok_or(ActionLogError::Empty)?;
+        let initial: Game = serde_json::from_str(initial).map_err(|e| ActionLogError::Format(1, e))?;
+
+        let mut history = GameHistory::new();
+        for (index, line) in lines {
+            let (player_num, action) = serde_json::from_str(line).map_err(|e| ActionLogError::Format(index + 1, e))?;
+            history.add(player_num, action);
+        }
+        if let Some(anomaly) = history.detect_anomalies(&initial).into_iter().next() {
+            return Err(ActionLogError::Invalid(anomaly));
+        }
+        Ok(Replay::new(initial).finalize_with(history))
+    }
+
+    /// The game after every move in the replay has been played
+    pub fn final_game(&self) -> Game {
+        let mut game = self.inner.initial_game.clone();
+        game.advance_history_with(&self.inner.history);
+        game
+    }
+
+    /// Build a replay that starts from an arbitrary position (e.g. a puzzle)
+    /// where the history only contains the actions played after that position
+    pub fn from_position(game: Game, history: GameHistory) -> Replay<Finalized> {
+        Replay::new(game).finalize_with(history)
+    }
+
+    pub fn next_move(&mut self) -> MovePosition {
         self.go_to_move(self.inner.move_index as i32 + 1)
     }
 
-    pub fn previous_move(&mut self) {
+    pub fn previous_move(&mut self) -> MovePosition {
         self.go_to_move(self.inner.move_index as i32 - 1)
     }
 
-    pub fn go_to_move(&mut self, new_move_index: i32) {
+    pub fn go_to_move(&mut self, new_move_index: i32) -> MovePosition {
         // Bound between 0 and the number of moves no matter the input
+        let requested = new_move_index;
         let new_move_index = new_move_index.max(0);
         let new_move_index = new_move_index.min(self.inner.history.num_moves());
 
@@ -96,9 +267,21 @@ impl Replay<Finalized> {
         let history = self.inner.history.take_until_move(new_move_index);
         trace!("Replaying history : {:?}", history);
         let mut init_game = self.inner.initial_game.clone();
-        init_game.advance_history_with(history);
+        init_game.advance_history_with(&history);
 
         self.inner.viewable_game = init_game;
+
+        MovePosition {
+            move_index: self.inner.move_index,
+            total_moves: self.num_moves(),
+            clamped: requested != new_move_index,
+        }
+    }
+
+    /// The number of moves in the replay as counted by `GameHistory::num_moves`,
+    /// which is also the index of the last move that can be viewed
+    pub fn num_moves(&self) -> usize {
+        self.inner.history.num_moves() as usize
     }
 
     pub fn current_game(&self) -> &Game {
@@ -108,6 +291,15 @@ impl Replay<Finalized> {
 
 pub type FinalizedReplay = Arc<RwLock<Replay<Finalized>>>;
 
+/// Where a replay landed after a request to move, `clamped` is set when the
+/// requested move was out of range and the closest valid move is shown instead
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct MovePosition {
+    pub move_index: usize,
+    pub total_moves: usize,
+    pub clamped: bool,
+}
+
 // (color/gem, amount)
 type JSTokens = Vec<(usize, i8)>;
 
@@ -142,10 +334,27 @@ pub struct JSPlayer {
     noble_points: u8,
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct JSCurrent {
+    #[serde(rename = "playerIndex")]
+    player_index: usize,
+    phase: Phase,
+}
+
+/// The full state of the viewable game, pushed over the replay websocket
+#[derive(Debug, Clone, Serialize)]
+pub struct JSReplayState {
+    move_index: usize,
+    nobles: Vec<JSTokens>,
+    cards: Vec<Vec<JSCard>>,
+    decks: Vec<JSDeck>,
+    bank: JSTokens,
+    players: Vec<JSPlayer>,
+    current: JSCurrent,
+}
+
 #[derive(Debug, Serialize)]
 enum Success {
-    #[serde(rename = "move_index")]
-    Move(usize),
     #[serde(rename = "nobles")]
     Nobles(Vec<JSTokens>),
     #[serde(rename = "cards")]
@@ -156,6 +365,13 @@ enum Success {
     Bank(JSTokens),
     #[serde(rename = "players")]
     Players(Vec<JSPlayer>),
+    #[serde(rename = "current")]
+    Current(JSCurrent),
+    #[serde(rename = "state")]
+    State(JSReplayState),
+    /// Serialized as the fields of the position, e.g. `{ "move_index": 3, .. }`
+    #[serde(untagged)]
+    Move(MovePosition),
 }
 
 #[derive(Debug, Serialize)]
@@ -184,10 +400,9 @@ pub async fn next_move(arena: GlobalArena) -> Result<impl Reply, Rejection> {
             "No replay available".to_string(),
         ))),
         Some(replay) => {
-            replay.write().await.next_move();
-            let move_index = replay.read().await.inner.move_index;
+            let position = replay.write().await.next_move();
             Ok(warp::reply::json(&EndpointReply::Success(Success::Move(
-                move_index,
+                position,
             ))))
         }
     }
@@ -200,10 +415,9 @@ pub async fn previous_move(arena: GlobalArena) -> Result<impl Reply, Rejection>
             "No replay available".to_string(),
         ))),
         Some(replay) => {
-            replay.write().await.previous_move();
-            let move_index = replay.read().await.inner.move_index;
+            let position = replay.write().await.previous_move();
             Ok(warp::reply::json(&EndpointReply::Success(Success::Move(
-                move_index,
+                position,
             ))))
         }
     }
@@ -217,53 +431,70 @@ pub async fn go_to_move(move_number: Move, arena: GlobalArena) -> Result<impl Re
         ))),
         Some(replay) => {
             let move_number = move_number.move_index;
-            replay.write().await.go_to_move(move_number);
-            let move_index = replay.read().await.inner.move_index;
+            let position = replay.write().await.go_to_move(move_number);
             Ok(warp::reply::json(&EndpointReply::Success(Success::Move(
-                move_index,
+                position,
             ))))
         }
     }
 }
 
 /// TODO: update this to use enums or string so we don't have to look at this reference again
-/// Match the conventions of the frontend gems
-///
-///          color    : index
-///	 white (diamond) : 0
-///	 blue (sapphire) : 1
-///	 green (emerald) : 2
-///	 red (ruby)      : 3
-///	 black (onyx)    : 4
-///	 yellow (gold)   : 5
-fn js_gems_map() -> HashMap<Gem, usize> {
-    let mut map = HashMap::new();
-    map.insert(Gem::Diamond, 0);
-    map.insert(Gem::Sapphire, 1);
-    map.insert(Gem::Emerald, 2);
-    map.insert(Gem::Ruby, 3);
-    map.insert(Gem::Onyx, 4);
-    map.insert(Gem::Gold, 5);
-    map
+/// Match the conventions of the frontend gems: white (diamond) is 0,
+/// blue (sapphire) 1, green (emerald) 2, red (ruby) 3, black (onyx) 4
+/// and yellow (gold) 5
+fn js_gem_index(gem: Gem) -> usize {
+    match gem {
+        Gem::Diamond => 0,
+        Gem::Sapphire => 1,
+        Gem::Emerald => 2,
+        Gem::Ruby => 3,
+        Gem::Onyx => 4,
+        Gem::Gold => 5,
+    }
 }
 
-/// Converts a noble to a vector representing the color distribution
-/// of the cost of the noble as a list of (color_index, number_needed)
-fn to_js_noble(noble: &Noble) -> JSTokens {
-    let mut map = js_gems_map();
-    let mut js_noble = Vec::new();
+/// Collect the (color_index, count) pairs of the given colors, skipping
+/// the colors with no tokens
+fn to_js_tokens(colors: &[Gem], count: impl Fn(Gem) -> i8) -> JSTokens {
+    colors
+        .iter()
+        .map(|&gem| (js_gem_index(gem), count(gem)))
+        .filter(|&(_, count)| count > 0)
+        .collect()
+}
 
-    let gems = noble.requirements();
+impl Gems {
+    /// Converts the gems to the frontend's list of (color_index, count),
+    /// leaving out colors with no tokens
+    pub fn to_js_tokens(&self, include_gold: bool) -> JSTokens {
+        let colors: &[Gem] = if include_gold { &Gem::ALL } else { &Gem::ALL_EXCEPT_GOLD };
+        to_js_tokens(colors, |gem| self[gem])
+    }
+}
 
-    for gem in Gem::all_expect_gold() {
-        let index = map.get(&gem).unwrap();
-        let count = gems[gem];
-        if count > 0 {
-            js_noble.push((*index, count));
-        }
+impl Cost {
+    /// Converts the cost to the frontend's list of (color_index, count),
+    /// leaving out colors that cost nothing
+    pub fn to_js_tokens(&self) -> JSTokens {
+        to_js_tokens(&Gem::ALL_EXCEPT_GOLD, |gem| self[gem])
     }
+}
 
-    js_noble
+/// Converts a card to a JSCard using the conventions laid out in the frontend
+fn to_js_card(card: &Card) -> JSCard {
+    JSCard {
+        tier: (card.tier() - 1) as usize,
+        points: card.points() as usize,
+        color_index: js_gem_index(card.gem()),
+        tokens: card.cost().to_js_tokens(),
+    }
+}
+
+/// Converts a noble to a vector representing the color distribution
+/// of the cost of the noble as a list of (color_index, number_needed)
+fn to_js_noble(noble: &Noble) -> JSTokens {
+    noble.requirements().to_js_tokens(false)
 }
 
 /// Returns the nobles in the game, or an error if no replay is available
@@ -294,33 +525,7 @@ fn to_js_cards(card_ids: Vec<Vec<CardId>>, card_lookup: Arc<Vec<Card>>) -> Vec<V
         .flatten()
         .map(|&c| card_lookup[c as usize].clone())
         .collect::<Vec<Card>>();
-    let map = js_gems_map();
-    let js_cards: Vec<JSCard> = cards
-        .iter()
-        .map(|c| {
-            let tier = (c.tier() - 1) as usize;
-            let points = c.points() as usize;
-            let cost = c.cost();
-            let mut js_cost = Vec::new();
-
-            for gem in Gem::all_expect_gold() {
-                let index = map.get(&gem).unwrap();
-                let count = cost[gem];
-                if count > 0 {
-                    js_cost.push((*index, count));
-                }
-            }
-
-            let color_index = map.get(&c.gem()).unwrap();
-
-            JSCard {
-                tier,
-                points,
-                color_index: *color_index,
-                tokens: js_cost,
-            }
-        })
-        .collect();
+    let js_cards: Vec<JSCard> = cards.iter().map(to_js_card).collect();
 
     // Group by tier
     let mut grouped = vec![Vec::new(); 3];
@@ -381,16 +586,7 @@ pub async fn board_decks(arena: GlobalArena) -> Result<impl Reply, Rejection> {
 /// Converts a list of gems from the public board area to a list of JSGems
 /// using the conventions laid out in the frontend
 pub fn to_js_bank(gems: &Gems) -> JSTokens {
-    let map = js_gems_map();
-    let mut js_bank = Vec::new();
-    for gem in Gem::all() {
-        let index = map.get(&gem).unwrap();
-        let count = gems[gem];
-        if count > 0 {
-            js_bank.push((*index, count));
-        }
-    }
-    js_bank
+    gems.to_js_tokens(true)
 }
 
 /// Returns the bank (remaining gems available to be taken) in the game, or an error if no replay is available
@@ -414,54 +610,24 @@ pub async fn board_bank(arena: GlobalArena) -> Result<impl Reply, Rejection> {
 pub fn to_js_players(players: &Vec<Player>, card_lookup: Arc<Vec<Card>>) -> Vec<JSPlayer> {
     let mut js_players = Vec::new();
     for player in players {
-        let developments = player.developments();
-        let map = js_gems_map();
-
-        let mut js_developments = Vec::new();
-        let mut js_gems = Vec::new();
-        let mut js_cards =  Vec::new();
-
-
-        for gem in Gem::all_expect_gold() {
-            let index = map.get(&gem).unwrap();
-            let count = developments[gem];
-            js_developments.push((*index, count));
-
-        }
-
-        for gem in Gem::all() {
-            let index = map.get(&gem).unwrap();
-            let count = player.gems()[gem];
-            js_gems.push((*index, count));
-        }
-
-        for card_id in player.all_reserved() {
-            let card = card_lookup[card_id as usize].clone();
-            let tier = (card.tier() - 1) as usize;
-            let points = card.points() as usize;
-            let cost = card.cost();
-            let mut js_cost = Vec::new();
-
-            for gem in Gem::all_expect_gold() {
-                let index = map.get(&gem).unwrap();
-                let count = cost[gem];
-                if count > 0 {
-                    js_cost.push((*index, count));
-                }
-            }
-
-            let color_index = map.get(&card.gem()).unwrap();
-            let color_index = *color_index;
-
-            js_cards.push(JSCard {
-                tier,
-                points,
-                color_index,
-                tokens: js_cost,
-            });
-        }
-
-        let total_gems = player.gems().total();
+        let developments = player.developments().as_cost_dropping_gold();
+
+        // Unlike the other tokens, every color is listed even when empty
+        let js_developments = Gem::ALL_EXCEPT_GOLD
+            .iter()
+            .map(|&gem| (js_gem_index(gem), developments[gem]))
+            .collect();
+        let js_gems = Gem::ALL
+            .iter()
+            .map(|&gem| (js_gem_index(gem), player.gems()[gem]))
+            .collect();
+        let js_cards = player
+            .all_reserved()
+            .iter()
+            .map(|&card_id| to_js_card(&card_lookup[card_id as usize]))
+            .collect();
+
+        let total_gems = player.gems().total() as u32;
         let total_points = player.total_points();
         let noble_points = player.noble_points();
 
@@ -494,3 +660,248 @@ pub async fn board_players(arena: GlobalArena) -> Result<impl Reply, Rejection>
         }
     }
 }
+
+/// Returns the player whose turn it is and the phase of their turn,
+/// or an error if no replay is available
+pub async fn board_current(arena: GlobalArena) -> Result<impl Reply, Rejection> {
+    let replay = arena.write().await.get_replay();
+    match replay {
+        None => Ok(warp::reply::json(&EndpointReply::Error(
+            "No replay available".to_string(),
+        ))),
+        Some(replay) => {
+            let game = &replay.read().await.inner.viewable_game;
+            let current = JSCurrent {
+                player_index: game.current_player_num(),
+                phase: game.phase(),
+            };
+            Ok(warp::reply::json(&EndpointReply::Success(Success::Current(
+                current,
+            ))))
+        }
+    }
+}
+
+/// Commands a replay viewer can send over the replay websocket
+#[derive(Debug, Deserialize, Serialize)]
+pub enum ReplayCommand {
+    #[serde(rename = "next")]
+    Next,
+    #[serde(rename = "previous")]
+    Previous,
+    #[serde(rename = "goto")]
+    Goto(Move),
+    /// Fetch the state without moving
+    #[serde(rename = "current")]
+    Current,
+}
+
+/// Gathers the full viewable state of a replay into a single message
+fn to_js_replay_state(replay: &Replay<Finalized>) -> JSReplayState {
+    let game = &replay.inner.viewable_game;
+    let card_lookup = game.card_lookup();
+    JSReplayState {
+        move_index: replay.inner.move_index,
+        nobles: game.nobles().iter().map(to_js_noble).collect(),
+        cards: to_js_cards(game.cards(), card_lookup.clone()),
+        decks: to_js_decks(game.deck_counts()),
+        bank: to_js_bank(game.bank()),
+        players: to_js_players(game.players(), card_lookup),
+        current: JSCurrent {
+            player_index: game.current_player_num(),
+            phase: game.phase(),
+        },
+    }
+}
+
+/// Drive the replay over a websocket, every command is answered
+/// with the full state of the replay after applying it
+pub async fn replay_connected(ws: WebSocket, arena: GlobalArena) {
+    let (mut tx, mut rx) = ws.split();
+    while let Some(Ok(msg)) = rx.next().await {
+        if msg.is_close() {
+            break;
+        }
+        let Ok(text) = msg.to_str() else {
+            continue;
+        };
+
+        let reply = match (serde_json::from_str::<ReplayCommand>(text), arena.read().await.get_replay()) {
+            (Err(e), _) => EndpointReply::Error(format!("Unknown replay command: {}", e)),
+            (_, None) => EndpointReply::Error("No replay available".to_string()),
+            (Ok(command), Some(replay)) => {
+                let mut replay = replay.write().await;
+                match command {
+                    ReplayCommand::Next => {
+                        replay.next_move();
+                    }
+                    ReplayCommand::Previous => {
+                        replay.previous_move();
+                    }
+                    ReplayCommand::Goto(target) => {
+                        replay.go_to_move(target.move_index);
+                    }
+                    ReplayCommand::Current => {}
+                }
+                EndpointReply::Success(Success::State(to_js_replay_state(&replay)))
+            }
+        };
+
+        let reply = serde_json::to_string(&reply).expect("Error converting replay state to string");
+        if tx.send(Message::text(reply)).await.is_err() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_js_tokens() {
+        let gems = Gems { onyx: 1, diamond: 2, gold: 3, ..Gems::empty() };
+        assert_eq!(gems.to_js_tokens(true), vec![(4, 1), (0, 2), (5, 3)]);
+        assert_eq!(gems.to_js_tokens(false), vec![(4, 1), (0, 2)]);
+        assert_eq!(to_js_bank(&gems), gems.to_js_tokens(true));
+
+        let cost = Cost { sapphire: 3, ruby: 1, ..Cost::default() };
+        assert_eq!(cost.to_js_tokens(), vec![(1, 3), (3, 1)]);
+        let card = Card::all()[0];
+        assert_eq!(to_js_card(&card).tokens, card.cost().to_js_tokens());
+    }
+
+    #[test]
+    fn test_replay_current_player_alternates() {
+        let game = Game::new(2, Arc::new(Card::all()));
+        let mut played = game.clone();
+        for _ in 0..4 {
+            played.play_action(Action::ReserveHidden(0));
+            played.play_action(Action::Pass);
+            played.play_action(Action::Continue);
+        }
+
+        let mut replay = Replay::new(game).finalize_with(played.history());
+        for move_index in 0..4 {
+            replay.go_to_move(move_index);
+            let current = replay.current_game();
+            assert_eq!(current.current_player_num(), (move_index as usize + 1) % 2);
+            assert_eq!(current.phase(), Phase::PlayerStart);
+        }
+    }
+
+    #[test]
+    fn test_replay_from_mid_game_position() {
+        let mut position = Game::new(2, Arc::new(Card::all()));
+        for _ in 0..3 {
+            position.play_action(Action::ReserveHidden(0));
+            position.play_action(Action::Pass);
+            position.play_action(Action::Continue);
+        }
+        let actions_before = position.history().num_actions();
+
+        let mut played = position.clone();
+        let mut reserved_after_each_move = Vec::new();
+        for _ in 0..3 {
+            played.play_action(Action::ReserveHidden(1));
+            played.play_action(Action::Pass);
+            played.play_action(Action::Continue);
+            let reserved: Vec<usize> = played
+                .players()
+                .iter()
+                .map(|p| p.num_reserved_cards())
+                .collect();
+            reserved_after_each_move.push(reserved);
+        }
+        let history = GameHistory::from(played.history().history[actions_before..].to_vec());
+
+        let mut replay = Replay::from_position(position, history);
+        assert_eq!(replay.current_game().players()[0].num_reserved_cards(), 2);
+        assert_eq!(replay.current_game().players()[1].num_reserved_cards(), 1);
+
+        for (move_index, reserved) in reserved_after_each_move.iter().enumerate() {
+            replay.go_to_move(move_index as i32);
+            let current = replay.current_game();
+            let current_reserved: Vec<usize> = current
+                .players()
+                .iter()
+                .map(|p| p.num_reserved_cards())
+                .collect();
+            assert_eq!(&current_reserved, reserved);
+            assert_eq!(
+                current.history().num_actions(),
+                actions_before + 3 * (move_index + 1)
+            );
+        }
+    }
+
+    #[test]
+    fn test_save_and_load_custom_nobles() {
+        let mut game = Game::new(2, Arc::new(Card::all()));
+        let custom = Noble::from_requirements(4, 3, Gems::from_counts(&[(Gem::Ruby, 1)]));
+        let unknown = Noble::from_requirements(3, 42, Gems::from_counts(&[(Gem::Onyx, 9)]));
+        game.with_custom_nobles(vec![custom.clone(), unknown.clone()]).unwrap();
+        let mut played = game.clone();
+        played.play_action(Action::ReserveHidden(0));
+        played.play_action(Action::Pass);
+        played.play_action(Action::Continue);
+
+        let replay = Replay::new(game).finalize_with(played.history());
+        let path = std::env::temp_dir().join("splendor_test_custom_nobles.json");
+        replay.save(&path).unwrap();
+        let loaded = Replay::load(&path);
+        std::fs::remove_file(&path).ok();
+
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.current_game().nobles(), &vec![custom, unknown]);
+        assert_eq!(loaded.final_game().nobles(), played.nobles());
+        assert_eq!(loaded.num_moves(), replay.num_moves());
+
+        // Action logs only carry noble ids, so they refuse custom nobles
+        let path = std::env::temp_dir().join("splendor_test_custom_nobles.jsonl");
+        assert!(matches!(replay.save_action_log(&path), Err(ActionLogError::CustomSet)));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_save_rejects_custom_card_set() {
+        let mut cards = Card::all();
+        cards.swap(0, 1);
+        let game = Game::new(2, Arc::new(cards));
+        let replay = Replay::new(game).finalize_with(GameHistory::new());
+        let path = std::env::temp_dir().join("splendor_test_custom_card_set.json");
+        assert!(matches!(replay.save(&path), Err(ReplayFileError::CustomCardSet)));
+        assert!(matches!(replay.save_action_log(&path), Err(ActionLogError::CustomSet)));
+        assert!(!path.exists());
+    }
+
+    #[tokio::test]
+    async fn test_goto_out_of_range_is_clamped() {
+        let game = Game::new(2, Arc::new(Card::all()));
+        let mut played = game.clone();
+        for _ in 0..4 {
+            played.play_action(Action::ReserveHidden(0));
+            played.play_action(Action::Pass);
+            played.play_action(Action::Continue);
+        }
+        let replay = Replay::new(game).finalize_with(played.history());
+        assert_eq!(replay.num_moves(), 3);
+
+        let arena = ArenaBuilder::new()
+            .binaries(vec!["a".to_string(), "b".to_string()])
+            .build_with_replay(replay);
+        let arena = Arc::new(RwLock::new(arena));
+
+        let position = go_to_move(Move { move_index: 999 }, arena.clone()).await.unwrap();
+        let body = warp::hyper::body::to_bytes(position.into_response().into_body()).await.unwrap();
+        let reply: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(reply["success"]["move_index"], 3);
+        assert_eq!(reply["success"]["total_moves"], 3);
+        assert_eq!(reply["success"]["clamped"], true);
+
+        let replay = arena.read().await.get_replay().unwrap();
+        let position = replay.write().await.go_to_move(2);
+        assert_eq!(position, MovePosition { move_index: 2, total_moves: 3, clamped: false });
+        assert!(replay.write().await.go_to_move(-1).clamped);
+    }
+}
@@ -1,6 +1,7 @@
 use crate::gem::Gem;
 use crate::gems::Gems;
-use serde::{Deserialize, Serialize};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::ops::{Index, IndexMut};
 
 #[derive(PartialEq, Eq, Copy, Clone, Debug, Default, Serialize, Deserialize)]
@@ -88,6 +89,43 @@ pub struct Card {
     tier: u8,
 }
 
+/// Cards are serialized as their id and looked up again when deserialized
+impl Serialize for Card {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.id.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Card {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Card, D::Error> {
+        let id = CardId::deserialize(deserializer)?;
+        Card::from_id(id).ok_or_else(|| D::Error::custom(format!("Unknown card id: {}", id)))
+    }
+}
+
+/// A card with every attribute spelled out instead of just its id,
+/// for clients that do not carry their own copy of the card table
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CardDetails {
+    pub id: CardId,
+    pub tier: u8,
+    pub points: u8,
+    pub cost: Cost,
+    pub gem: Gem,
+}
+
+impl From<Card> for CardDetails {
+    fn from(card: Card) -> Self {
+        CardDetails {
+            id: card.id,
+            tier: card.tier,
+            points: card.points,
+            cost: card.cost,
+            gem: card.gem,
+        }
+    }
+}
+
 impl Card {
     pub fn cost(&self) -> Cost {
         self.cost
@@ -750,4 +788,89 @@ impl Card {
     pub fn all() -> Vec<Card> {
         Card::all_const().to_vec()
     }
+
+    /// Look up a card by its id without needing a card lookup,
+    /// returns None if no card has the given id
+    pub fn from_id(id: CardId) -> Option<Card> {
+        const CARDS: [Card; 90] = Card::all_const();
+        CARDS.get(id as usize).copied()
+    }
+
+    /// All cards that produce the given gem when purchased, in order of id
+    pub fn by_gem(gem: Gem) -> Vec<Card> {
+        Card::all_const()
+            .into_iter()
+            .filter(|card| card.gem() == gem)
+            .collect()
+    }
+
+    /// A fingerprint of a set of cards, equal for two sets only if they hold
+    /// the same cards in the same order. Stable across builds and platforms
+    /// so bots can check which cards the game server plays with
+    pub fn set_hash(cards: &[Card]) -> u64 {
+        // FNV-1a
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for card in cards {
+            let cost = card.cost;
+            let fields = [
+                card.id,
+                card.tier,
+                card.points,
+                card.gem as u8,
+                cost.onyx as u8,
+                cost.sapphire as u8,
+                cost.emerald as u8,
+                cost.ruby as u8,
+                cost.diamond as u8,
+            ];
+            for byte in fields {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+        }
+        hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_id_valid() {
+        for card in Card::all() {
+            assert_eq!(Card::from_id(card.id()), Some(card));
+        }
+        let card = Card::from_id(89).unwrap();
+        assert_eq!(card.tier(), 3);
+        assert_eq!(card.gem(), Gem::Ruby);
+        assert_eq!(card.points(), 5);
+    }
+
+    #[test]
+    fn test_from_id_invalid() {
+        assert_eq!(Card::from_id(90), None);
+        assert_eq!(Card::from_id(CardId::MAX), None);
+    }
+
+    #[test]
+    fn test_by_gem() {
+        let onyx = Card::by_gem(Gem::Onyx);
+        assert_eq!(onyx.len(), 18);
+        assert!(onyx.iter().all(|card| card.gem() == Gem::Onyx));
+        let expected: Vec<Card> = Card::all().into_iter().filter(|c| c.gem() == Gem::Onyx).collect();
+        assert_eq!(onyx, expected);
+        assert!(Card::by_gem(Gem::Gold).is_empty());
+    }
+
+    #[test]
+    fn test_set_hash() {
+        let cards = Card::all();
+        assert_eq!(Card::set_hash(&cards), Card::set_hash(&Card::all()));
+        assert_ne!(Card::set_hash(&cards), Card::set_hash(&cards[1..]));
+
+        let mut swapped = cards.clone();
+        swapped.swap(0, 1);
+        assert_ne!(Card::set_hash(&cards), Card::set_hash(&swapped));
+    }
 }
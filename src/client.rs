@@ -1,13 +1,20 @@
 use crate::*;
 use clap::Parser;
 use std::ops::Deref;
+use std::time::Duration;
 use tungstenite::{connect, stream::MaybeTlsStream, Message};
 use url::Url;
 
 pub type WebSocket = tungstenite::WebSocket<MaybeTlsStream<std::net::TcpStream>>;
 
+/// Where a `Log` sends its messages
+enum LogSink {
+    Socket(Box<WebSocket>),
+    Memory(Vec<String>),
+}
+
 pub struct Log {
-    socket: WebSocket,
+    sink: LogSink,
 }
 
 impl Log {
@@ -15,21 +22,81 @@ impl Log {
         let url = format!("ws://127.0.0.1:{}/log", port);
         let url = Url::parse(&url).unwrap();
         let (socket, _) = connect(url).expect("Can't connect to the game server");
-        Self { socket }
+        Self {
+            sink: LogSink::Socket(Box::new(socket)),
+        }
+    }
+
+    /// A log that keeps its messages instead of sending them to the game server,
+    /// so bots can be tested without a running server
+    pub fn in_memory() -> Self {
+        Self {
+            sink: LogSink::Memory(Vec::new()),
+        }
     }
 
     pub fn send(&mut self, message: &str) {
-        let message = ClientMessage::Log(message.to_string());
-        let message = serde_json::to_string(&message).expect("Error converting message to string");
-        self.socket
-            .send(Message::Text(message))
-            .expect("Error writing message");
+        match &mut self.sink {
+            LogSink::Socket(socket) => {
+                let message = ClientMessage::Log(message.to_string());
+                let message = serde_json::to_string(&message).expect("Error converting message to string");
+                socket
+                    .send(Message::Text(message))
+                    .expect("Error writing message");
+            }
+            LogSink::Memory(messages) => messages.push(message.to_string()),
+        }
     }
+
+    /// The messages sent to an in-memory log, always empty
+    /// for a log connected to the game server
+    pub fn messages(&self) -> &[String] {
+        match &self.sink {
+            LogSink::Socket(_) => &[],
+            LogSink::Memory(messages) => messages,
+        }
+    }
+}
+
+/// Parse a message from the game server, returns None for the public
+/// snapshots that are sent on connect and for `ServerMessage`s,
+/// neither of which ask for an action. Messages that cannot be parsed
+/// are logged and skipped as well
+pub fn parse_server_message(msg: &str) -> Option<ClientInfo> {
+    match serde_json::from_str::<ClientInfo>(msg) {
+        Ok(info) => Some(info),
+        Err(_) if serde_json::from_str::<SmallClientInfo>(msg).is_ok() => None,
+        Err(_) if serde_json::from_str::<ServerMessage>(msg).is_ok() => None,
+        Err(e) => {
+            eprintln!("Unable to parse message from the game server: {}", e);
+            None
+        }
+    }
+}
+
+/// What the game server asks of a bot in `play_game`
+pub enum ServerEvent {
+    /// It is the bot's turn, and it must answer with an action
+    Turn(Box<ClientInfo>),
+    /// The server rejected the bot's last message and
+    /// may have played a fallback move in its place
+    Error(String),
 }
 
 pub trait Runnable<C : From<ClientInfo>, A : Into<Action>> {
-    fn initialize(&mut self, log: &mut Log);
+    /// The name the bot goes by in the server's logs, e.g. `GreedyBot v2`,
+    /// defaults to the name of the bot's type. `--username` takes precedence
+    fn name(&self) -> String {
+        let type_name = std::any::type_name::<Self>();
+        type_name.rsplit("::").next().unwrap_or(type_name).to_string()
+    }
+    /// Called once before the game starts with the settings it is played with
+    fn initialize(&mut self, config: &GameConfig, log: &mut Log);
     fn take_action(&mut self, info: C, log: &mut Log) -> A;
+    /// Called when the server rejects a message from the bot, such as an illegal action
+    fn on_error(&mut self, error: String, log: &mut Log) {
+        eprintln!("Error from the game server: {}", error);
+    }
     fn game_over(&self, info: C, results: GameResults) {
         todo!()
     }
@@ -40,6 +107,155 @@ pub struct Args {
     /// The port to connect to
     #[arg(short, long)]
     port: u16,
+    /// The name to display for this bot
+    #[arg(short, long)]
+    username: Option<String>,
+    /// How many times to try (re)connecting to the game server before giving up
+    #[arg(long, default_value_t = 5)]
+    max_attempts: u32,
+}
+
+/// How often and how patiently a bot retries a connection to the game server
+#[derive(Debug, Clone, Copy)]
+pub struct Backoff {
+    pub max_attempts: u32,
+    pub initial_delay: Duration,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            max_attempts: 5,
+            initial_delay: Duration::from_millis(100),
+        }
+    }
+}
+
+impl Backoff {
+    /// The delay doubles after every failed attempt
+    fn delay(&self, attempt: u32) -> Duration {
+        self.initial_delay * 2u32.saturating_pow(attempt)
+    }
+}
+
+/// Connect to the game server, retrying with exponential backoff
+/// on transient failures
+pub fn connect_with_retry(url: &Url, backoff: Backoff) -> Result<WebSocket, Box<tungstenite::Error>> {
+    let mut attempt = 0;
+    loop {
+        match connect(url.as_str()) {
+            Ok((socket, _)) => return Ok(socket),
+            Err(e) if attempt + 1 >= backoff.max_attempts => return Err(Box::new(e)),
+            Err(e) => {
+                println!("Unable to connect to the game server ({}), retrying...", e);
+                std::thread::sleep(backoff.delay(attempt));
+                attempt += 1;
+            }
+        }
+    }
+}
+
+/// The url of the game endpoint, announcing the protocol version of this crate
+/// and the bot's username if given
+pub fn game_url(port: u16, username: Option<&str>) -> Url {
+    let mut url = Url::parse(&format!("ws://127.0.0.1:{}/game", port)).unwrap();
+    url.query_pairs_mut()
+        .append_pair("version", &constants::PROTOCOL_VERSION.to_string());
+    if let Some(username) = username {
+        url.query_pairs_mut().append_pair("username", username);
+    }
+    url
+}
+
+/// The url to reconnect with after a dropped connection, which asks the server
+/// for the seats named in the connection's `GameConfig` back
+pub fn rejoin_url(url: &Url, config: &GameConfig) -> Url {
+    let mut url = url.clone();
+    if let Some(seat) = config.seats.first() {
+        url.query_pairs_mut().append_pair("seat", &seat.to_string());
+    }
+    url
+}
+
+/// Read the `GameConfig` the server sends as its first message on a new
+/// connection, returns the server's error if it rejected the connection instead
+pub fn read_game_config(game_socket: &mut WebSocket) -> Result<GameConfig, String> {
+    let msg = game_socket.read().map_err(|e| e.to_string())?;
+    let msg = msg.to_text().map_err(|e| e.to_string())?;
+    match serde_json::from_str::<ServerMessage>(msg) {
+        Ok(ServerMessage::Config(config)) => Ok(config),
+        Ok(ServerMessage::Error(error)) => Err(error),
+        Err(e) => Err(format!("Expected the game config first: {}", e)),
+    }
+}
+
+/// Let the server know the bot is ready to start the game
+fn send_ready(game_socket: &mut WebSocket) -> bool {
+    let ready = serde_json::to_string(&ClientMessage::Ready).expect("Error converting message to string");
+    game_socket.send(Message::Text(ready)).is_ok()
+}
+
+/// Play the game over an open connection until the server closes it,
+/// passing every `ServerEvent` to `handle`, which returns the action to play
+/// for `ServerEvent::Turn`. Nothing is sent for a turn `handle` has no action
+/// for, the server plays for the bot once its time runs out.
+///
+/// If the connection drops unexpectedly the bot reconnects to `url`, which
+/// should name its seat (see `rejoin_url`), and signals it is ready again,
+/// giving up once `backoff.max_attempts` reconnections in a row have failed
+pub fn play_game(
+    mut game_socket: WebSocket,
+    url: &Url,
+    backoff: Backoff,
+    mut handle: impl FnMut(ServerEvent) -> Option<Action>,
+) {
+    if !send_ready(&mut game_socket) {
+        panic!("Error sending ready message");
+    }
+
+    loop {
+        let msg = match game_socket.read() {
+            Ok(Message::Close(_)) => break,
+            Ok(msg) => msg,
+            Err(tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed) => break,
+            Err(e) => {
+                println!("Lost connection to the game server ({}), reconnecting...", e);
+                match connect_with_retry(url, backoff) {
+                    Ok(socket) => game_socket = socket,
+                    Err(_) => break,
+                }
+                if !send_ready(&mut game_socket) {
+                    break;
+                }
+                continue;
+            }
+        };
+        let msg = match msg.to_text() {
+            Ok(msg) => msg,
+            Err(e) => {
+                eprintln!("Unable to read message from the game server: {}", e);
+                continue;
+            }
+        };
+        if let Ok(ServerMessage::Error(error)) = serde_json::from_str(msg) {
+            handle(ServerEvent::Error(error));
+            continue;
+        }
+        let info = match parse_server_message(msg) {
+            Some(info) => info,
+            None => continue,
+        };
+        let request_id = info.request_id;
+        let action = match handle(ServerEvent::Turn(Box::new(info))) {
+            Some(action) => action,
+            None => continue,
+        };
+        let msg = ClientMessage::Action { action, request_id };
+
+        let msg_str = serde_json::to_string(&msg).expect("Error converting action to string");
+        // A failed send shows up as an error on the next read
+        let _ = game_socket.send(Message::Text(msg_str));
+    }
 }
 
 /// The protocol for communication and running the bot between the client and
@@ -47,40 +263,340 @@ pub struct Args {
 pub fn run_bot<C : From<ClientInfo>, A : Into<Action>, B: Runnable<C, A> + Default>() {
     let args = Args::parse();
     let port = args.port;
+    let backoff = Backoff {
+        max_attempts: args.max_attempts,
+        ..Backoff::default()
+    };
 
-    let url = format!("ws://127.0.0.1:{}/game", port);
-    let url = Url::parse(&url).unwrap();
-    let (mut game_socket, _) = connect(url).expect("Can't connect to the game server");
-
+    let mut bot = B::default();
+    let username = args.username.unwrap_or_else(|| bot.name());
+    let url = game_url(port, Some(&username));
+    let mut game_socket = connect_with_retry(&url, backoff).expect("Can't connect to the game server");
+    let config = read_game_config(&mut game_socket)
+        .unwrap_or_else(|e| panic!("The game server did not send its config: {}", e));
 
     // Give the server a chance to start up
     std::thread::sleep(std::time::Duration::from_millis(100));
 
     let mut log = Log::new(port);
 
-    let mut bot = B::default();
-    bot.initialize(&mut log);
+    bot.initialize(&config, &mut log);
+
     println!("Connected to the game server...");
+    play_game(game_socket, &rejoin_url(&url, &config), backoff, |event| match event {
+        ServerEvent::Turn(info) => {
+            let info : C = C::from(*info);
+            Some(bot.take_action(info, &mut log).into())
+        }
+        ServerEvent::Error(error) => {
+            bot.on_error(error, &mut log);
+            None
+        }
+    });
+}
 
-    loop {
-        let msg = game_socket.read();
-        let msg = match msg {
-            Ok(msg) => msg,
-            Err(e) => {
-                break;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::card::Card;
+    use std::net::TcpListener;
+    use std::sync::Arc;
+
+    /// Read messages until the client signals that it is ready
+    fn wait_for_ready(socket: &mut tungstenite::WebSocket<std::net::TcpStream>) {
+        loop {
+            let msg = socket.read().unwrap();
+            let msg: ClientMessage = serde_json::from_str(msg.to_text().unwrap()).unwrap();
+            if matches!(msg, ClientMessage::Ready) {
+                return;
             }
+        }
+    }
+
+    #[test]
+    fn test_reconnects_after_dropped_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            // The first connection is dropped without a closing handshake
+            let (stream, _) = listener.accept().unwrap();
+            let mut socket = tungstenite::accept(stream).unwrap();
+            wait_for_ready(&mut socket);
+            drop(socket);
+
+            // The bot comes back, plays a move and the game ends cleanly
+            let (stream, _) = listener.accept().unwrap();
+            let mut socket = tungstenite::accept(stream).unwrap();
+            wait_for_ready(&mut socket);
+            let game = Game::new(2, Arc::new(Card::all()));
+            let info = ClientInfo::from_game(&game, String::new());
+            let expected = info.legal_actions[0].clone();
+            socket
+                .send(Message::Text(serde_json::to_string(&info).unwrap()))
+                .unwrap();
+            let msg = socket.read().unwrap();
+            let msg: ClientMessage = serde_json::from_str(msg.to_text().unwrap()).unwrap();
+            socket.close(None).unwrap();
+            while socket.read().is_ok() {}
+            (msg, expected)
+        });
+
+        let url = Url::parse(&format!("ws://127.0.0.1:{}/game", port)).unwrap();
+        let backoff = Backoff {
+            max_attempts: 3,
+            initial_delay: Duration::from_millis(10),
         };
-        let msg = msg.to_text().expect("Error converting message to text");
-        let info: ClientInfo = serde_json::from_str(msg).expect("Error parsing message");
-        let info : C = C::from(info);
-        let action = bot.take_action(info, &mut log);
-        let action = action.into();
-        let msg = ClientMessage::Action(action);
+        let game_socket = connect_with_retry(&url, backoff).unwrap();
+        let mut turns = 0;
+        play_game(game_socket, &url, backoff, |event| match event {
+            ServerEvent::Turn(info) => {
+                turns += 1;
+                Some(info.legal_actions[0].clone())
+            }
+            ServerEvent::Error(error) => panic!("Unexpected error: {}", error),
+        });
 
-        let msg_str = serde_json::to_string(&msg).expect("Error converting action to string");
-        let game_socket_result = game_socket.send(Message::Text(msg_str));
-        if let Err(_) = game_socket_result {
-            break;
+        let (msg, expected) = server.join().unwrap();
+        assert_eq!(turns, 1);
+        assert!(matches!(msg, ClientMessage::Action { action, .. } if action == expected));
+    }
+
+    /// Read messages until the server asks for a move
+    fn next_turn(socket: &mut WebSocket) -> ClientInfo {
+        loop {
+            let msg = socket.read().unwrap();
+            if let Some(info) = parse_server_message(msg.to_text().unwrap()) {
+                return info;
+            }
         }
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_reconnected_bot_gets_its_seat_back() {
+        let port = 3179;
+        let arena = ArenaBuilder::new()
+            .binaries(vec!["missing_a".to_string(), "missing_b".to_string()])
+            .port(port)
+            .build();
+        let (handle, shutdown) = arena.spawn();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let backoff = Backoff {
+            max_attempts: 2,
+            initial_delay: Duration::from_millis(10),
+        };
+        let url = game_url(port, None);
+        let first_url = url.clone();
+        let (mut socket, config) = tokio::task::spawn_blocking(move || {
+            let mut socket = connect_with_retry(&first_url, backoff).unwrap();
+            let config = read_game_config(&mut socket).unwrap();
+            assert!(send_ready(&mut socket));
+            (socket, config)
+        })
+        .await
+        .unwrap();
+        assert_eq!(config.seats, vec![0]);
+
+        // The opponent plays every move it is asked for
+        std::thread::spawn(move || {
+            let url = game_url(port, None);
+            let mut socket = connect_with_retry(&url, backoff).unwrap();
+            let config = read_game_config(&mut socket).unwrap();
+            play_game(socket, &rejoin_url(&url, &config), backoff, |event| match event {
+                ServerEvent::Turn(info) => Some(info.legal_actions[0].clone()),
+                ServerEvent::Error(_) => None,
+            });
+        });
+
+        let (dropped, resent, next) = tokio::task::spawn_blocking(move || {
+            // The bot crashes as soon as it is asked for its first move
+            let dropped = next_turn(&mut socket);
+            drop(socket);
+
+            let mut socket = connect_with_retry(&rejoin_url(&url, &config), backoff).unwrap();
+            assert_eq!(read_game_config(&mut socket).unwrap().seats, vec![0]);
+            assert!(send_ready(&mut socket));
+            let resent = next_turn(&mut socket);
+            let action = ClientMessage::Action {
+                action: resent.legal_actions[0].clone(),
+                request_id: resent.request_id,
+            };
+            socket
+                .send(Message::Text(serde_json::to_string(&action).unwrap()))
+                .unwrap();
+            (dropped, resent, next_turn(&mut socket))
+        })
+        .await
+        .unwrap();
+
+        // The move is asked for again on the new connection, and the seat
+        // keeps being played from it once the opponent has moved
+        assert_eq!(resent.current_player_num, 0);
+        assert_eq!(resent.request_id, dropped.request_id);
+        assert_eq!(next.current_player_num, 0);
+        assert!(next.history.num_actions() > resent.history.num_actions());
+
+        shutdown.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), handle).await.unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_garbage_from_server_is_skipped() {
+        assert!(parse_server_message("not json").is_none());
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut socket = tungstenite::accept(stream).unwrap();
+            wait_for_ready(&mut socket);
+            socket.send(Message::Text("{\"garbage\": true".to_string())).unwrap();
+            socket.send(Message::Binary(vec![0xff, 0xfe])).unwrap();
+
+            // The bot is still around to take its turn
+            let game = Game::new(2, Arc::new(Card::all()));
+            let info = ClientInfo::from_game(&game, String::new());
+            socket
+                .send(Message::Text(serde_json::to_string(&info).unwrap()))
+                .unwrap();
+            let msg = socket.read().unwrap();
+            let msg: ClientMessage = serde_json::from_str(msg.to_text().unwrap()).unwrap();
+            socket.close(None).unwrap();
+            while socket.read().is_ok() {}
+            msg
+        });
+
+        let url = Url::parse(&format!("ws://127.0.0.1:{}/game", port)).unwrap();
+        let game_socket = connect_with_retry(&url, Backoff::default()).unwrap();
+        let mut turns = 0;
+        play_game(game_socket, &url, Backoff::default(), |event| match event {
+            ServerEvent::Turn(info) => {
+                turns += 1;
+                Some(info.legal_actions[0].clone())
+            }
+            ServerEvent::Error(error) => panic!("Unexpected error: {}", error),
+        });
+
+        assert_eq!(turns, 1);
+        assert!(matches!(server.join().unwrap(), ClientMessage::Action { .. }));
+    }
+
+    #[derive(Default)]
+    struct GreedyBot;
+
+    impl Runnable<ClientInfo, Action> for GreedyBot {
+        fn initialize(&mut self, _config: &GameConfig, _log: &mut Log) {}
+        fn take_action(&mut self, info: ClientInfo, _log: &mut Log) -> Action {
+            info.legal_actions[0].clone()
+        }
+    }
+
+    #[derive(Default)]
+    struct NamedBot;
+
+    impl Runnable<ClientInfo, Action> for NamedBot {
+        fn name(&self) -> String {
+            "GreedyBot v2".to_string()
+        }
+        fn initialize(&mut self, _config: &GameConfig, _log: &mut Log) {}
+        fn take_action(&mut self, info: ClientInfo, _log: &mut Log) -> Action {
+            info.legal_actions[0].clone()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_bot_name_labels_server_logs() {
+        assert_eq!(GreedyBot.name(), "GreedyBot");
+
+        // The name is sent as the username when connecting, which the server
+        // then uses to label the bot's logs
+        let url = game_url(3030, Some(&NamedBot.name()));
+        let params = warp::test::request()
+            .path(&format!("/game?{}", url.query().unwrap()))
+            .filter(&warp::query::<ConnectParams>())
+            .await
+            .unwrap();
+        assert_eq!(params.version, Some(constants::PROTOCOL_VERSION));
+
+        let mut arena = ArenaBuilder::new()
+            .binaries(vec!["a".to_string(), "b".to_string()])
+            .build();
+        let seat = arena.take_seat().unwrap();
+        assert_eq!(arena.log_label(seat), "0");
+        arena.set_username(seat, &params.username.unwrap());
+        assert_eq!(arena.log_label(seat), "GreedyBot v2");
+    }
+
+    #[derive(Default)]
+    struct ChattyBot;
+
+    impl Runnable<ClientInfo, Action> for ChattyBot {
+        fn initialize(&mut self, _config: &GameConfig, log: &mut Log) {
+            log.send("Ready to play");
+        }
+        fn take_action(&mut self, info: ClientInfo, log: &mut Log) -> Action {
+            log.send(&format!("Choosing from {} actions", info.legal_actions.len()));
+            info.legal_actions[0].clone()
+        }
+    }
+
+    #[test]
+    fn test_in_memory_log_captures_bot_logs() {
+        let mut log = Log::in_memory();
+        let mut bot = ChattyBot;
+        let config = ArenaBuilder::new()
+            .binaries(vec!["a".to_string(), "b".to_string()])
+            .build()
+            .game_config();
+        bot.initialize(&config, &mut log);
+
+        let game = Game::new(2, Arc::new(Card::all()));
+        let info = ClientInfo::from_game(&game, String::new());
+        let expected = info.legal_actions[0].clone();
+        assert_eq!(bot.take_action(info, &mut log), expected);
+        assert_eq!(log.messages(), ["Ready to play", "Choosing from 30 actions"]);
+    }
+
+    #[derive(Default)]
+    struct PlannerBot {
+        opponents: usize,
+    }
+
+    impl Runnable<ClientInfo, Action> for PlannerBot {
+        fn initialize(&mut self, config: &GameConfig, _log: &mut Log) {
+            self.opponents = config.num_players - 1;
+        }
+        fn take_action(&mut self, info: ClientInfo, _log: &mut Log) -> Action {
+            info.legal_actions[0].clone()
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_bot_reads_config_on_initialize() {
+        let port = 3174;
+        let arena = ArenaBuilder::new()
+            .binaries(vec!["missing_a".to_string(), "missing_b".to_string(), "missing_c".to_string()])
+            .port(port)
+            .build();
+        let (handle, shutdown) = arena.spawn();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let config = tokio::task::spawn_blocking(move || {
+            let url = game_url(port, None);
+            let mut game_socket = connect_with_retry(&url, Backoff::default()).unwrap();
+            read_game_config(&mut game_socket).unwrap()
+        })
+        .await
+        .unwrap();
+        assert_eq!(config.num_players, 3);
+        assert_eq!(config.win_threshold, WIN_THRESHOLD);
+        assert_eq!(config.card_set_hash, Card::set_hash(&Card::all()));
+
+        let mut bot = PlannerBot::default();
+        bot.initialize(&config, &mut Log::in_memory());
+        assert_eq!(bot.opponents, 2);
+
+        shutdown.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), handle).await.unwrap().unwrap();
+    }
 }
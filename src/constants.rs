@@ -1 +1,5 @@
 pub const STOURNEY_WEBSOCKET_URL: &str = "wss://api.stourney.com/ws";
+
+/// The version of the messages exchanged between the game server and its clients,
+/// bumped whenever their shape changes. Clients send it when connecting
+pub const PROTOCOL_VERSION: u32 = 4;
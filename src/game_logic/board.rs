@@ -1,5 +1,7 @@
 use super::*;
+use crate::card::Card;
 use serde::{Deserialize, Serialize};
+use std::ops::Deref;
 
 /// Contains public board information that all players have
 /// access to such as card counts, nobles available, and gems available in
@@ -11,6 +13,9 @@ pub struct Board {
     pub available_cards: Vec<Vec<CardId>>,
     pub nobles: Vec<NobleId>,
     pub gems: Gems,
+    /// The face up cards by tier and slot, see `slot_layout`
+    #[serde(default)]
+    pub slots: Vec<Vec<Option<CardId>>>,
 }
 
 impl Board {
@@ -20,6 +25,155 @@ impl Board {
             available_cards: game.cards(),
             nobles: game.nobles().iter().map(|n| n.id()).collect(),
             gems: game.bank().clone(),
+            slots: game.slot_layout(),
         }
     }
+
+    /// The face up cards by tier in the slots they were dealt to, with None
+    /// for a slot left empty once its deck ran out. Boards sent without slots
+    /// are laid out from the start of each tier
+    pub fn slot_layout(&self) -> Vec<Vec<Option<CardId>>> {
+        if !self.slots.is_empty() {
+            return self.slots.clone();
+        }
+        self.available_cards
+            .iter()
+            .map(|tier| tier.iter().copied().map(Some).collect())
+            .collect()
+    }
+
+    /// The face up cards that produce the given gem when purchased, from tier 1 to 3
+    pub fn face_up_producing(&self, gem: Gem) -> Vec<CardId> {
+        self.available_cards
+            .iter()
+            .flatten()
+            .copied()
+            .filter(|&id| Card::from_id(id).is_some_and(|card| card.gem() == gem))
+            .collect()
+    }
+
+    /// The number of cards still on the board, face up or in the decks
+    pub fn total_cards_accounted(&self) -> usize {
+        let face_up: usize = self.available_cards.iter().map(|tier| tier.len()).sum();
+        let in_decks: usize = self.deck_counts.iter().sum();
+        face_up + in_decks
+    }
+
+    /// Check that the face up cards and the deck counts agree,
+    /// each tier shows at most 4 cards (and only fewer if its deck is empty)
+    /// and never holds more cards than exist in that tier
+    pub fn validate(&self) -> bool {
+        let all_cards = Card::all();
+        (0..3).all(|tier| {
+            let face_up = self.available_cards[tier].len();
+            let in_deck = self.deck_counts[tier];
+            let tier_size = all_cards
+                .iter()
+                .filter(|card| card.tier() as usize == tier + 1)
+                .count();
+
+            face_up <= 4 && (face_up == 4 || in_deck == 0) && face_up + in_deck <= tier_size
+        })
+    }
+}
+
+/// The public board together with whose turn it is and the phase of that turn,
+/// so a single object fully describes the visible state of the game.
+/// Serialized as the board's fields plus `current_player_num` and `phase`,
+/// both of which default when missing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BoardSnapshot {
+    #[serde(flatten)]
+    pub board: Board,
+    #[serde(default)]
+    pub current_player_num: usize,
+    #[serde(default)]
+    pub phase: Phase,
+}
+
+impl BoardSnapshot {
+    pub fn from_game(game: &Game) -> Self {
+        BoardSnapshot {
+            board: Board::from_game(game),
+            current_player_num: game.current_player_num(),
+            phase: game.phase(),
+        }
+    }
+}
+
+impl Deref for BoardSnapshot {
+    type Target = Board;
+
+    fn deref(&self) -> &Board {
+        &self.board
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_fresh_board_accounts_for_all_cards() {
+        let game = Game::new(2, Arc::new(Card::all()));
+        let board = Board::from_game(&game);
+        assert_eq!(board.available_cards.iter().flatten().count(), 12);
+        assert_eq!(board.total_cards_accounted(), 90);
+        assert!(board.validate());
+    }
+
+    #[test]
+    fn test_face_up_producing() {
+        let game = Game::new(2, Arc::new(Card::all()));
+        let board = Board::from_game(&game);
+        let mut total = 0;
+        for gem in Gem::all_expect_gold() {
+            let producing = board.face_up_producing(gem);
+            assert!(producing.iter().all(|&id| Card::from_id(id).unwrap().gem() == gem));
+            total += producing.len();
+        }
+        assert_eq!(total, 12);
+        assert!(board.face_up_producing(Gem::Gold).is_empty());
+    }
+
+    #[test]
+    fn test_validate_catches_desync() {
+        let game = Game::new(2, Arc::new(Card::all()));
+        let mut board = Board::from_game(&game);
+        board.available_cards[0].pop();
+        assert!(!board.validate());
+
+        let mut board = Board::from_game(&game);
+        board.deck_counts[2] += 1;
+        assert!(!board.validate());
+    }
+
+    #[test]
+    fn test_snapshot_follows_current_player() {
+        let mut game = Game::new(2, Arc::new(Card::all()));
+        let snapshot = BoardSnapshot::from_game(&game);
+        assert_eq!(snapshot.current_player_num, 0);
+        assert_eq!(snapshot.phase, Phase::PlayerStart);
+
+        game.play_action(Action::ReserveHidden(0));
+        game.play_action(Action::Pass);
+        let snapshot = BoardSnapshot::from_game(&game);
+        assert_eq!(snapshot.current_player_num, 0);
+        assert_eq!(snapshot.phase, Phase::PlayerActionEnd);
+
+        game.play_action(Action::Continue);
+        let snapshot = BoardSnapshot::from_game(&game);
+        assert_eq!(snapshot.current_player_num, 1);
+        assert_eq!(snapshot.phase, Phase::PlayerStart);
+        assert_eq!(snapshot.deck_counts, Board::from_game(&game).deck_counts);
+
+        // The board's fields sit next to the turn information when serialized
+        let json = serde_json::to_value(&snapshot).unwrap();
+        assert_eq!(json["current_player_num"], 1);
+        assert!(json.get("deck_counts").is_some());
+        let board_only = serde_json::to_string(&Board::from_game(&game)).unwrap();
+        let parsed: BoardSnapshot = serde_json::from_str(&board_only).unwrap();
+        assert_eq!(parsed.current_player_num, 0);
+    }
 }
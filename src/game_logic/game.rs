@@ -1,11 +1,12 @@
 use crate::card::{Card, CardId};
 use crate::gem::Gem;
 use crate::nobles::*;
-use crate::player::Player;
+use crate::player::{Player, PlayerPublicInfo};
 use crate::gems::Gems;
 
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
-use rand::thread_rng;
+use rand::{thread_rng, Rng, SeedableRng};
 
 use super::{Action::*, *};
 
@@ -13,21 +14,81 @@ use std::collections::HashSet;
 use std::sync::Arc;
 
 use cached::proc_macro::cached;
+use derive_more::{Display, Error};
+use serde::{Deserialize, Serialize};
 
 use log::{debug, error, info, trace};
 
-#[derive(Debug, Clone)]
+/// The number of points a player needs to trigger the end of the game
+pub const WIN_THRESHOLD: u8 = 15;
+
+/// All ways to take num_chosen distinct gems from a bank where
+/// `available` has a 1 for each color with gems remaining
+///
+/// The combinations only depend on which colors are present,
+/// so there are few enough keys to cache them all
+#[cached]
+fn distinct_gem_choices(available: Gems, num_chosen: u32) -> Vec<Gems> {
+    choose_distinct_gems(&mut available.clone(), &mut Gems::empty(), num_chosen)
+        .into_iter()
+        .collect()
+}
+
+fn default_card_lookup() -> Arc<Vec<Card>> {
+    Arc::new(Card::all())
+}
+
+#[derive(Debug, Display, Error)]
+pub enum SetupError {
+    #[display(fmt = "Cannot set up the board after actions have been played")]
+    GameInProgress,
+    #[display(fmt = "Expected face up cards for exactly 3 tiers")]
+    WrongNumberOfTiers,
+    #[display(fmt = "Card ids must be from 0-89")]
+    UnknownCard,
+    #[display(fmt = "Card placed in a tier it does not belong to")]
+    WrongTier,
+    #[display(fmt = "Each tier has at most 4 face up cards")]
+    TooManyCards,
+    #[display(fmt = "The same card cannot be placed twice")]
+    DuplicateCard,
+    #[display(fmt = "Unknown noble id")]
+    UnknownNoble,
+    #[display(fmt = "The same noble cannot be placed twice")]
+    DuplicateNoble,
+    #[display(fmt = "There can be at most one more noble than there are players")]
+    TooManyNobles,
+    #[display(fmt = "No player with that number is in the game")]
+    UnknownPlayer,
+    #[display(fmt = "A player cannot hold a negative number of gems")]
+    InvalidGems,
+    #[display(fmt = "Developments cannot be gold or negative")]
+    InvalidDevelopments,
+    #[display(fmt = "The bank does not hold enough gems to give to the player")]
+    NotEnoughGems,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Game {
     players: Vec<Player>,
     bank: Gems,
     decks: Vec<Vec<Card>>,
     current_player: usize,
+    /// The player who took the first turn, every round starts with them
+    #[serde(default)]
+    first_player: usize,
     nobles: Vec<Noble>,
-    dealt_cards: Vec<Vec<CardId>>,
+    /// The face up cards by tier and slot, a slot stays empty
+    /// once the tier's deck is exhausted
+    dealt_cards: Vec<Vec<Option<CardId>>>,
     current_phase: Phase,
+    #[serde(skip, default = "default_card_lookup")]
     card_lookup: Arc<Vec<Card>>,
     history: GameHistory,
     deadlock_count: u8,
+    /// Passes in a row that end the game, 2 per player unless set
+    #[serde(default)]
+    deadlock_threshold: Option<u8>,
 }
 
 impl Game {
@@ -46,16 +107,19 @@ impl Game {
         self.decks[0].extend(
             self.dealt_cards[0]
                 .drain(..)
+                .flatten()
                 .map(|id| self.card_lookup[id as usize]),
         );
         self.decks[1].extend(
             self.dealt_cards[1]
                 .drain(..)
+                .flatten()
                 .map(|id| self.card_lookup[id as usize]),
         );
         self.decks[2].extend(
             self.dealt_cards[2]
                 .drain(..)
+                .flatten()
                 .map(|id| self.card_lookup[id as usize]),
         );
         // Filter out the initial cards from the decks
@@ -63,9 +127,9 @@ impl Game {
         self.decks[1].retain(|card| !initial_cards[1].contains(card));
         self.decks[2].retain(|card| !initial_cards[2].contains(card));
 
-        self.dealt_cards[0] = initial_cards[0].iter().map(|card| card.id()).collect();
-        self.dealt_cards[1] = initial_cards[1].iter().map(|card| card.id()).collect();
-        self.dealt_cards[2] = initial_cards[2].iter().map(|card| card.id()).collect();
+        self.dealt_cards[0] = initial_cards[0].iter().map(|card| Some(card.id())).collect();
+        self.dealt_cards[1] = initial_cards[1].iter().map(|card| Some(card.id())).collect();
+        self.dealt_cards[2] = initial_cards[2].iter().map(|card| Some(card.id())).collect();
     }
 
     /// Get the number of cards in each deck from tier 1 to 3
@@ -86,41 +150,295 @@ impl Game {
     /// Get the cards that have been dealt to the board
     /// and are face up
     pub fn cards(&self) -> Vec<Vec<CardId>> {
+        self.dealt_cards
+            .iter()
+            .map(|slots| slots.iter().flatten().copied().collect())
+            .collect()
+    }
+
+    /// Get the face up cards by tier in the slots they were dealt to,
+    /// with None for slots left empty by an exhausted deck
+    pub fn slot_layout(&self) -> Vec<Vec<Option<CardId>>> {
         self.dealt_cards.clone()
     }
 
+    /// Every face up card, from tier 1 to 3
+    fn face_up(&self) -> impl Iterator<Item = &CardId> {
+        self.dealt_cards.iter().flatten().flatten()
+    }
+
     /// Get the gems that are currently available for taking
     pub fn bank(&self) -> &Gems {
         &self.bank
     }
 
+    /// Get what the bank would hold after the given action, without playing it.
+    /// None if the action is not legal right now or never moves tokens to or
+    /// from the bank
+    pub fn bank_after(&self, action: &Action) -> Option<Gems> {
+        let legal = self.get_legal_actions()?;
+        if !legal.contains(action) {
+            return None;
+        }
+        let gold = Gems::one(Gem::Gold);
+        match action {
+            TakeDouble(color) => Some(self.bank - Gems::one(*color) - Gems::one(*color)),
+            TakeDistinct(colors) => Some(self.bank - Gems::from_set(colors)),
+            Reserve(_) | ReserveHidden(_) if self.bank[Gem::Gold] > 0 => Some(self.bank - gold),
+            Reserve(_) | ReserveHidden(_) => Some(self.bank),
+            Purchase { payment, .. } => Some(self.bank + *payment),
+            Discard(discards) => Some(self.bank + *discards),
+            AttractNoble(_) | Continue | Pass => None,
+        }
+    }
+
+    /// Get the gems in the bank plus the gems held by every player,
+    /// which always equals `expected_gems`
+    pub fn gems_in_play(&self) -> Gems {
+        self.players
+            .iter()
+            .map(|p| *p.gems())
+            .fold(self.bank, |a, b| a + b)
+    }
+
+    /// Get the gems the game started with, tokens are only ever moved
+    /// between the bank and the players
+    pub fn expected_gems(&self) -> Gems {
+        Gems::start(self.players.len() as u8)
+    }
+
     /// Get the nobles that are currently available
     pub fn nobles(&self) -> &Vec<Noble> {
         &self.nobles
     }
 
+    /// The number of passes in a row after which the game ends in a deadlock
+    pub fn deadlock_threshold(&self) -> u8 {
+        self.deadlock_threshold.unwrap_or(2 * self.players.len() as u8)
+    }
+
+    /// End the game after `threshold` passes in a row instead of the usual 2 per player
+    pub fn set_deadlock_threshold(&mut self, threshold: u8) {
+        self.deadlock_threshold = Some(threshold);
+    }
+
+    /// The number of nobles still available to be attracted
+    pub fn noble_count(&self) -> usize {
+        self.nobles.len()
+    }
+
     /// Get the players in the game
     pub fn players(&self) -> &Vec<Player> {
         &self.players
     }
 
+    /// Give a player of a game that has not started yet the given gems,
+    /// developments and points, e.g. to set up a puzzle. The gems are taken
+    /// from (or returned to) the bank, so the bank must be able to cover them
+    pub fn setup_player(
+        &mut self,
+        index: usize,
+        gems: Gems,
+        developments: Gems,
+        points: u8,
+    ) -> Result<(), SetupError> {
+        if self.history.num_actions() > 0 {
+            return Err(SetupError::GameInProgress);
+        }
+        let player = self.players.get(index).ok_or(SetupError::UnknownPlayer)?;
+        if !gems.legal() {
+            return Err(SetupError::InvalidGems);
+        }
+        if !developments.legal() || developments[Gem::Gold] > 0 {
+            return Err(SetupError::InvalidDevelopments);
+        }
+        let bank = (self.bank + *player.gems())
+            .checked_sub(&gems)
+            .ok_or(SetupError::NotEnoughGems)?;
+
+        self.bank = bank;
+        self.players[index].set_state(gems, developments, points);
+        debug_assert_eq!(self.gems_in_play(), self.expected_gems());
+        Ok(())
+    }
+
+    /// The developments of each player, in seat order
+    pub fn developments_summary(&self) -> Vec<Gems> {
+        self.players.iter().map(|p| *p.developments()).collect()
+    }
+
     /// Get the index of the current player
     pub fn current_player_num(&self) -> usize {
         self.current_player
     }
 
+    /// Get the index of the player who took the first turn
+    pub fn first_player(&self) -> usize {
+        self.first_player
+    }
+
+    /// Give the first turn to another player in a game that has not started yet,
+    /// every round then starts with that player
+    pub fn set_first_player(&mut self, player_num: usize) -> Result<(), SetupError> {
+        if self.history.num_actions() > 0 {
+            return Err(SetupError::GameInProgress);
+        }
+        if player_num >= self.players.len() {
+            return Err(SetupError::UnknownPlayer);
+        }
+        self.first_player = player_num;
+        self.current_player = player_num;
+        Ok(())
+    }
+
+    /// Get the phase of the current player's turn
+    pub fn phase(&self) -> Phase {
+        self.current_phase
+    }
+
     /// Get the Player object of the current player
     pub fn current_player(&self) -> Player {
         self.players[self.current_player].clone()
     }
 
+    /// Get the number of points the given player still needs
+    /// to reach the winning threshold
+    pub fn points_to_win(&self, player_num: usize) -> u8 {
+        WIN_THRESHOLD.saturating_sub(self.players[player_num].total_points())
+    }
+
+    /// Get the index of the player closest to winning, ties are broken
+    /// by the fewest developments and then by turn order
+    pub fn closest_to_winning(&self) -> usize {
+        let mut leader = 0;
+        for (i, player) in self.players.iter().enumerate() {
+            let best = &self.players[leader];
+            if player.total_points() > best.total_points()
+                || (player.total_points() == best.total_points()
+                    && player.developments().total() < best.developments().total())
+            {
+                leader = i;
+            }
+        }
+        leader
+    }
+
     pub fn history(&self) -> GameHistory {
         self.history.clone()
     }
 
+    /// The number of actions played so far, without cloning the history
+    pub fn num_actions(&self) -> usize {
+        self.history.num_actions()
+    }
+
+    /// The number of turns that have been completed by any player,
+    /// a turn ends when it is passed to the next player
+    pub fn turn_number(&self) -> u32 {
+        self.history
+            .history
+            .iter()
+            .filter(|(_, action)| *action == Action::Continue)
+            .count() as u32
+    }
+
+    /// The number of full rounds completed, where a round is
+    /// a turn taken by every player
+    pub fn round_number(&self) -> u32 {
+        self.turn_number() / self.players.len() as u32
+    }
+
     /// Initialize a new game with the given number of players 
     /// and a global array of cards where indices are card ids
     pub fn new(players: u8, card_lookup: Arc<Vec<Card>>) -> Game {
+        Game::from_rng(players, card_lookup, &mut thread_rng())
+    }
+
+    /// Initialize a new game where the nobles and the order of every deck
+    /// are fully determined by the given seed
+    pub fn new_seeded(players: u8, card_lookup: Arc<Vec<Card>>, seed: u64) -> Game {
+        Game::from_rng(players, card_lookup, &mut StdRng::seed_from_u64(seed))
+    }
+
+    /// Replace the face up cards (listed by tier) and the nobles of a game
+    /// that has not started yet, the decks are left with the remaining cards
+    pub fn setup_board(
+        &mut self,
+        initial_cards: Vec<Vec<CardId>>,
+        nobles: Vec<NobleId>,
+    ) -> Result<(), SetupError> {
+        if self.history.num_actions() > 0 {
+            return Err(SetupError::GameInProgress);
+        }
+        if initial_cards.len() != 3 {
+            return Err(SetupError::WrongNumberOfTiers);
+        }
+
+        let mut seen_cards = HashSet::new();
+        for (tier, cards) in initial_cards.iter().enumerate() {
+            if cards.len() > 4 {
+                return Err(SetupError::TooManyCards);
+            }
+            for &card_id in cards {
+                let card = self
+                    .card_lookup
+                    .get(card_id as usize)
+                    .ok_or(SetupError::UnknownCard)?;
+                if card.tier() as usize != tier + 1 {
+                    return Err(SetupError::WrongTier);
+                }
+                if !seen_cards.insert(card_id) {
+                    return Err(SetupError::DuplicateCard);
+                }
+            }
+        }
+
+        let num_nobles = Noble::all().len();
+        let mut seen_nobles = HashSet::new();
+        for &noble_id in &nobles {
+            if noble_id as usize >= num_nobles {
+                return Err(SetupError::UnknownNoble);
+            }
+            if !seen_nobles.insert(noble_id) {
+                return Err(SetupError::DuplicateNoble);
+            }
+        }
+        if nobles.len() > self.players.len() + 1 {
+            return Err(SetupError::TooManyNobles);
+        }
+
+        let initial_cards = initial_cards
+            .iter()
+            .map(|tier| tier.iter().map(|&id| self.card_lookup[id as usize]).collect())
+            .collect();
+        self.with_initial_cards(initial_cards);
+        self.with_nobles(nobles);
+        Ok(())
+    }
+
+    /// Replace the nobles of a game that has not started yet with custom ones,
+    /// e.g. built with `Noble::from_requirements`
+    pub fn with_custom_nobles(&mut self, nobles: Vec<Noble>) -> Result<(), SetupError> {
+        if self.history.num_actions() > 0 {
+            return Err(SetupError::GameInProgress);
+        }
+        let mut seen_nobles = HashSet::new();
+        if !nobles.iter().all(|noble| seen_nobles.insert(noble.id())) {
+            return Err(SetupError::DuplicateNoble);
+        }
+        if nobles.len() > self.players.len() + 1 {
+            return Err(SetupError::TooManyNobles);
+        }
+        self.nobles = nobles;
+        Ok(())
+    }
+
+    /// Swap out the nobles regardless of the state of the game
+    pub(crate) fn replace_nobles(&mut self, nobles: Vec<Noble>) -> Vec<Noble> {
+        std::mem::replace(&mut self.nobles, nobles)
+    }
+
+    fn from_rng<R: Rng>(players: u8, card_lookup: Arc<Vec<Card>>, rng: &mut R) -> Game {
         let mut decks = Vec::new();
         for tier in 1..=3 {
             let mut deck = Vec::new();
@@ -133,45 +451,200 @@ impl Game {
         }
 
         let mut nobles = Noble::all();
-        nobles.shuffle(&mut thread_rng());
+        nobles.shuffle(rng);
         nobles.truncate(players as usize + 1);
 
-        let mut dealt_cards = Vec::<Vec<CardId>>::new();
+        let mut dealt_cards = Vec::<Vec<Option<CardId>>>::new();
 
-        decks[0].shuffle(&mut thread_rng());
-        decks[1].shuffle(&mut thread_rng());
-        decks[2].shuffle(&mut thread_rng());
+        decks[0].shuffle(rng);
+        decks[1].shuffle(rng);
+        decks[2].shuffle(rng);
 
-        // Deal 4 cards to start
-        dealt_cards.push(decks[0].drain(0..4).map(|card| card.id()).collect());
-        dealt_cards.push(decks[1].drain(0..4).map(|card| card.id()).collect());
-        dealt_cards.push(decks[2].drain(0..4).map(|card| card.id()).collect());
+        // Deal 4 cards to start, all cards are drawn from the front of the deck
+        dealt_cards.push(decks[0].drain(0..4).map(|card| Some(card.id())).collect());
+        dealt_cards.push(decks[1].drain(0..4).map(|card| Some(card.id())).collect());
+        dealt_cards.push(decks[2].drain(0..4).map(|card| Some(card.id())).collect());
 
         Game {
             players: (0..players).map(|_| Player::new()).collect(),
             bank: Gems::start(players),
             decks,
             current_player: 0,
+            first_player: 0,
             nobles,
             current_phase: Phase::PlayerStart,
             dealt_cards,
             card_lookup,
             history: GameHistory::new(),
             deadlock_count: 0,
+            deadlock_threshold: None,
+        }
+    }
+
+    /// The tiers (as deck indices) of the cards a player still holds reserved
+    /// face down, read from their ReserveHidden actions. Buying a card they did not
+    /// reserve face up may have spent one of them, which the history cannot tell
+    /// apart from buying off the board, so such purchases only count against the
+    /// reservations while the player would otherwise hold more than `num_hidden`
+    fn hidden_reserve_tiers(history: &GameHistory, player_num: usize, num_hidden: usize) -> Vec<usize> {
+        let mut tiers = Vec::new();
+        let mut face_up = HashSet::new();
+        let mut maybe_spent = Vec::new();
+        for (_, action) in history.iter().filter(|(player, _)| *player == player_num) {
+            match action {
+                ReserveHidden(tier) => tiers.push(*tier),
+                Reserve(card) => {
+                    face_up.insert(*card);
+                }
+                Purchase { card, .. } if !face_up.remove(card) => {
+                    let tier = Card::from_id(*card).map(|card| card.tier() as usize - 1);
+                    if let Some(tier) = tier.filter(|tier| tiers.contains(tier)) {
+                        maybe_spent.push(tier);
+                    }
+                }
+                _ => {}
+            }
+        }
+        for tier in maybe_spent {
+            if tiers.len() <= num_hidden {
+                break;
+            }
+            if let Some(index) = tiers.iter().position(|&reserved| reserved == tier) {
+                tiers.remove(index);
+            }
+        }
+        tiers.truncate(num_hidden);
+        tiers
+    }
+
+    /// Reconstruct a game from the point of view of the player to act, given
+    /// the board, every player's public info, the full state of the player to act
+    /// and the history so far. Information hidden from that player (the order of
+    /// the decks and the cards opponents reserved face down) is filled in with a
+    /// random arrangement of the cards it has not seen, each face down reservation
+    /// drawn from the tier it was reserved from
+    pub fn from_view(
+        board: &Board,
+        players: &[PlayerPublicInfo],
+        me: usize,
+        current_player: &Player,
+        phase: Phase,
+        history: &GameHistory,
+    ) -> Game {
+        let card_lookup = Arc::new(Card::all());
+
+        let mut seen: HashSet<CardId> = board.available_cards.iter().flatten().copied().collect();
+        seen.extend(current_player.all_reserved());
+        for player in players {
+            seen.extend(player.public_reserved.iter());
+        }
+        // Bought cards are no longer in play, face up reservations are public
+        for (_, action) in history {
+            match action {
+                Purchase { card, .. } | Reserve(card) => {
+                    seen.insert(*card);
+                }
+                _ => {}
+            }
+        }
+
+        let mut unseen: Vec<Card> = card_lookup
+            .iter()
+            .filter(|card| !seen.contains(&card.id()))
+            .copied()
+            .collect();
+        unseen.shuffle(&mut thread_rng());
+
+        let mut decks = Vec::new();
+        for (tier, &count) in board.deck_counts.iter().enumerate() {
+            let deck: Vec<Card> = unseen
+                .iter()
+                .filter(|card| card.tier() as usize == tier + 1)
+                .take(count)
+                .copied()
+                .collect();
+            unseen.retain(|card| !deck.contains(card));
+            decks.push(deck);
+        }
+
+        let players = players
+            .iter()
+            .enumerate()
+            .map(|(player_num, public)| {
+                if player_num == me {
+                    return current_player.clone();
+                }
+                let num_hidden = public.num_reserved - public.public_reserved.len();
+                let mut tiers = Game::hidden_reserve_tiers(history, player_num, num_hidden).into_iter();
+                let hidden = (0..num_hidden)
+                    .map(|_| {
+                        // Any unseen card stands in if the tier is unknown or has run out
+                        let position = tiers
+                            .next()
+                            .and_then(|tier| unseen.iter().position(|card| card.tier() as usize == tier + 1))
+                            .unwrap_or(0);
+                        unseen.remove(position).id()
+                    })
+                    .collect();
+                Player::from_public(public, hidden)
+            })
+            .collect();
+
+        let noble_lookup = Noble::all();
+        let nobles = board
+            .nobles
+            .iter()
+            .map(|id| noble_lookup[*id as usize].clone())
+            .collect();
+
+        // Passes in a row since the last action that changed the board
+        let deadlock_count = history
+            .history
+            .iter()
+            .rev()
+            .filter(|(_, action)| *action != Continue)
+            .take_while(|(_, action)| *action == Pass)
+            .count() as u8;
+
+        // Before anyone has played, the current player is the one to start
+        let first_player = history
+            .history
+            .first()
+            .map(|(player_num, _)| *player_num)
+            .unwrap_or(me);
+
+        Game {
+            players,
+            bank: board.gems,
+            decks,
+            current_player: me,
+            first_player,
+            nobles,
+            current_phase: phase,
+            dealt_cards: board.slot_layout(),
+            card_lookup,
+            history: history.clone(),
+            deadlock_count,
+            deadlock_threshold: None,
         }
     }
 
     /// Given a game state return all 
-    /// legal actions that can be taken
+    /// legal actions that can be taken, in sorted order
+    /// (discards instead start with the canonical default, see `choose_discards`)
     ///
     /// returns None if the game is deadlocked or over 
     pub fn get_legal_actions(&self) -> Option<Vec<Action>> {
-        if self.deadlock_count == 2 * self.players.len() as u8 {
+        if self.deadlock_count >= self.deadlock_threshold() {
             return None;
         }
 
         match self.current_phase {
             Phase::NobleAction => {
+                // Every noble has been claimed, nothing to check
+                if self.nobles.is_empty() {
+                    return Some(vec![Pass]);
+                }
                 let mut available_nobles = Vec::new();
                 let player = &self.players[self.current_player];
                 for noble in &self.nobles {
@@ -179,10 +652,11 @@ impl Game {
                         available_nobles.push(noble);
                     }
                 }
-                let nobles: Vec<Action> = available_nobles
+                let mut nobles: Vec<Action> = available_nobles
                     .into_iter()
                     .map(|n| AttractNoble(n.id()))
                     .collect();
+                nobles.sort();
                 if nobles.len() == 0 {
                     Some(vec![Pass])
                 } else {
@@ -193,8 +667,9 @@ impl Game {
                 // There are no legal actions remaining if
                 // there's a player with >= 15 points and we are on the last player's
                 // turn
-                if self.current_player == self.players.len() - 1
-                    && self.players.iter().any(|p| p.total_points() >= 15)
+                let last_player = (self.first_player + self.players.len() - 1) % self.players.len();
+                if self.current_player == last_player
+                    && self.players.iter().any(|p| p.total_points() >= WIN_THRESHOLD)
                 {
                     None
                 } else {
@@ -203,13 +678,11 @@ impl Game {
             }
 
             Phase::PlayerGemCapExceeded => {
-                let mut running = Gems::empty();
                 let player = &self.players[self.current_player];
-                let mut gems = player.gems().clone();
 
-                let discard_num = player.gems().total() - 10;
-                let choices = choose_gems(&mut gems, &mut running, discard_num);
-                let discard_actions = choices.iter().map(|d| Discard(*d)).collect();
+                let discard_num = (player.gems().total() - 10) as u32;
+                let choices = choose_discards(player.gems(), discard_num);
+                let discard_actions = choices.into_iter().map(Discard).collect();
                 Some(discard_actions)
             }
 
@@ -217,15 +690,15 @@ impl Game {
                 let mut actions = Vec::<Action>::new();
                 let player = &self.players[self.current_player];
 
-                // If num reserved cards < 3:
+                // If num reserved cards < MAX_RESERVED:
                 // -> Can reserve a card from board
                 // -> Can reserve a card from decks that are not empty
-                if player.num_reserved_cards() < 3 {
+                if player.reservations_remaining() > 0 {
                     for tier in 0..3 {
                         if self.decks[tier].len() > 0 {
                             actions.push(ReserveHidden(tier));
                         }
-                        self.dealt_cards[tier].iter().for_each(|card| {
+                        self.dealt_cards[tier].iter().flatten().for_each(|card| {
                             actions.push(Reserve(*card));
                         });
                     }
@@ -235,15 +708,13 @@ impl Game {
                 // -> Can purchase a card from board
                 // -> Can purchase a card from hand
                 for card_index in self
-                    .dealt_cards
-                    .iter()
-                    .flatten()
+                    .face_up()
                     .chain(player.all_reserved().iter())
                 {
                     let card = &self.card_lookup[*card_index as usize];
                     if let Some(payments) = player.payment_options_for(&card) {
                         for payment in payments {
-                            actions.push(Purchase((*card_index, payment)));
+                            actions.push(Purchase { card: *card_index, payment });
                         }
                     }
                 }
@@ -254,8 +725,8 @@ impl Game {
                 // -> Can take x distinct tokens
                 let distinct_tokens = self.bank.distinct();
                 let take_max = distinct_tokens.min(3) as u32;
-                let choices =
-                    choose_distinct_gems(&mut self.bank.clone(), &mut Gems::empty(), take_max);
+                let available = Gems::from_set(&self.bank.to_set());
+                let choices = distinct_gem_choices(available, take_max);
 
                 if take_max > 0 {
                     for choice in choices {
@@ -265,7 +736,7 @@ impl Game {
 
                 // If there are 4 tokens of the same color:
                 // -> Can take the two tokens of that color
-                for color in Gem::all_expect_gold() {
+                for color in Gem::ALL_EXCEPT_GOLD {
                     if self.bank[color] >= 4 {
                         actions.push(TakeDouble(color));
                     }
@@ -276,6 +747,7 @@ impl Game {
                 if actions.len() == 0 {
                     Some(vec![Pass])
                 } else {
+                    actions.sort();
                     Some(actions)
                 }
             }
@@ -283,14 +755,14 @@ impl Game {
     }
 
     /// Given an action and the current phase, determine if the action is legal
-    fn is_phase_correct_for(&self, action: Action) -> bool {
+    pub(crate) fn is_phase_correct_for(&self, action: Action) -> bool {
         match self.current_phase {
             Phase::PlayerStart => match action {
                 TakeDouble(_) => true,
                 TakeDistinct(_) => true,
                 Reserve(_) => true,
                 ReserveHidden(_) => true,
-                Purchase(_) => true,
+                Purchase { .. } => true,
                 Pass => true,
                 _ => false,
             },
@@ -310,27 +782,33 @@ impl Game {
         }
     }
 
-    /// Deals a card to a certain tier and return the id
-    /// Deals no card if the deck for that tier is exhausted
-    fn deal_to(&mut self, tier: usize) -> Option<CardId> {
+    /// Takes the card from the front of the deck of a certain tier and return the id
+    /// Draws no card if the deck for that tier is exhausted
+    fn draw(&mut self, tier: usize) -> Option<CardId> {
         if self.decks[tier].len() == 0 {
             return None;
         }
-        let new_card = self.decks[tier].pop().unwrap();
-        self.dealt_cards[tier].push(new_card.id());
-        Some(new_card.id())
+        Some(self.decks[tier].remove(0).id())
     }
 
-    fn has_card(&self, card_id: CardId) -> bool {
-        for tier in &self.dealt_cards {
-            if tier.contains(&card_id) {
-                return true;
-            }
+    /// Deals a card from the front of the deck to the first empty slot
+    /// of a certain tier and return the id
+    /// Deals no card if the deck for that tier is exhausted
+    fn deal_to(&mut self, tier: usize) -> Option<CardId> {
+        let new_card_id = self.draw(tier)?;
+        let slots = &mut self.dealt_cards[tier];
+        match slots.iter_mut().find(|slot| slot.is_none()) {
+            Some(slot) => *slot = Some(new_card_id),
+            None => slots.push(Some(new_card_id)),
         }
-        false
+        Some(new_card_id)
+    }
+
+    fn has_card(&self, card_id: CardId) -> bool {
+        self.face_up().any(|&id| id == card_id)
     }
 
-    /// Removes a faceup card from the board
+    /// Removes a faceup card from the board, leaving its slot empty,
     /// and return the tier it was removed from
     fn remove_card(&mut self, card_id: CardId) -> usize {
         debug_assert!(self.has_card(card_id));
@@ -338,21 +816,53 @@ impl Game {
         let mut remove_index = (5, 5);
         for (tier, tiers) in self.dealt_cards.iter().enumerate() {
             for (index, id) in tiers.iter().enumerate() {
-                if *id == card_id {
+                if *id == Some(card_id) {
                     remove_index = (tier, index);
                 }
             }
         }
 
         let (i, j) = remove_index;
-        self.dealt_cards[i].remove(j);
+        self.dealt_cards[i][j] = None;
         i
     }
 
-    pub fn advance_history_with(&mut self, history: GameHistory) {
-        for (p, a) in history {
-            self.history.add(p, a.clone());
-            self.play_action(a);
+    /// The cards the current player can buy from the board or their reserves,
+    /// each with its cheapest payment (the one spending the fewest gold tokens).
+    /// Empty outside of the start of a turn, when purchases are not legal
+    pub fn available_purchases(&self) -> Vec<(CardId, Gems)> {
+        if self.current_phase != Phase::PlayerStart || self.game_over() {
+            return Vec::new();
+        }
+        let player = &self.players[self.current_player];
+        self.face_up()
+            .chain(player.all_reserved().iter())
+            .filter_map(|&card_id| {
+                let card = &self.card_lookup[card_id as usize];
+                Some((card_id, player.cheapest_payment_for(card)?))
+            })
+            .collect()
+    }
+
+    /// Every face up card, or card reserved by the given player,
+    /// that the player could currently afford, whether or not it is their turn
+    pub fn affordable_cards_for(&self, player_num: usize) -> Vec<CardId> {
+        let player = &self.players[player_num];
+        self.face_up()
+            .chain(player.all_reserved().iter())
+            .copied()
+            .filter(|&card_id| {
+                let card = &self.card_lookup[card_id as usize];
+                player.payment_options_for(card).is_some()
+            })
+            .collect()
+    }
+
+    /// Play every action in the given history, which is relative to
+    /// the current game state (not necessarily the start of the game)
+    pub fn advance_history_with(&mut self, history: &GameHistory) {
+        for (_, a) in history {
+            self.play_action(a.clone());
         }
     }
 
@@ -368,15 +878,11 @@ impl Game {
         debug_assert!(self.is_phase_correct_for(action.clone()));
 
         // If there are enough passes in a row, the game is over (deadlocked)
-        match action {
-            Pass => {
-                self.deadlock_count += 1;
-            }
-            Continue => {}
-            _ => {
-                self.deadlock_count = 0;
-            }
-        }
+        let deadlock_count = match action {
+            Pass => self.deadlock_count + 1,
+            Continue => self.deadlock_count,
+            _ => 0,
+        };
 
         self.history.add(self.current_player, action.clone());
 
@@ -438,7 +944,7 @@ impl Game {
             Reserve(card_id) => {
                 // Preconditions
                 // -> Card with id:card_id is on the board
-                debug_assert!(self.dealt_cards.iter().flatten().any(|id| card_id == *id));
+                debug_assert!(self.has_card(card_id));
 
                 let tier = self.remove_card(card_id);
                 self.deal_to(tier);
@@ -461,8 +967,7 @@ impl Game {
             }
 
             ReserveHidden(tier) => {
-                let new_card_id = self.deal_to(tier).expect("Cannot reserve from empty deck");
-                self.remove_card(new_card_id);
+                let new_card_id = self.draw(tier).expect("Cannot reserve from empty deck");
 
                 let gets_gold = self.bank[Gem::Gold] > 0;
                 let player = &mut self.players[self.current_player];
@@ -481,14 +986,14 @@ impl Game {
                 }
             }
 
-            Purchase((card_id, payment)) => {
+            Purchase { card: card_id, payment } => {
                 let card = self.card_lookup[card_id as usize];
                 let player = &self.players[self.current_player];
                 // Preconditions:
                 // -> The tokens being used is one of the legal ways to purchase this card
                 debug_assert!({
                     let payment_options = player.payment_options_for(&card);
-                    let payments = payment_options.unwrap_or(HashSet::new());
+                    let payments = payment_options.unwrap_or_default();
                     payments.iter().any(|&p| p == payment)
                 });
                 // -> Must have been on the board or in the player's reserved cards
@@ -561,75 +1066,140 @@ impl Game {
         };
 
         debug_assert!(
-            Gems::start(self.players.len() as u8)
-                == self.bank
-                    + self
-                        .players
-                        .iter()
-                        .map(|p| p.gems())
-                        .fold(Gems::empty(), |a, b| a + *b),
-            "Tokens should be conserved"
+            self.expected_gems() == self.gems_in_play(),
+            "Tokens should be conserved, bank is {}",
+            self.bank.debug_compact()
         );
+
+        // Updated last so the preconditions above see the game as it was
+        self.deadlock_count = deadlock_count;
         self.current_phase = next_phase;
     }
 
     pub fn game_over(&self) -> bool {
+        self.is_terminal()
+    }
+
+    /// Whether the game has ended, as opposed to a player being
+    /// left with only a forced Pass or Continue
+    pub fn is_terminal(&self) -> bool {
         self.get_legal_actions().is_none()
     }
 
+    /// The only legal action when there is no choice to make, such as
+    /// a Continue at the end of a turn. None if the game is over or
+    /// the player has more than one option
+    pub fn only_forced_move(&self) -> Option<Action> {
+        match self.get_legal_actions()?.as_slice() {
+            [action] => Some(action.clone()),
+            _ => None,
+        }
+    }
+
+    /// A default choice among the nobles the current player can attract.
+    /// Every noble is worth the same, so this is the one an opponent is
+    /// closest to attracting (fewest developments missing), denying it to
+    /// them, ties going to the lowest id. None unless nobles can be attracted
+    pub fn suggested_noble(&self) -> Option<NobleId> {
+        let attractable = self.get_legal_actions()?.into_iter().filter_map(|action| match action {
+            AttractNoble(id) => Some(id),
+            _ => None,
+        });
+        let closest_opponent = |id: NobleId| {
+            let requirements = *Noble::from_id(id).requirements();
+            self.players
+                .iter()
+                .enumerate()
+                .filter(|(player_num, _)| *player_num != self.current_player)
+                .map(|(_, player)| requirements.saturating_sub(player.developments()).total())
+                .min()
+                .unwrap_or(0)
+        };
+        attractable.min_by_key(|&id| (closest_opponent(id), id))
+    }
+
     /// Given a terminal game state, determine the winner
     pub fn get_winner(&self) -> Option<usize> {
         // The winner of a splendor game is the player with the most points
-        // and fewest development cards in the event of a point tie
-        // Note: there is no indication of what to do in the event of a cards + point tie
-        // TODO: return None instead of whatever we do now
+        // and fewest development cards in the event of a point tie,
+        // a cards + point tie has no winner, see `outcome`
 
         // Preconditions:
         // -> The game is over
         // -> Someone has at least >= 15 points or the game is deadlocked
         debug_assert!(self.get_legal_actions().is_none());
         debug_assert!(
-            self.players.iter().any(|p| p.total_points() >= 15)
-                || self.deadlock_count >= (2 * self.players.len() as u8)
+            self.players.iter().any(|p| p.total_points() >= WIN_THRESHOLD)
+                || self.deadlock_count >= self.deadlock_threshold()
         );
 
-        let mut max_points = 15;
-        let mut min_developments = u32::MAX;
-        let mut winner = None;
-        for (i, player) in self.players.iter().enumerate() {
-            if player.total_points() > max_points {
-                max_points = player.total_points();
-                min_developments = player.developments().total();
-                winner = Some(i);
-            } else if player.total_points() == max_points {
-                if player.developments().total() < min_developments as u32 {
-                    min_developments = player.developments().total();
-                    winner = Some(i);
-                }
-            }
+        match self.outcome() {
+            GameOutcome::Winner(winner) => Some(winner),
+            GameOutcome::Draw | GameOutcome::Undecided => None,
+        }
+    }
+
+    /// Determine the outcome of the game, distinguishing between a game
+    /// that is still in progress and one that ended without a single winner
+    ///
+    /// The winner is the player with the most points, with ties broken by
+    /// the fewest development cards. If the tie remains, or the game ended in a
+    /// deadlock before anyone reached 15 points, the game is a draw
+    pub fn outcome(&self) -> GameOutcome {
+        if !self.game_over() {
+            return GameOutcome::Undecided;
         }
 
-        winner
+        let max_points = self
+            .players
+            .iter()
+            .map(|p| p.total_points())
+            .max()
+            .unwrap_or(0);
+        if max_points < WIN_THRESHOLD {
+            return GameOutcome::Draw;
+        }
+
+        let leaders = self
+            .players
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| p.total_points() == max_points);
+        let min_developments = leaders
+            .clone()
+            .map(|(_, p)| p.developments().total())
+            .min()
+            .unwrap_or(0);
+        let winners: Vec<usize> = leaders
+            .filter(|(_, p)| p.developments().total() == min_developments)
+            .map(|(i, _)| i)
+            .collect();
+
+        match winners.as_slice() {
+            [winner] => GameOutcome::Winner(*winner),
+            _ => GameOutcome::Draw,
+        }
+    }
+
+    /// Uniformly sample one of the legal actions,
+    /// returns None if the game is over
+    pub fn random_legal_action(&self, rng: &mut impl Rng) -> Option<Action> {
+        let actions = self.get_legal_actions()?;
+        actions.choose(rng).cloned()
     }
 
     /// Given a game state, play random legal moves until the game is over
     /// Returns the winner of the game
     /// Returns None if there is no clear winner 
     pub fn rollout(&mut self) -> Option<usize> {
-        loop {
-            let actions = self.get_legal_actions();
-            // If there are no legal actions, the game is over
-            // and we should break out of the loop
-            if actions.is_none() {
-                break;
-            }
-
-            let actions = actions.unwrap();
+        self.rollout_with(&mut thread_rng())
+    }
 
-            let action = actions
-                .choose(&mut thread_rng())
-                .expect("List should not be empty");
-            self.play_action(action.clone());
+    /// Same as `rollout`, but draws the random moves from the given rng
+    pub fn rollout_with(&mut self, rng: &mut impl Rng) -> Option<usize> {
+        // If there are no legal actions, the game is over
+        while let Some(action) = self.random_legal_action(rng) {
+            self.play_action(action);
         }
 
         self.get_winner()
@@ -640,6 +1210,7 @@ impl Game {
 pub mod test {
     use super::Gem::*;
     pub use super::*;
+    use crate::arena::ClientInfo;
     #[test]
 
     pub fn test_choose_tokens_1() {
@@ -660,7 +1231,7 @@ pub mod test {
         let choices = choose_gems(&mut gems, &mut running, 1);
         assert_eq!(
             choices,
-            HashSet::from_iter(vec![
+            BTreeSet::from_iter(vec![
                 Gems::from_vec(&vec![Gem::Ruby]),
                 Gems::from_vec(&vec![Gem::Sapphire]),
                 Gems::from_vec(&vec![Gem::Emerald]),
@@ -688,7 +1259,7 @@ pub mod test {
         let choices = choose_gems(&mut gems, &mut running, 2);
         assert_eq!(
             choices,
-            HashSet::from_iter(vec![
+            BTreeSet::from_iter(vec![
                 Gems::from_vec(&vec![Gem::Ruby, Gem::Ruby]),
                 Gems::from_vec(&vec![Gem::Sapphire, Gem::Sapphire]),
                 Gems::from_vec(&vec![Gem::Emerald, Gem::Sapphire]),
@@ -698,6 +1269,32 @@ pub mod test {
         );
     }
 
+    #[test]
+    pub fn test_discard_actions_are_distinct_and_ordered() {
+        let mut game = Game::new(4, Arc::new(Card::all()));
+        let held = Gems::from_vec(&vec![
+            Ruby, Ruby, Ruby, Ruby, Ruby, Ruby, Ruby, Onyx, Onyx, Onyx, Onyx, Onyx,
+        ]);
+        game.bank -= held;
+        game.players[0].add_gems(held);
+        game.current_phase = Phase::PlayerGemCapExceeded;
+
+        // Only the makeup of the discard matters, not which token is picked
+        let actions = game.get_legal_actions().unwrap();
+        assert_eq!(
+            actions,
+            vec![
+                Discard(Gems::from_vec(&vec![Ruby, Ruby])),
+                Discard(Gems::from_vec(&vec![Onyx, Ruby])),
+                Discard(Gems::from_vec(&vec![Onyx, Onyx])),
+            ]
+        );
+
+        game.play_action(actions[0].clone());
+        assert_eq!(game.players[0].gems().total(), 10);
+        assert_eq!(game.players[0].gems()[Ruby], 5);
+    }
+
     #[test]
     pub fn test_choose_3_distinct_tokens() {
         let mut gems = Gems::start(2);
@@ -705,7 +1302,7 @@ pub mod test {
         let choices = choose_distinct_gems(&mut gems, &mut running, 3);
         assert_eq!(
             choices,
-            HashSet::from_iter(vec![
+            BTreeSet::from_iter(vec![
                 Gems::from_vec(&vec![Gem::Ruby, Gem::Sapphire, Gem::Emerald]),
                 Gems::from_vec(&vec![Gem::Ruby, Gem::Sapphire, Gem::Diamond]),
                 Gems::from_vec(&vec![Gem::Ruby, Gem::Sapphire, Gem::Onyx]),
@@ -740,7 +1337,7 @@ pub mod test {
         let choices = choose_distinct_gems(&mut gems, &mut running, 2);
         assert_eq!(
             choices,
-            HashSet::from_iter(vec![
+            BTreeSet::from_iter(vec![
                 Gems::from_vec(&vec![Gem::Emerald, Gem::Sapphire]),
                 Gems::from_vec(&vec![Gem::Ruby, Gem::Sapphire]),
                 Gems::from_vec(&vec![Gem::Ruby, Gem::Emerald]),
@@ -748,6 +1345,40 @@ pub mod test {
         );
     }
 
+    #[test]
+    pub fn test_cached_distinct_choices_match() {
+        // Every combination of colors that could be present in the bank
+        for mask in 0..32 {
+            let mut bank = Gems::empty();
+            for (i, color) in Gem::all_expect_gold().into_iter().enumerate() {
+                if mask & (1 << i) != 0 {
+                    bank[color] = 4;
+                }
+            }
+            let available = Gems::from_set(&bank.to_set());
+            for num_chosen in 0..=bank.distinct().min(3) as u32 {
+                let expected = choose_distinct_gems(&mut bank.clone(), &mut Gems::empty(), num_chosen);
+                let cached = distinct_gem_choices(available, num_chosen);
+                assert_eq!(cached.len(), expected.len());
+                assert_eq!(BTreeSet::from_iter(cached), expected);
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_cached_distinct_choices_reused() {
+        use cached::Cached;
+
+        let available = Gems::from_set(&Gems::start(4).to_set());
+        distinct_gem_choices(available, 3);
+        let hits = DISTINCT_GEM_CHOICES.lock().unwrap().cache_hits().unwrap();
+        for _ in 0..100 {
+            distinct_gem_choices(available, 3);
+        }
+        let new_hits = DISTINCT_GEM_CHOICES.lock().unwrap().cache_hits().unwrap();
+        assert!(new_hits >= hits + 100);
+    }
+
     #[test]
     pub fn test_init_legal_actions() {
         let card_lookup = Arc::new(Card::all());
@@ -768,6 +1399,766 @@ pub mod test {
         // Note: this is manually inspected as a test for now due to time contraints
     }
 
+    /// Put the game in the final phase of the last player's turn
+    fn end_round(game: &mut Game) {
+        game.current_player = game.players.len() - 1;
+        game.current_phase = Phase::PlayerActionEnd;
+    }
+
+    #[test]
+    pub fn test_random_legal_action_is_legal() {
+        let mut game = Game::new_seeded(3, Arc::new(Card::all()), 7);
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..45 {
+            let action = game.random_legal_action(&mut rng).unwrap();
+            assert!(game.get_legal_actions().unwrap().contains(&action));
+            game.play_action(action);
+        }
+    }
+
+    #[test]
+    pub fn test_gems_in_play_conserved_in_rollout() {
+        let mut game = Game::new_seeded(3, Arc::new(Card::all()), 5);
+        let mut rng = StdRng::seed_from_u64(5);
+        assert_eq!(game.expected_gems(), Gems::start(3));
+        while let Some(action) = game.random_legal_action(&mut rng) {
+            assert_eq!(game.gems_in_play(), game.expected_gems());
+            game.play_action(action);
+        }
+        assert_eq!(game.gems_in_play(), game.expected_gems());
+        assert!(game.players().iter().any(|p| p.gems().total() > 0));
+    }
+
+    #[test]
+    pub fn test_set_first_player() {
+        let mut game = Game::new(3, Arc::new(Card::all()));
+        assert!(matches!(game.set_first_player(3), Err(SetupError::UnknownPlayer)));
+        game.set_first_player(2).unwrap();
+        assert_eq!(game.current_player_num(), 2);
+
+        game.play_action(ReserveHidden(0));
+        assert!(matches!(game.set_first_player(0), Err(SetupError::GameInProgress)));
+        game.play_action(Pass);
+        game.play_action(Continue);
+        assert_eq!(game.current_player_num(), 0);
+        assert_eq!(ClientInfo::from_game(&game, String::new()).to_game().first_player(), 2);
+    }
+
+    #[test]
+    pub fn test_from_view_leaves_out_bought_cards() {
+        let mut game = Game::new_seeded(2, Arc::new(Card::all()), 5);
+        let mut rng = StdRng::seed_from_u64(5);
+        let bought = |game: &Game| -> Vec<CardId> {
+            game.history()
+                .iter()
+                .filter_map(|(_, action)| match action {
+                    Purchase { card, .. } => Some(*card),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        // Buy whenever possible until a few cards have left play
+        while bought(&game).len() < 3 {
+            let actions = game.get_legal_actions().unwrap();
+            let action = actions
+                .iter()
+                .find(|a| matches!(a, Purchase { .. }))
+                .cloned()
+                .unwrap_or_else(|| actions.choose(&mut rng).unwrap().clone());
+            game.play_action(action);
+        }
+
+        let bought = bought(&game);
+        for _ in 0..20 {
+            let view = ClientInfo::from_game(&game, String::new()).to_game();
+            assert_eq!(view.deck_counts(), game.deck_counts());
+            let in_play: HashSet<CardId> = view
+                .decks
+                .iter()
+                .flatten()
+                .map(|card| card.id())
+                .chain(view.players.iter().flat_map(|p| p.all_reserved()))
+                .collect();
+            assert!(bought.iter().all(|card| !in_play.contains(card)));
+        }
+    }
+
+    #[test]
+    pub fn test_from_view_keeps_blind_reserves_in_their_tier() {
+        let mut game = Game::new(2, Arc::new(Card::all()));
+        for tier in [2, 0, 1, 2] {
+            game.play_action(ReserveHidden(tier));
+            game.play_action(Pass);
+            game.play_action(Continue);
+        }
+
+        // Player 1 reserved face down from tiers 1 and 3
+        for _ in 0..20 {
+            let view = ClientInfo::from_game(&game, String::new()).to_game();
+            assert_eq!(view.deck_counts(), game.deck_counts());
+            let mut tiers: Vec<u8> = view.players[1]
+                .blind_reserved()
+                .iter()
+                .map(|&id| Card::from_id(id).unwrap().tier())
+                .collect();
+            tiers.sort();
+            assert_eq!(tiers, vec![1, 3]);
+        }
+    }
+
+    #[test]
+    pub fn test_legal_actions_sorted_and_stable() {
+        let game = Game::new_seeded(2, Arc::new(Card::all()), 11);
+        let actions = game.get_legal_actions().unwrap();
+        assert!(actions.windows(2).all(|pair| pair[0] < pair[1]));
+
+        for _ in 0..5 {
+            let again = Game::new_seeded(2, Arc::new(Card::all()), 11);
+            assert_eq!(again.get_legal_actions().unwrap(), actions);
+        }
+    }
+
+    #[test]
+    pub fn test_action_total_order() {
+        let actions = vec![
+            TakeDouble(Ruby),
+            TakeDistinct(HashSet::from_iter(vec![Onyx, Ruby])),
+            TakeDistinct(HashSet::from_iter(vec![Sapphire])),
+            Reserve(3),
+            ReserveHidden(0),
+            Purchase { card: 3, payment: Gems::one(Ruby) },
+            Purchase { card: 3, payment: Gems::one(Onyx) },
+            Discard(Gems::one(Gold)),
+            AttractNoble(2),
+            Pass,
+            Continue,
+        ];
+
+        for a in &actions {
+            for b in &actions {
+                assert_eq!(a.cmp(b), b.cmp(a).reverse());
+                assert_eq!(a.cmp(b) == std::cmp::Ordering::Equal, a == b);
+            }
+        }
+
+        let mut sorted = actions.clone();
+        sorted.reverse();
+        sorted.sort();
+        assert_eq!(sorted, actions);
+    }
+
+    #[test]
+    pub fn test_purchase_fields() {
+        let purchase = Purchase { card: 12, payment: Gems::one(Emerald) };
+        match &purchase {
+            Purchase { card, payment } => {
+                assert_eq!(*card, 12);
+                assert_eq!(*payment, Gems::one(Emerald));
+            }
+            _ => panic!("Expected a purchase"),
+        }
+        assert_eq!(purchase.kind(), ActionKind::Purchase);
+
+        // Purchases are still sent as a [card, payment] pair
+        let json = serde_json::to_value(&purchase).unwrap();
+        let pair = serde_json::json!({ "Purchase": [12, Gems::one(Emerald)] });
+        assert_eq!(json, pair);
+        let parsed: Action = serde_json::from_value(pair).unwrap();
+        assert_eq!(parsed, purchase);
+    }
+
+    #[test]
+    pub fn test_setup_board() {
+        let mut game = Game::new(3, Arc::new(Card::all()));
+        let cards = vec![vec![0, 1, 2, 3], vec![40, 41, 42, 43], vec![70, 71, 72, 73]];
+        game.setup_board(cards.clone(), vec![1, 4, 7]).unwrap();
+
+        assert_eq!(game.cards(), cards);
+        let nobles: Vec<NobleId> = game.nobles().iter().map(|n| n.id()).collect();
+        assert_eq!(nobles, vec![1, 4, 7]);
+        assert_eq!(game.deck_counts(), [36, 26, 16]);
+        assert!(Board::from_game(&game).validate());
+    }
+
+    #[test]
+    pub fn test_setup_near_winning_player() {
+        let mut game = Game::new(2, Arc::new(Card::all()));
+        let gems = Gems::from_counts(&[(Ruby, 2), (Gold, 1)]);
+        let developments = Gems::from_counts(&[(Onyx, 3), (Diamond, 4)]);
+        game.setup_player(1, gems, developments, 14).unwrap();
+        assert_eq!(*game.players()[1].gems(), gems);
+        assert_eq!(*game.players()[1].developments(), developments);
+        assert_eq!(game.points_to_win(1), 1);
+        assert_eq!(game.bank()[Ruby], 2);
+        assert_eq!(game.gems_in_play(), game.expected_gems());
+
+        // Gems the bank does not have, or that no player could hold
+        let too_many = Gems::from_counts(&[(Ruby, 3)]);
+        let empty = Gems::empty();
+        let owed = Gems::empty() - Gems::one(Ruby);
+        assert!(matches!(game.setup_player(0, too_many, empty, 0), Err(SetupError::NotEnoughGems)));
+        assert!(matches!(game.setup_player(0, owed, empty, 0), Err(SetupError::InvalidGems)));
+        assert!(matches!(game.setup_player(0, empty, Gems::one(Gold), 0), Err(SetupError::InvalidDevelopments)));
+        assert!(matches!(game.setup_player(2, empty, empty, 0), Err(SetupError::UnknownPlayer)));
+        assert_eq!(*game.players()[0].gems(), Gems::empty());
+
+        // Seeding the same player again returns their old gems to the bank
+        game.setup_player(1, Gems::one(Ruby), developments, 15).unwrap();
+        assert_eq!(game.bank()[Ruby], 3);
+        assert_eq!(game.gems_in_play(), game.expected_gems());
+
+        // The game ends with the seeded player's win once the round is over
+        assert!(!game.game_over());
+        while let Some(action) = game.get_legal_actions().and_then(|actions| actions.first().cloned()) {
+            game.play_action(action);
+        }
+        assert!(game.is_terminal());
+        assert_eq!(game.outcome(), GameOutcome::Winner(1));
+        assert!(matches!(game.setup_player(0, empty, empty, 0), Err(SetupError::GameInProgress)));
+    }
+
+    #[test]
+    pub fn test_setup_board_rejects_bad_positions() {
+        let mut game = Game::new(2, Arc::new(Card::all()));
+        let tiers = |first: Vec<CardId>| vec![first, vec![40], vec![70]];
+
+        assert!(matches!(game.setup_board(tiers(vec![90]), vec![]), Err(SetupError::UnknownCard)));
+        assert!(matches!(game.setup_board(tiers(vec![41]), vec![]), Err(SetupError::WrongTier)));
+        assert!(matches!(game.setup_board(tiers(vec![0, 0]), vec![]), Err(SetupError::DuplicateCard)));
+        assert!(matches!(
+            game.setup_board(tiers(vec![0, 1, 2, 3, 4]), vec![]),
+            Err(SetupError::TooManyCards)
+        ));
+        assert!(matches!(game.setup_board(tiers(vec![0]), vec![0, 1, 2, 3]), Err(SetupError::TooManyNobles)));
+        assert!(matches!(game.setup_board(tiers(vec![0]), vec![2, 2]), Err(SetupError::DuplicateNoble)));
+
+        game.play_action(ReserveHidden(0));
+        assert!(matches!(game.setup_board(tiers(vec![0]), vec![]), Err(SetupError::GameInProgress)));
+    }
+
+    #[test]
+    pub fn test_with_custom_nobles() {
+        let mut game = Game::new(2, Arc::new(Card::all()));
+        let custom = |id| Noble::from_requirements(4, id, Gems::from_counts(&[(Ruby, 2)]));
+
+        assert!(matches!(game.with_custom_nobles(vec![custom(20), custom(20)]), Err(SetupError::DuplicateNoble)));
+        assert!(matches!(
+            game.with_custom_nobles((20..24).map(custom).collect()),
+            Err(SetupError::TooManyNobles)
+        ));
+        game.with_custom_nobles(vec![custom(20), custom(21)]).unwrap();
+        assert_eq!(game.nobles(), &vec![custom(20), custom(21)]);
+
+        game.play_action(ReserveHidden(0));
+        assert!(matches!(game.with_custom_nobles(vec![]), Err(SetupError::GameInProgress)));
+    }
+
+    #[test]
+    pub fn test_score_timeline() {
+        let mut game = Game::new(2, Arc::new(Card::all()));
+        game.setup_board(
+            vec![vec![7, 15, 0, 1], vec![40, 41, 42, 43], vec![70, 71, 72, 73]],
+            vec![0],
+        )
+        .unwrap();
+
+        // Cards 7 and 15 are worth 1 point each and cost 4 sapphire and 4 ruby
+        let sapphires = Gems::from_vec(&vec![Sapphire; 4]);
+        let rubies = Gems::from_vec(&vec![Ruby; 4]);
+        game.bank -= sapphires + rubies;
+        game.players[0].add_gems(sapphires);
+        game.players[1].add_gems(rubies);
+        let initial_game = game.clone();
+
+        for action in [
+            Purchase { card: 7, payment: sapphires },
+            Purchase { card: 15, payment: rubies },
+            ReserveHidden(0),
+        ] {
+            game.play_action(action);
+            game.play_action(Pass);
+            game.play_action(Continue);
+        }
+
+        assert_eq!(
+            game.history().score_timeline(&initial_game),
+            vec![vec![1, 0], vec![1, 1], vec![1, 1]]
+        );
+    }
+
+    #[test]
+    pub fn test_available_purchases() {
+        let mut game = Game::new(2, Arc::new(Card::all()));
+        game.setup_board(
+            vec![vec![7, 15, 0, 1], vec![40, 41, 42, 43], vec![70, 71, 72, 73]],
+            vec![0],
+        )
+        .unwrap();
+        assert_eq!(game.available_purchases(), vec![]);
+
+        // Cards 7 and 15 cost 4 sapphire and 4 ruby, the gold token
+        // could stand in for either but is never the cheapest payment
+        let sapphires = Gems::from_vec(&vec![Sapphire; 4]);
+        let rubies = Gems::from_vec(&vec![Ruby; 4]);
+        let held = sapphires + rubies + Gems::one(Gold);
+        game.bank -= held;
+        game.players[0].add_gems(held);
+
+        assert_eq!(
+            game.available_purchases(),
+            vec![(7, sapphires), (15, rubies)]
+        );
+        for (card_id, payment) in game.available_purchases() {
+            let legal = game.get_legal_actions().unwrap();
+            assert!(legal.contains(&Purchase { card: card_id, payment }));
+        }
+    }
+
+    /// Hand the tokens missing from `bank` to the players so tokens stay conserved
+    fn set_bank(game: &mut Game, bank: Gems, to_player_1: Gems) {
+        let removed = game.bank - bank;
+        game.bank = bank;
+        game.players[1].add_gems(to_player_1);
+        game.players[0].add_gems(removed - to_player_1);
+    }
+
+    fn legal_takes(game: &Game) -> Vec<Action> {
+        game.get_legal_actions()
+            .unwrap()
+            .into_iter()
+            .filter(|a| matches!(a, TakeDistinct(_) | TakeDouble(_)))
+            .collect()
+    }
+
+    #[test]
+    pub fn test_bank_after_take_double() {
+        let game = Game::new(2, Arc::new(Card::all()));
+        let bank = game.bank_after(&TakeDouble(Onyx)).unwrap();
+        assert_eq!(bank[Onyx], game.bank()[Onyx] - 2);
+        assert_eq!(bank + Gems::one(Onyx) + Gems::one(Onyx), *game.bank());
+
+        // The game itself is left untouched
+        assert_eq!(*game.bank(), Gems::start(2));
+
+        // Illegal or bank neutral actions have nothing to simulate
+        assert_eq!(game.bank_after(&TakeDouble(Gold)), None);
+        assert_eq!(game.bank_after(&Continue), None);
+    }
+
+    #[test]
+    pub fn test_take_with_two_colors_left() {
+        let mut game = Game::new(2, Arc::new(Card::all()));
+        let bank = Gems { sapphire: 1, ruby: 2, gold: 5, ..Gems::empty() };
+        let to_player_1 = Gems { onyx: 4, emerald: 4, diamond: 2, ..Gems::empty() };
+        set_bank(&mut game, bank, to_player_1);
+
+        let both = TakeDistinct([Sapphire, Ruby].into_iter().collect());
+        assert_eq!(legal_takes(&game), vec![both.clone()]);
+
+        game.play_action(both);
+        assert_eq!(game.bank, Gems { ruby: 1, gold: 5, ..Gems::empty() });
+    }
+
+    #[test]
+    pub fn test_take_with_one_color_left() {
+        let mut game = Game::new(2, Arc::new(Card::all()));
+        let bank = Gems { onyx: 3, gold: 5, ..Gems::empty() };
+        let to_player_1 = Gems { sapphire: 4, emerald: 4, ruby: 2, ..Gems::empty() };
+        set_bank(&mut game, bank, to_player_1);
+
+        let one = TakeDistinct([Onyx].into_iter().collect());
+        assert_eq!(legal_takes(&game), vec![one.clone()]);
+
+        game.play_action(one);
+        assert_eq!(game.bank, Gems { onyx: 2, gold: 5, ..Gems::empty() });
+
+        // With enough tokens left in the single pile a double is offered too
+        let mut game = Game::new(2, Arc::new(Card::all()));
+        let bank = Gems { onyx: 4, gold: 5, ..Gems::empty() };
+        set_bank(&mut game, bank, to_player_1);
+        assert_eq!(
+            legal_takes(&game),
+            vec![TakeDouble(Onyx), TakeDistinct([Onyx].into_iter().collect())]
+        );
+    }
+
+    #[test]
+    pub fn test_noble_phase_passes_once_nobles_are_claimed() {
+        let mut game = Game::new(2, Arc::new(Card::all()));
+        assert_eq!(game.noble_count(), 3);
+
+        // Player 0 would attract any noble, but they have all been claimed
+        for id in 0..40 {
+            game.players[0].purchase_card(&Card::all()[id], &Gems::empty());
+        }
+        game.nobles.clear();
+        assert_eq!(game.noble_count(), 0);
+
+        let take = game
+            .get_legal_actions()
+            .unwrap()
+            .into_iter()
+            .find(|a| matches!(a, TakeDistinct(_)))
+            .unwrap();
+        game.play_action(take);
+        assert_eq!(game.phase(), Phase::NobleAction);
+        assert_eq!(game.get_legal_actions(), Some(vec![Pass]));
+
+        game.play_action(Pass);
+        assert_eq!(game.phase(), Phase::PlayerActionEnd);
+        assert_eq!(ClientInfo::from_game(&game, String::new()).noble_count, 0);
+    }
+
+    #[test]
+    pub fn test_suggested_noble_denies_closest_opponent() {
+        let mut game = Game::new(2, Arc::new(Card::all()));
+        game.nobles = vec![Noble::from_id(0), Noble::from_id(2), Noble::from_id(1)];
+        let develop = |player: &mut Player, gem: Gem, count: usize| {
+            for card in Card::all().iter().filter(|card| card.gem() == gem).take(count) {
+                player.purchase_card(card, &Gems::empty());
+            }
+        };
+
+        // Player 0 qualifies for nobles 0 (4 emerald, 4 ruby)
+        // and 2 (3 onyx, 3 emerald, 3 ruby), but not 1 (3 onyx, 3 ruby, 3 diamond)
+        develop(&mut game.players[0], Emerald, 4);
+        develop(&mut game.players[0], Ruby, 4);
+        develop(&mut game.players[0], Onyx, 3);
+        // Player 1 is a single ruby away from noble 2
+        develop(&mut game.players[1], Onyx, 3);
+        develop(&mut game.players[1], Emerald, 3);
+        develop(&mut game.players[1], Ruby, 2);
+
+        assert_eq!(game.suggested_noble(), None);
+        let take = game
+            .get_legal_actions()
+            .unwrap()
+            .into_iter()
+            .find(|a| matches!(a, TakeDistinct(_)))
+            .unwrap();
+        game.play_action(take);
+        assert_eq!(game.phase(), Phase::NobleAction);
+
+        let legal = game.get_legal_actions().unwrap();
+        assert_eq!(legal, vec![AttractNoble(0), AttractNoble(2)]);
+        assert_eq!(game.suggested_noble(), Some(2));
+        assert!(legal.contains(&AttractNoble(game.suggested_noble().unwrap())));
+    }
+
+    #[test]
+    pub fn test_reservations_remaining_in_client_info() {
+        let mut game = Game::new(2, Arc::new(Card::all()));
+        let info = ClientInfo::from_game(&game, String::new());
+        assert_eq!(info.reservations_remaining, 3);
+
+        // Player 0 reserves a card on each of their turns, player 1 takes gems
+        for _ in 0..2 {
+            let reserve = game
+                .get_legal_actions()
+                .unwrap()
+                .into_iter()
+                .find(|a| matches!(a, ReserveHidden(_)))
+                .unwrap();
+            game.play_action(reserve);
+            for player_num in [0, 1] {
+                while game.current_player_num() == player_num {
+                    let action = game.get_legal_actions().unwrap()[0].clone();
+                    game.play_action(action);
+                }
+            }
+        }
+
+        let info = ClientInfo::from_game(&game, String::new());
+        assert_eq!(info.current_player_num, 0);
+        assert_eq!(info.current_player.num_reserved_cards(), 2);
+        assert_eq!(info.reservations_remaining, 1);
+    }
+
+    #[test]
+    pub fn test_affordable_cards_for() {
+        let mut game = Game::new(2, Arc::new(Card::all()));
+        game.setup_board(
+            vec![vec![7, 15, 31, 0], vec![40, 41, 42, 43], vec![70, 71, 72, 73]],
+            vec![0],
+        )
+        .unwrap();
+
+        // Cards 7, 15 and 31 cost 4 sapphire, 4 ruby and 4 onyx
+        let player_0 = Gems::from_counts(&[(Sapphire, 4), (Onyx, 4)]);
+        let player_1 = Gems::from_counts(&[(Ruby, 4)]);
+        game.bank -= player_0 + player_1;
+        game.players[0].add_gems(player_0);
+        game.players[1].add_gems(player_1);
+
+        game.play_action(Reserve(31));
+        game.play_action(Pass);
+        game.play_action(Continue);
+
+        // Player 0 can still afford the card they reserved, even off turn
+        let affordable = game.affordable_cards_for(0);
+        assert!(affordable.contains(&7));
+        assert!(affordable.contains(&31));
+        assert!(!affordable.contains(&15));
+
+        // For the current player they match the legal purchases
+        let mut purchasable: Vec<CardId> = game
+            .get_legal_actions()
+            .unwrap()
+            .into_iter()
+            .filter_map(|a| match a {
+                Purchase { card, .. } => Some(card),
+                _ => None,
+            })
+            .collect();
+        purchasable.dedup();
+        let mut affordable = game.affordable_cards_for(1);
+        affordable.sort();
+        assert!(affordable.contains(&15));
+        assert_eq!(affordable, purchasable);
+    }
+
+    #[test]
+    pub fn test_developments_summary() {
+        let mut game = Game::new(2, Arc::new(Card::all()));
+        game.setup_board(
+            vec![vec![7, 15, 31, 0], vec![40, 41, 42, 43], vec![70, 71, 72, 73]],
+            vec![0],
+        )
+        .unwrap();
+        assert_eq!(game.developments_summary(), vec![Gems::empty(); 2]);
+
+        // Cards 7, 15 and 31 cost 4 sapphire, 4 ruby and 4 onyx
+        let player_0 = Gems::from_counts(&[(Sapphire, 4), (Onyx, 4)]);
+        let player_1 = Gems::from_counts(&[(Ruby, 4)]);
+        game.bank -= player_0 + player_1;
+        game.players[0].add_gems(player_0);
+        game.players[1].add_gems(player_1);
+
+        for card_id in [7, 15, 31] {
+            let purchase = game
+                .get_legal_actions()
+                .unwrap()
+                .into_iter()
+                .find(|a| matches!(a, Purchase { card, .. } if *card == card_id))
+                .unwrap();
+            game.play_action(purchase);
+            game.play_action(Pass);
+            game.play_action(Continue);
+        }
+
+        let summary = game.developments_summary();
+        assert_eq!(summary.len(), 2);
+        for (player, developments) in game.players().iter().zip(&summary) {
+            assert_eq!(player.developments(), developments);
+        }
+        assert_eq!(summary[0].total(), 2);
+        assert_eq!(summary[1].total(), 1);
+
+        let info = ClientInfo::from_game(&game, String::new());
+        assert_eq!(info.developments_summary(), summary);
+    }
+
+    #[test]
+    pub fn test_points_to_win() {
+        let mut game = Game::new(3, Arc::new(Card::all()));
+        game.players[0].add_points(4);
+        game.players[1].add_points(13);
+        game.players[2].add_points(9);
+
+        assert_eq!(game.points_to_win(1), 2);
+        assert_eq!(game.points_to_win(0), 11);
+        assert_eq!(game.closest_to_winning(), 1);
+
+        game.players[2].add_points(8);
+        assert_eq!(game.points_to_win(2), 0);
+        assert_eq!(game.closest_to_winning(), 2);
+    }
+
+    #[test]
+    pub fn test_turn_and_round_number() {
+        let mut game = Game::new(3, Arc::new(Card::all()));
+        assert_eq!(game.turn_number(), 0);
+        assert_eq!(game.round_number(), 0);
+
+        let mut rounds = vec![];
+        while game.turn_number() < 7 {
+            let action = game.get_legal_actions().unwrap()[0].clone();
+            let ends_turn = action == Action::Continue;
+            game.play_action(action);
+            if ends_turn {
+                rounds.push(game.round_number());
+            }
+        }
+
+        assert_eq!(rounds, vec![0, 0, 1, 1, 1, 2, 2]);
+        assert_eq!(game.current_player_num(), 1);
+    }
+
+    #[test]
+    pub fn test_noble_awarded_on_winning_purchase() {
+        let mut game = Game::new(2, Arc::new(Card::all()));
+        let cards = Card::all();
+        game.with_nobles(vec![0]);
+        game.with_initial_cards(vec![
+            cards[0..4].to_vec(),
+            cards[40..44].to_vec(),
+            cards[86..90].to_vec(),
+        ]);
+
+        // Player 0 has already passed the threshold
+        game.players[0].add_points(16);
+
+        // Player 1 is one ruby development away from noble 0 (4 emerald, 4 ruby)
+        for id in [24, 25, 26, 27, 32, 33, 34] {
+            game.players[1].purchase_card(&cards[id], &Gems::empty());
+        }
+        game.players[1].add_points(10);
+        let payment = Gems::from_vec(&vec![Emerald, Emerald, Emerald]);
+        game.bank -= payment;
+        game.players[1].add_gems(payment);
+        game.current_player = 1;
+
+        // Card 89 is a 5 point ruby card, reaching the threshold and attracting the noble
+        game.play_action(Purchase { card: 89, payment });
+        assert_eq!(game.players[1].total_points(), 15);
+        assert!(!game.game_over());
+        assert_eq!(game.get_legal_actions(), Some(vec![AttractNoble(0)]));
+
+        game.play_action(AttractNoble(0));
+        assert_eq!(game.players[1].total_points(), 18);
+        assert!(game.game_over());
+        assert_eq!(game.outcome(), GameOutcome::Winner(1));
+    }
+
+    #[test]
+    pub fn test_outcome_undecided() {
+        let game = Game::new(2, Arc::new(Card::all()));
+        assert_eq!(game.outcome(), GameOutcome::Undecided);
+    }
+
+    #[test]
+    pub fn test_slot_layout_keeps_empty_slots() {
+        let mut game = Game::new(2, Arc::new(Card::all()));
+        let tier = game.cards()[2].clone();
+
+        // The replacement is dealt to the slot the reserved card left
+        game.play_action(Reserve(tier[1]));
+        let slots = game.slot_layout();
+        assert_eq!(slots[2][0], Some(tier[0]));
+        assert!(slots[2][1].is_some_and(|id| id != tier[1]));
+        assert_eq!(slots[2][2..], [Some(tier[2]), Some(tier[3])]);
+
+        // Once the deck is exhausted the slot stays empty
+        game.play_action(Pass);
+        game.play_action(Continue);
+        game.decks[2].clear();
+        game.play_action(Reserve(tier[2]));
+        let slots = game.slot_layout();
+        assert_eq!(slots[2].len(), 4);
+        assert_eq!(slots[2][2], None);
+        assert_eq!(game.cards()[2].len(), 3);
+        assert!(!game.cards()[2].contains(&tier[2]));
+
+        let board = Board::from_game(&game);
+        assert_eq!(board.slot_layout(), slots);
+        assert!(board.validate());
+
+        // Boards from before slots were sent are laid out from the left
+        let mut json: serde_json::Value = serde_json::to_value(&board).unwrap();
+        json.as_object_mut().unwrap().remove("slots");
+        let old_board: Board = serde_json::from_value(json).unwrap();
+        assert_eq!(old_board.slot_layout()[2], vec![Some(slots[2][0].unwrap()), slots[2][1], Some(tier[3])]);
+    }
+
+    #[test]
+    pub fn test_terminal_and_forced_moves() {
+        // At the start there is a choice to make
+        let mut game = Game::new(2, Arc::new(Card::all()));
+        assert!(!game.is_terminal());
+        assert_eq!(game.only_forced_move(), None);
+
+        // Without a noble to attract, the player can only pass
+        game.play_action(TakeDouble(Ruby));
+        game.nobles.clear();
+        assert!(!game.is_terminal());
+        assert_eq!(game.only_forced_move(), Some(Pass));
+        game.play_action(Pass);
+        assert_eq!(game.only_forced_move(), Some(Continue));
+
+        // The game ends with the round once someone has enough points
+        game.players[0].add_points(15);
+        end_round(&mut game);
+        assert!(game.is_terminal());
+        assert!(game.game_over());
+        assert_eq!(game.only_forced_move(), None);
+    }
+
+    #[test]
+    pub fn test_outcome_winner() {
+        let mut game = Game::new(2, Arc::new(Card::all()));
+        game.players[1].add_points(16);
+        game.players[0].add_points(15);
+        end_round(&mut game);
+
+        assert_eq!(game.outcome(), GameOutcome::Winner(1));
+    }
+
+    #[test]
+    pub fn test_outcome_winner_fewest_developments() {
+        let mut game = Game::new(3, Arc::new(Card::all()));
+        let card = Card::all()[0];
+        game.players[0].add_points(15);
+        game.players[0].purchase_card(&card, &Gems::empty());
+        game.players[2].add_points(15);
+        end_round(&mut game);
+
+        assert_eq!(game.outcome(), GameOutcome::Winner(2));
+        assert_eq!(game.get_winner(), Some(2));
+    }
+
+    #[test]
+    pub fn test_outcome_draw_on_full_tie() {
+        let mut game = Game::new(2, Arc::new(Card::all()));
+        game.players[0].add_points(15);
+        game.players[1].add_points(15);
+        end_round(&mut game);
+
+        assert_eq!(game.outcome(), GameOutcome::Draw);
+        assert_eq!(game.get_winner(), None);
+    }
+
+    #[test]
+    pub fn test_low_deadlock_threshold() {
+        let mut game = Game::new(2, Arc::new(Card::all()));
+        assert_eq!(game.deadlock_threshold(), 4);
+        game.set_deadlock_threshold(1);
+
+        // Passing on nobles after taking gems is enough to deadlock
+        let take = game
+            .get_legal_actions()
+            .unwrap()
+            .into_iter()
+            .find(|a| matches!(a, TakeDistinct(_)))
+            .unwrap();
+        game.play_action(take);
+        assert!(!game.game_over());
+        game.play_action(Pass);
+
+        assert!(game.game_over());
+        assert_eq!(game.get_winner(), None);
+        assert_eq!(game.outcome(), GameOutcome::Draw);
+    }
+
+    #[test]
+    pub fn test_outcome_draw_on_deadlock() {
+        let mut game = Game::new(2, Arc::new(Card::all()));
+        game.players[0].add_points(3);
+        game.deadlock_count = 4;
+
+        assert_eq!(game.outcome(), GameOutcome::Draw);
+    }
+
     #[test]
     pub fn test_init_legal_rounds_specific_board_state() {
         let mut game = Game::new(3, Arc::new(Card::all()));
@@ -834,15 +2225,15 @@ pub mod test {
         let actions = game.get_legal_actions().unwrap();
         assert_eq!(actions.len(), 30 - 5 - 6 + 1);
 
-        game.play_action(Purchase((
-            8,
-            Gems::from_vec(&vec![
+        game.play_action(Purchase {
+            card: 8,
+            payment: Gems::from_vec(&vec![
                 Gem::Diamond,
                 Gem::Emerald,
                 Gem::Ruby,
                 Gem::Onyx,
             ]),
-        )));
+        });
         game.play_action(Pass);
         game.play_action(Continue);
 
@@ -874,6 +2265,43 @@ pub mod test {
         assert_eq!(actions.len(), 30);
     }
 
+    // (face up cards, reserved cards of each player)
+    type DrawSnapshot = (Vec<Vec<CardId>>, Vec<Vec<CardId>>);
+
+    /// Reserve cards with both players, recording the board and every
+    /// reserved card after each turn
+    fn reserve_sequence(seed: u64) -> Vec<DrawSnapshot> {
+        let mut game = Game::new_seeded(2, Arc::new(Card::all()), seed);
+        let mut sequence = vec![];
+        for turn in 0..6 {
+            let tier = turn % 3;
+            if turn % 2 == 0 {
+                let top_card = game.decks[tier][0].id();
+                game.play_action(ReserveHidden(tier));
+                assert!(game.players[game.current_player].has_reserved_card(top_card));
+            } else {
+                let top_card = game.decks[tier][0].id();
+                game.play_action(Reserve(game.cards()[tier][0]));
+                assert!(game.cards()[tier].contains(&top_card));
+            }
+            game.play_action(Pass);
+            game.play_action(Continue);
+
+            let reserved = game.players.iter().map(|p| p.all_reserved()).collect();
+            sequence.push((game.cards(), reserved));
+        }
+        sequence
+    }
+
+    #[test]
+    pub fn test_seeded_draws_are_reproducible() {
+        assert_eq!(reserve_sequence(7), reserve_sequence(7));
+        assert_eq!(
+            Game::new_seeded(4, Arc::new(Card::all()), 11).nobles(),
+            Game::new_seeded(4, Arc::new(Card::all()), 11).nobles()
+        );
+    }
+
     #[test]
     pub fn test_randomized_rollout() {
         let card_lookup = Arc::new(Card::all());
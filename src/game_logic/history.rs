@@ -36,6 +36,11 @@ impl GameHistory {
         GameHistory::from(new_history)
     }
 
+    /// Iterate over the (player number, action) pairs without cloning the history
+    pub fn iter(&self) -> impl Iterator<Item = &(usize, Action)> {
+        self.history.iter()
+    }
+
     pub fn add(&mut self, player_num: usize, action: Action) {
         self.history.push((player_num, action));
     }
@@ -97,6 +102,185 @@ impl GameHistory {
     pub fn num_actions(&self) -> usize {
         self.history.len()
     }
+
+    /// Replay the history against the game it started from and record
+    /// the points of every player at the end of each move
+    pub fn score_timeline(&self, initial_game: &Game) -> Vec<Vec<u8>> {
+        let mut game = initial_game.clone();
+        self.group_by_player()
+            .into_iter()
+            .map(|actions| {
+                for (_, action) in actions {
+                    game.play_action(action);
+                }
+                game.players().iter().map(|p| p.total_points()).collect()
+            })
+            .collect()
+    }
+
+    /// Return all actions in the history that change the game state,
+    /// skipping the Pass and Continue markers
+    pub fn meaningful_actions(&self) -> Vec<(usize, Action)> {
+        GameHistory::meaningful_entries(&self.history).cloned().collect()
+    }
+
+    /// The entries that change the game state among any run of the history,
+    /// e.g. a single turn from `group_by_player`
+    pub fn meaningful_entries(entries: &[(usize, Action)]) -> impl Iterator<Item = &(usize, Action)> {
+        entries.iter().filter(|(_, action)| !action.is_no_op())
+    }
+
+    /// Whether both histories, replayed from `initial`, leave the same position
+    /// on the table (players, bank, face up cards, decks and nobles).
+    /// Whose turn it is and the phase are not compared, so histories that only
+    /// differ by trailing Pass or Continue markers are equivalent
+    pub fn equivalent(&self, other: &GameHistory, initial: &Game) -> bool {
+        let replay = |history: &GameHistory| {
+            let mut game = initial.clone();
+            game.advance_history_with(history);
+            game
+        };
+        let (a, b) = (replay(self), replay(other));
+        a.players() == b.players()
+            && a.bank() == b.bank()
+            && a.cards() == b.cards()
+            && a.deck_counts() == b.deck_counts()
+            && a.nobles() == b.nobles()
+    }
+}
+
+/// Why an action recorded in a history could not have been played
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AnomalyKind {
+    /// The game had already ended
+    GameOver,
+    /// It was another player's turn
+    WrongPlayer,
+    /// The action is never legal in the phase the game was in
+    WrongPhase,
+    /// The card is on offer but the player could not pay for it this way
+    UnaffordablePurchase,
+    /// The bank did not hold the gems that were taken
+    UnavailableGems,
+    /// Any other reason the action was not legal
+    Illegal,
+}
+
+/// An action in a history that was not legal when it was recorded
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Anomaly {
+    /// The position of the action in the history
+    pub index: usize,
+    pub player_num: usize,
+    pub action: Action,
+    pub kind: AnomalyKind,
+}
+
+impl GameHistory {
+    /// Replay the history from `initial` and report every action that was
+    /// not legal at that point, e.g. for a game record from an untrusted client.
+    /// Flagged actions are skipped, so the actions after them are checked
+    /// against the game as if they were never played
+    pub fn detect_anomalies(&self, initial: &Game) -> Vec<Anomaly> {
+        let mut game = initial.clone();
+        let mut anomalies = Vec::new();
+        for (index, (player_num, action)) in self.iter().enumerate() {
+            let kind = match game.get_legal_actions() {
+                None => Some(AnomalyKind::GameOver),
+                Some(_) if *player_num != game.current_player_num() => Some(AnomalyKind::WrongPlayer),
+                Some(legal) if legal.contains(action) => None,
+                Some(_) => Some(classify_anomaly(&game, action)),
+            };
+            match kind {
+                Some(kind) => anomalies.push(Anomaly {
+                    index,
+                    player_num: *player_num,
+                    action: action.clone(),
+                    kind,
+                }),
+                None => game.play_action(action.clone()),
+            }
+        }
+        anomalies
+    }
+}
+
+/// Explain why an action the current player took is not legal in the game
+fn classify_anomaly(game: &Game, action: &Action) -> AnomalyKind {
+    if !game.is_phase_correct_for(action.clone()) {
+        return AnomalyKind::WrongPhase;
+    }
+    let bank = game.bank();
+    match action {
+        Action::TakeDouble(color) if *color != Gem::Gold && bank[*color] < 4 => {
+            AnomalyKind::UnavailableGems
+        }
+        Action::TakeDistinct(colors) if colors.iter().any(|color| bank[*color] < 1) => {
+            AnomalyKind::UnavailableGems
+        }
+        Action::Purchase { card: card_id, .. } => {
+            let on_board = game.cards().iter().flatten().any(|id| id == card_id);
+            if on_board || game.current_player().has_reserved_card(*card_id) {
+                AnomalyKind::UnaffordablePurchase
+            } else {
+                AnomalyKind::Illegal
+            }
+        }
+        _ => AnomalyKind::Illegal,
+    }
+}
+
+/// A GameHistory with the Pass and Continue markers removed, these are
+/// always the only legal action when played so they can be recovered
+/// by replaying the choices against the same initial game
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompressedHistory {
+    pub choices: Vec<Action>,
+    pub num_actions: usize,
+}
+
+impl GameHistory {
+    pub fn compress(&self) -> CompressedHistory {
+        CompressedHistory {
+            choices: GameHistory::meaningful_entries(&self.history)
+                .map(|(_, action)| action.clone())
+                .collect(),
+            num_actions: self.num_actions(),
+        }
+    }
+}
+
+impl CompressedHistory {
+    /// Rebuild the full history by replaying the stored choices against
+    /// the game the original history started from
+    pub fn decompress(&self, initial_game: &Game) -> GameHistory {
+        let mut game = initial_game.clone();
+        let mut history = GameHistory::new();
+        let mut choices = self.choices.iter();
+
+        while history.num_actions() < self.num_actions {
+            let forced = match game.get_legal_actions() {
+                Some(actions) if actions.len() == 1 && actions[0].is_no_op() => {
+                    Some(actions[0].clone())
+                }
+                Some(_) => None,
+                None => break,
+            };
+
+            let action = match forced {
+                Some(action) => action,
+                None => match choices.next() {
+                    Some(action) => action.clone(),
+                    None => break,
+                },
+            };
+
+            history.add(game.current_player_num(), action.clone());
+            game.play_action(action);
+        }
+
+        history
+    }
 }
 
 impl IntoIterator for GameHistory {
@@ -107,3 +291,151 @@ impl IntoIterator for GameHistory {
         self.history.into_iter()
     }
 }
+
+impl<'a> IntoIterator for &'a GameHistory {
+    type Item = &'a (usize, Action);
+    type IntoIter = std::slice::Iter<'a, (usize, Action)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.history.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::seq::SliceRandom;
+
+    #[test]
+    fn test_meaningful_actions_skip_markers() {
+        let history = GameHistory::from(vec![
+            (0, Action::TakeDouble(Gem::Ruby)),
+            (0, Action::Pass),
+            (0, Action::Continue),
+            (1, Action::Reserve(4)),
+            (1, Action::Pass),
+            (1, Action::Continue),
+            (0, Action::Pass),
+            (0, Action::Continue),
+        ]);
+
+        assert_eq!(
+            history.meaningful_actions(),
+            vec![(0, Action::TakeDouble(Gem::Ruby)), (1, Action::Reserve(4))]
+        );
+    }
+
+    #[test]
+    fn test_iterate_by_reference() {
+        let history = GameHistory::from(vec![
+            (0, Action::TakeDouble(Gem::Ruby)),
+            (0, Action::Pass),
+            (0, Action::Continue),
+            (1, Action::Reserve(4)),
+        ]);
+
+        let players: Vec<usize> = history.iter().map(|(player_num, _)| *player_num).collect();
+        assert_eq!(players, vec![0, 0, 0, 1]);
+        let mut reserves = 0;
+        for (_, action) in &history {
+            if matches!(action, Action::Reserve(_)) {
+                reserves += 1;
+            }
+        }
+        assert_eq!(reserves, 1);
+
+        // The history was only borrowed, so it can still be used as is
+        assert_eq!(history.num_actions(), 4);
+        let mut game = Game::new(2, std::sync::Arc::new(crate::card::Card::all()));
+        game.advance_history_with(&GameHistory::from(history.history[..3].to_vec()));
+        assert_eq!(game.current_player_num(), 1);
+    }
+
+    #[test]
+    fn test_equivalent_ignores_trailing_markers() {
+        let initial_game = Game::new(2, std::sync::Arc::new(crate::card::Card::all()));
+        let mut game = initial_game.clone();
+        game.play_action(Action::TakeDouble(Gem::Ruby));
+        let taken = game.history();
+        game.play_action(Action::Pass);
+        game.play_action(Action::Continue);
+        let passed = game.history();
+
+        assert!(taken.equivalent(&passed, &initial_game));
+        assert!(passed.equivalent(&taken, &initial_game));
+
+        let mut other = initial_game.clone();
+        other.play_action(Action::TakeDouble(Gem::Onyx));
+        assert!(!other.history().equivalent(&passed, &initial_game));
+        assert!(!GameHistory::new().equivalent(&taken, &initial_game));
+    }
+
+    #[test]
+    fn test_compress_round_trip() {
+        let initial_game = Game::new(3, std::sync::Arc::new(crate::card::Card::all()));
+        let mut game = initial_game.clone();
+        // Stop well before any player could run out of legal moves
+        let mut rng = rand::thread_rng();
+        for _ in 0..45 {
+            let actions = game.get_legal_actions().expect("Game ended early");
+            let action = actions.choose(&mut rng).unwrap().clone();
+            game.play_action(action);
+        }
+        let history = game.history();
+
+        let compressed = history.compress();
+        assert!(compressed.choices.len() < history.num_actions());
+        assert!(compressed.choices.iter().all(|action| !action.is_no_op()));
+
+        let decompressed = compressed.decompress(&initial_game);
+        assert_eq!(decompressed.history, history.history);
+
+        let mut replayed = initial_game.clone();
+        replayed.advance_history_with(&decompressed);
+        assert_eq!(replayed.history().history, history.history);
+        assert_eq!(replayed.bank(), game.bank());
+        assert_eq!(replayed.current_player_num(), game.current_player_num());
+        assert_eq!(replayed.phase(), game.phase());
+        for (a, b) in replayed.players().iter().zip(game.players().iter()) {
+            assert_eq!(a.total_points(), b.total_points());
+            assert_eq!(a.gems(), b.gems());
+            assert_eq!(a.all_reserved(), b.all_reserved());
+        }
+    }
+
+    #[test]
+    fn test_detect_anomalies_in_tampered_history() {
+        let initial_game = Game::new(2, std::sync::Arc::new(crate::card::Card::all()));
+        let card_id = initial_game.cards()[2][0];
+        let history = GameHistory::from(vec![
+            (0, Action::TakeDouble(Gem::Ruby)),
+            (0, Action::Pass),
+            (0, Action::Continue),
+            // Tier 3 cards cannot be bought for free
+            (1, Action::Purchase { card: card_id, payment: Gems::empty() }),
+            // Only 2 rubies are left
+            (1, Action::TakeDouble(Gem::Ruby)),
+            (0, Action::TakeDouble(Gem::Onyx)),
+            (1, Action::Continue),
+            (1, Action::TakeDouble(Gem::Onyx)),
+        ]);
+
+        let anomalies = history.detect_anomalies(&initial_game);
+        let found: Vec<(usize, AnomalyKind)> = anomalies.iter().map(|a| (a.index, a.kind)).collect();
+        assert_eq!(
+            found,
+            vec![
+                (3, AnomalyKind::UnaffordablePurchase),
+                (4, AnomalyKind::UnavailableGems),
+                (5, AnomalyKind::WrongPlayer),
+                (6, AnomalyKind::WrongPhase),
+            ]
+        );
+        assert_eq!(anomalies[2].player_num, 0);
+        assert_eq!(anomalies[2].action, Action::TakeDouble(Gem::Onyx));
+
+        // Untampered games have nothing to report
+        let clean = GameHistory::from(history.history[..3].to_vec());
+        assert!(clean.detect_anomalies(&initial_game).is_empty());
+    }
+}
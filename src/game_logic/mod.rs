@@ -4,7 +4,8 @@ use crate::nobles::*;
 use crate::player::Player;
 use crate::gems::Gems;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::cmp::Ordering;
+use std::collections::{BTreeSet, HashSet};
 
 pub mod board;
 pub mod game;
@@ -14,8 +15,9 @@ pub use self::board::*;
 pub use self::game::*;
 pub use self::history::*;
 
-#[derive(Debug, Clone)]
-enum Phase {
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Phase {
+    #[default]
     PlayerStart,            // Take some player action
     PlayerGemCapExceeded,   // [Optional] Player has > 10 gems
     NobleAction,            // See if any nobles get attracted (multiple may be attracted)
@@ -28,7 +30,10 @@ pub enum Action {
     TakeDistinct(HashSet<Gem>),
     Reserve(CardId),
     ReserveHidden(usize),
-    Purchase((CardId, Gems)),
+    /// Purchase a face up or reserved card, paying with the given gems.
+    /// Serialized as a `[card, payment]` pair for wire compatibility
+    #[serde(serialize_with = "serialize_purchase", deserialize_with = "deserialize_purchase")]
+    Purchase { card: CardId, payment: Gems },
 
     Discard(Gems),
 
@@ -43,20 +48,129 @@ pub enum Action {
     Continue,
 }
 
+fn serialize_purchase<S>(card: &CardId, payment: &Gems, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    (card, payment).serialize(serializer)
+}
+
+fn deserialize_purchase<'de, D>(deserializer: D) -> Result<(CardId, Gems), D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    <(CardId, Gems)>::deserialize(deserializer)
+}
+
+/// The kind of an action, without its payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ActionKind {
+    TakeDouble,
+    TakeDistinct,
+    Reserve,
+    ReserveHidden,
+    Purchase,
+    Discard,
+    AttractNoble,
+    Pass,
+    Continue,
+}
+
+/// The result of a game from the perspective of the
+/// game engine
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GameOutcome {
+    /// The player with the given index has won the game
+    Winner(usize),
+    /// The game is over, but no single player can be declared the winner
+    Draw,
+    /// The game is still in progress
+    Undecided,
+}
+
+impl Action {
+    /// Whether this action is a marker that does not change the
+    /// state of the board (Pass or Continue)
+    pub fn is_no_op(&self) -> bool {
+        matches!(self, Action::Pass | Action::Continue)
+    }
+
+    pub fn kind(&self) -> ActionKind {
+        match self {
+            Action::TakeDouble(_) => ActionKind::TakeDouble,
+            Action::TakeDistinct(_) => ActionKind::TakeDistinct,
+            Action::Reserve(_) => ActionKind::Reserve,
+            Action::ReserveHidden(_) => ActionKind::ReserveHidden,
+            Action::Purchase { .. } => ActionKind::Purchase,
+            Action::Discard(_) => ActionKind::Discard,
+            Action::AttractNoble(_) => ActionKind::AttractNoble,
+            Action::Pass => ActionKind::Pass,
+            Action::Continue => ActionKind::Continue,
+        }
+    }
+
+    /// The position of the variant in the declaration of Action
+    fn variant_index(&self) -> u8 {
+        match self {
+            Action::TakeDouble(_) => 0,
+            Action::TakeDistinct(_) => 1,
+            Action::Reserve(_) => 2,
+            Action::ReserveHidden(_) => 3,
+            Action::Purchase { .. } => 4,
+            Action::Discard(_) => 5,
+            Action::AttractNoble(_) => 6,
+            Action::Pass => 7,
+            Action::Continue => 8,
+        }
+    }
+}
+
+/// Actions are ordered by variant (in order of declaration), then by their payload,
+/// where the colors of TakeDistinct are compared as a sorted list
+impl Ord for Action {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Action::TakeDouble(a), Action::TakeDouble(b)) => a.cmp(b),
+            (Action::TakeDistinct(a), Action::TakeDistinct(b)) => {
+                let mut a: Vec<&Gem> = a.iter().collect();
+                let mut b: Vec<&Gem> = b.iter().collect();
+                a.sort();
+                b.sort();
+                a.cmp(&b)
+            }
+            (Action::Reserve(a), Action::Reserve(b)) => a.cmp(b),
+            (Action::ReserveHidden(a), Action::ReserveHidden(b)) => a.cmp(b),
+            (
+                Action::Purchase { card: a, payment: pa },
+                Action::Purchase { card: b, payment: pb },
+            ) => (a, pa).cmp(&(b, pb)),
+            (Action::Discard(a), Action::Discard(b)) => a.cmp(b),
+            (Action::AttractNoble(a), Action::AttractNoble(b)) => a.cmp(b),
+            _ => self.variant_index().cmp(&other.variant_index()),
+        }
+    }
+}
+
+impl PartialOrd for Action {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 pub fn choose_distinct_gems(
     gems: &mut Gems,
     running: &mut Gems,
     num_chosen: u32,
-) -> HashSet<Gems> {
-    let mut total_choices = HashSet::new();
+) -> BTreeSet<Gems> {
+    let mut total_choices = BTreeSet::new();
     if num_chosen == 0 {
         total_choices.insert(running.clone());
         return total_choices;
     }
     // Pick one to discard and recurse
-    for color in Gem::all_expect_gold() {
-        if gems[color] > 0 {
-            if running[color] > 0 {
+    for color in Gem::ALL_EXCEPT_GOLD {
+        if gems.contains(color) {
+            if running.contains(color) {
                 continue;
             }
 
@@ -74,15 +188,15 @@ pub fn choose_distinct_gems(
     total_choices
 }
 
-pub fn choose_gems(gems: &mut Gems, running: &mut Gems, num_chosen: u32) -> HashSet<Gems> {
-    let mut total_choices = HashSet::new();
+pub fn choose_gems(gems: &mut Gems, running: &mut Gems, num_chosen: u32) -> BTreeSet<Gems> {
+    let mut total_choices = BTreeSet::new();
     if num_chosen == 0 {
         total_choices.insert(running.clone());
         return total_choices;
     }
     // Pick one to discard and recurse
-    for color in Gem::all() {
-        if gems[color] > 0 {
+    for color in Gem::ALL {
+        if gems.contains(color) {
             gems[color] -= 1;
             running[color] += 1;
 
@@ -96,3 +210,23 @@ pub fn choose_gems(gems: &mut Gems, running: &mut Gems, num_chosen: u32) -> Hash
 
     total_choices
 }
+
+/// All distinct ways to discard `num_discarded` tokens from `held`,
+/// ordered so that discards which leave the smallest largest pile come first.
+/// The first entry is the canonical default discard
+pub fn choose_discards(held: &Gems, num_discarded: u32) -> Vec<Gems> {
+    let mut gems = *held;
+    let mut running = Gems::empty();
+    let mut discards: Vec<Gems> = choose_gems(&mut gems, &mut running, num_discarded)
+        .into_iter()
+        .collect();
+
+    discards.sort_by_key(|discard| {
+        let remaining = *held - *discard;
+        let mut piles: Vec<i8> = Gem::ALL.iter().map(|&c| remaining[c]).collect();
+        piles.sort_unstable_by(|a, b| b.cmp(a));
+        let counts: Vec<i8> = Gem::ALL.iter().map(|&c| -discard[c]).collect();
+        (piles, counts)
+    });
+    discards
+}
@@ -1,5 +1,6 @@
 use serde::{Deserialize, Serialize};
-#[derive(PartialEq, Copy, Clone, Debug, Eq, Hash, Serialize, Deserialize)]
+/// Gems are ordered the same way as `Gem::all()`
+#[derive(PartialEq, Copy, Clone, Debug, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 pub enum Gem {
     Onyx,
     Sapphire,
@@ -10,23 +11,55 @@ pub enum Gem {
 }
 
 impl Gem {
+    /// Every color, in canonical order
+    pub const ALL: [Gem; 6] = [
+        Gem::Onyx,
+        Gem::Sapphire,
+        Gem::Emerald,
+        Gem::Ruby,
+        Gem::Diamond,
+        Gem::Gold,
+    ];
+
+    /// Every color except the wild (gold) tokens, in canonical order
+    pub const ALL_EXCEPT_GOLD: [Gem; 5] = [
+        Gem::Onyx,
+        Gem::Sapphire,
+        Gem::Emerald,
+        Gem::Ruby,
+        Gem::Diamond,
+    ];
+
     pub fn all_expect_gold() -> Vec<Gem> {
-        vec![
-            Gem::Onyx,
-            Gem::Sapphire,
-            Gem::Emerald,
-            Gem::Ruby,
-            Gem::Diamond,
-        ]
+        Gem::ALL_EXCEPT_GOLD.to_vec()
     }
     pub fn all() -> Vec<Gem> {
-        vec![
-            Gem::Onyx,
-            Gem::Sapphire,
-            Gem::Emerald,
+        Gem::ALL.to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sort_matches_all() {
+        let mut gems = vec![
             Gem::Ruby,
-            Gem::Diamond,
             Gem::Gold,
-        ]
+            Gem::Onyx,
+            Gem::Diamond,
+            Gem::Emerald,
+            Gem::Sapphire,
+        ];
+        gems.sort();
+        assert_eq!(gems, Gem::all());
+    }
+
+    #[test]
+    fn test_const_arrays_match_all() {
+        assert_eq!(Gem::ALL.to_vec(), Gem::all());
+        assert_eq!(Gem::ALL_EXCEPT_GOLD.to_vec(), Gem::all_expect_gold());
+        assert_eq!(&Gem::ALL[..5], &Gem::ALL_EXCEPT_GOLD[..]);
     }
 }
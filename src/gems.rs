@@ -1,19 +1,48 @@
+use crate::card::Cost;
+use crate::game_logic::Action;
 use crate::gem::Gem;
+use derive_more::{Display, Error};
 use serde::{Deserialize, Serialize};
 use std::cmp::{max, min};
 use std::collections::HashSet;
 use std::ops::{Add, AddAssign, Index, IndexMut, Sub, SubAssign};
 
-#[derive(PartialEq, Eq, Copy, Clone, Debug, Hash, Serialize, Deserialize)]
+/// Colors with a count of zero are left out when serialized
+/// and default to zero when missing during deserialization
+///
+/// Ordering compares the counts color by color in the order of `Gem::all()`
+#[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Debug, Hash, Serialize, Deserialize)]
 pub struct Gems {
+    #[serde(default, skip_serializing_if = "is_zero")]
     pub onyx: i8,
+    #[serde(default, skip_serializing_if = "is_zero")]
     pub sapphire: i8,
+    #[serde(default, skip_serializing_if = "is_zero")]
     pub emerald: i8,
+    #[serde(default, skip_serializing_if = "is_zero")]
     pub ruby: i8,
+    #[serde(default, skip_serializing_if = "is_zero")]
     pub diamond: i8,
+    #[serde(default, skip_serializing_if = "is_zero")]
     pub gold: i8,
 }
 
+fn is_zero(count: &i8) -> bool {
+    *count == 0
+}
+
+#[derive(Debug, Display, Error, PartialEq, Eq)]
+pub enum TakeError {
+    #[display(fmt = "Cannot take a negative number of gems")]
+    NegativeCount,
+    #[display(fmt = "Gold cannot be taken, only received by reserving a card")]
+    ContainsGold,
+    #[display(fmt = "At most 3 gems can be taken in a turn")]
+    TooManyTokens,
+    #[display(fmt = "Gems must be either 2 of a single color or at most 3 distinct colors")]
+    Ambiguous,
+}
+
 impl Gems {
 
     pub fn all() -> Vec<Gem> {
@@ -52,6 +81,17 @@ impl Gems {
         gems
     }
 
+    /// Build a bundle from (color, count) pairs, e.g. `&[(Gem::Ruby, 3), (Gem::Onyx, 2)]`,
+    /// counts for a color given more than once are added together
+    pub fn from_counts(counts: &[(Gem, i8)]) -> Gems {
+        let mut gems = Gems::empty();
+        for &(color, count) in counts {
+            assert!(count >= 0, "Cannot build gems with a negative count of {:?}", color);
+            gems[color] += count;
+        }
+        gems
+    }
+
     pub fn from_set(set: &HashSet<Gem>) -> Gems {
         let mut gems = Gems::empty();
         for color in set {
@@ -60,15 +100,19 @@ impl Gems {
         gems
     }
 
-    pub fn total(&self) -> u32 {
-        debug_assert!(self.legal(), "Illegal token state: {:?}", self);
-        self.onyx as u32
-            + self.sapphire as u32
-            + self.emerald as u32
-            + self.ruby as u32
-            + self.diamond as u32
-            + self.gold as u32
+    /// The total number of tokens, negative counts are summed as they are
+    pub fn total(&self) -> i32 {
+        Gem::ALL.into_iter().map(|color| self[color] as i32).sum()
+    }
+
+    /// The total number of tokens, or None if any color is negative
+    pub fn checked_total(&self) -> Option<u32> {
+        if !self.legal() {
+            return None;
+        }
+        Some(self.total() as u32)
     }
+
     pub fn legal(&self) -> bool {
         self.onyx >= 0
             && self.sapphire >= 0
@@ -130,12 +174,57 @@ impl Gems {
         }
     }
 
+    /// Subtract color by color, returning None if any color would go negative
+    /// (or overflow)
+    pub fn checked_sub(&self, other: &Gems) -> Option<Gems> {
+        let gems = Gems {
+            onyx: self.onyx.checked_sub(other.onyx)?,
+            sapphire: self.sapphire.checked_sub(other.sapphire)?,
+            emerald: self.emerald.checked_sub(other.emerald)?,
+            ruby: self.ruby.checked_sub(other.ruby)?,
+            diamond: self.diamond.checked_sub(other.diamond)?,
+            gold: self.gold.checked_sub(other.gold)?,
+        };
+        gems.legal().then_some(gems)
+    }
+
+    /// Subtract color by color, flooring each color at zero instead of going negative,
+    /// e.g. the gems still needed for a cost after spending what is on hand.
+    /// Never panics, counts that would overflow are clamped to `i8::MAX`
+    pub fn saturating_sub(&self, other: &Gems) -> Gems {
+        Gems {
+            onyx: self.onyx.saturating_sub(other.onyx).max(0),
+            sapphire: self.sapphire.saturating_sub(other.sapphire).max(0),
+            emerald: self.emerald.saturating_sub(other.emerald).max(0),
+            ruby: self.ruby.saturating_sub(other.ruby).max(0),
+            diamond: self.diamond.saturating_sub(other.diamond).max(0),
+            gold: self.gold.saturating_sub(other.gold).max(0),
+        }
+    }
+
+    /// The tokens only in `self` and the tokens only in `other`, counting
+    /// each color as a multiset. E.g. the gems a player held before a purchase
+    /// against the gems after it gives back what was paid
+    pub fn difference(&self, other: &Gems) -> (Gems, Gems) {
+        (self.saturating_sub(other), other.saturating_sub(self))
+    }
+
     pub fn one(color: Gem) -> Gems {
         let mut gems = Gems::empty();
         gems[color] = 1;
         gems
     }
 
+    /// The number of tokens of the given color, same as `self[gem]`
+    pub fn count_of(&self, gem: Gem) -> i8 {
+        self[gem]
+    }
+
+    /// Whether there is at least one token of the given color
+    pub fn contains(&self, gem: Gem) -> bool {
+        self[gem] > 0
+    }
+
     pub fn distinct(&self) -> usize {
         let mut count = 0;
         if self.onyx > 0 {
@@ -155,6 +244,56 @@ impl Gems {
         }
         count
     }
+    /// The colored part of the bundle as a Cost, any gold is silently dropped.
+    /// Unlike `Cost::from_gems`, which refuses bundles that hold gold
+    pub fn as_cost_dropping_gold(&self) -> Cost {
+        Cost::from_gems(&Gems { gold: 0, ..*self })
+    }
+
+    /// A one line view of all six colors, including negative counts
+    /// left behind by intermediate arithmetic, e.g. `o:-1 s:2 e:0 r:0 d:0 g:1`
+    pub fn debug_compact(&self) -> String {
+        Gem::ALL
+            .iter()
+            .zip(["o", "s", "e", "r", "d", "g"])
+            .map(|(&color, label)| format!("{}:{}", label, self[color]))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Whether adding `other` to this bundle would overflow the `i8` count of any color,
+    /// which can happen with very large custom banks
+    pub fn would_overflow(&self, other: &Gems) -> bool {
+        Gem::ALL
+            .iter()
+            .any(|&color| self[color].checked_add(other[color]).is_none())
+    }
+
+    /// Decide which take action this bundle describes, `TakeDouble` for 2 gems
+    /// of a single color and `TakeDistinct` for at most 3 gems of different colors.
+    /// Whether the bank can afford the take is not checked
+    pub fn classify_take(&self) -> Result<Action, TakeError> {
+        if !self.legal() {
+            return Err(TakeError::NegativeCount);
+        }
+        if self.gold > 0 {
+            return Err(TakeError::ContainsGold);
+        }
+        if self.total() > 3 {
+            return Err(TakeError::TooManyTokens);
+        }
+        let colors = Gem::all_expect_gold();
+        if let Some(&color) = colors.iter().find(|&&color| self[color] == 2) {
+            if self.total() == 2 {
+                return Ok(Action::TakeDouble(color));
+            }
+        }
+        if colors.iter().all(|&color| self[color] <= 1) {
+            return Ok(Action::TakeDistinct(self.to_set()));
+        }
+        Err(TakeError::Ambiguous)
+    }
+
     pub fn can_buy(&self, other: &Gems) -> bool {
         unimplemented!()
     }
@@ -190,6 +329,12 @@ impl IndexMut<Gem> for Gems {
 
 impl AddAssign for Gems {
     fn add_assign(&mut self, other: Gems) {
+        debug_assert!(
+            !self.would_overflow(&other),
+            "Adding {} to {} overflows the gem counts",
+            other.debug_compact(),
+            self.debug_compact()
+        );
         self.onyx += other.onyx;
         self.sapphire += other.sapphire;
         self.emerald += other.emerald;
@@ -216,6 +361,12 @@ impl Add for Gems {
     type Output = Gems;
 
     fn add(self, other: Gems) -> Gems {
+        debug_assert!(
+            !self.would_overflow(&other),
+            "Adding {} to {} overflows the gem counts",
+            other.debug_compact(),
+            self.debug_compact()
+        );
         let gems = Gems {
             onyx: self.onyx + other.onyx,
             sapphire: self.sapphire + other.sapphire,
@@ -245,3 +396,207 @@ impl Sub for Gems {
         gems
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_as_cost_dropping_gold() {
+        let gems = Gems::from_counts(&[(Gem::Ruby, 2), (Gem::Diamond, 1), (Gem::Gold, 3)]);
+        let cost = gems.as_cost_dropping_gold();
+        assert_eq!(cost.ruby, 2);
+        assert_eq!(cost.diamond, 1);
+        assert_eq!(cost.to_gems(), gems - Gems::from_counts(&[(Gem::Gold, 3)]));
+    }
+
+    #[test]
+    fn test_from_counts_matches_from_vec() {
+        let gems = Gems::from_counts(&[(Gem::Ruby, 3), (Gem::Onyx, 2), (Gem::Gold, 1)]);
+        let expected = Gems::from_vec(&vec![
+            Gem::Ruby,
+            Gem::Ruby,
+            Gem::Ruby,
+            Gem::Onyx,
+            Gem::Onyx,
+            Gem::Gold,
+        ]);
+        assert_eq!(gems, expected);
+        assert_eq!(Gems::from_counts(&[]), Gems::empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_from_counts_rejects_negative() {
+        Gems::from_counts(&[(Gem::Ruby, -1)]);
+    }
+
+    #[test]
+    fn test_debug_compact_shows_negatives() {
+        let gems = Gems::one(Gem::Sapphire) + Gems::one(Gem::Sapphire) + Gems::one(Gem::Gold)
+            - Gems::one(Gem::Onyx);
+        assert_eq!(gems.debug_compact(), "o:-1 s:2 e:0 r:0 d:0 g:1");
+    }
+
+    #[test]
+    fn test_sort_compares_colors_in_order() {
+        let onyx = Gems::one(Gem::Onyx);
+        let two_sapphire = Gems::from_counts(&[(Gem::Sapphire, 2)]);
+        let gold = Gems::one(Gem::Gold);
+        let onyx_ruby = Gems::from_counts(&[(Gem::Onyx, 1), (Gem::Ruby, 1)]);
+
+        let mut bundles = vec![onyx_ruby, gold, Gems::empty(), onyx, two_sapphire];
+        bundles.sort();
+        assert_eq!(
+            bundles,
+            vec![Gems::empty(), gold, two_sapphire, onyx, onyx_ruby]
+        );
+
+        // Ordering agrees with equality
+        assert_eq!(onyx.cmp(&Gems::one(Gem::Onyx)), std::cmp::Ordering::Equal);
+        assert!(onyx_ruby > onyx);
+    }
+
+    #[test]
+    fn test_checked_total_legal() {
+        let gems = Gems::start(2);
+        assert_eq!(gems.checked_total(), Some(25));
+        assert_eq!(gems.total(), 25);
+    }
+
+    #[test]
+    fn test_checked_total_negative() {
+        let mut gems = Gems::one(Gem::Ruby);
+        gems[Gem::Onyx] = -2;
+        assert_eq!(gems.checked_total(), None);
+        assert_eq!(gems.total(), -1);
+    }
+
+    #[test]
+    fn test_serialize_omits_zero_colors() {
+        let mut gems = Gems::empty();
+        gems[Gem::Ruby] = 2;
+        assert_eq!(serde_json::to_string(&gems).unwrap(), r#"{"ruby":2}"#);
+        assert_eq!(serde_json::to_string(&Gems::empty()).unwrap(), "{}");
+    }
+
+    #[test]
+    fn test_serialize_round_trip() {
+        let mut gems = Gems::start(3);
+        gems[Gem::Onyx] = 0;
+        gems[Gem::Sapphire] = -1;
+        let json = serde_json::to_string(&gems).unwrap();
+        assert_eq!(serde_json::from_str::<Gems>(&json).unwrap(), gems);
+
+        let full = r#"{"onyx":0,"sapphire":1,"emerald":0,"ruby":2,"diamond":0,"gold":0}"#;
+        let parsed: Gems = serde_json::from_str(full).unwrap();
+        assert_eq!(parsed, serde_json::from_str(r#"{"sapphire":1,"ruby":2}"#).unwrap());
+    }
+
+    #[test]
+    fn test_would_overflow_near_i8_limit() {
+        let almost_full = Gems::from_counts(&[(Gem::Ruby, 120), (Gem::Gold, 127)]);
+        assert!(!almost_full.would_overflow(&Gems::from_counts(&[(Gem::Ruby, 7)])));
+        assert!(almost_full.would_overflow(&Gems::from_counts(&[(Gem::Ruby, 8)])));
+        assert!(almost_full.would_overflow(&Gems::one(Gem::Gold)));
+        assert!(!almost_full.would_overflow(&Gems::one(Gem::Onyx)));
+        assert_eq!((almost_full + Gems::from_counts(&[(Gem::Ruby, 7)])).ruby, 127);
+    }
+
+    #[test]
+    #[should_panic(expected = "overflows the gem counts")]
+    #[cfg(debug_assertions)]
+    fn test_add_overflow_panics_with_message() {
+        let _ = Gems::from_counts(&[(Gem::Ruby, 127)]) + Gems::one(Gem::Ruby);
+    }
+
+    #[test]
+    fn test_saturating_sub_vs_checked_sub() {
+        let cost = Gems::from_counts(&[(Gem::Ruby, 3), (Gem::Onyx, 1)]);
+        let hand = Gems::from_counts(&[(Gem::Ruby, 1), (Gem::Onyx, 2), (Gem::Gold, 1)]);
+
+        assert_eq!(cost.checked_sub(&hand), None);
+        assert_eq!(cost.saturating_sub(&hand), Gems::from_counts(&[(Gem::Ruby, 2)]));
+
+        // Both agree whenever nothing goes negative
+        let enough = Gems::from_counts(&[(Gem::Ruby, 5), (Gem::Onyx, 1)]);
+        assert_eq!(enough.checked_sub(&cost), Some(Gems::from_counts(&[(Gem::Ruby, 2)])));
+        assert_eq!(Some(enough.saturating_sub(&cost)), enough.checked_sub(&cost));
+        assert_eq!(Gems::empty().saturating_sub(&hand), Gems::empty());
+    }
+
+    #[test]
+    fn test_sub_at_i8_limits_does_not_panic() {
+        let high = Gems { ruby: 127, onyx: -128, ..Gems::empty() };
+        let low = Gems { ruby: -2, onyx: 1, ..Gems::empty() };
+        assert_eq!(high.saturating_sub(&low), Gems { ruby: 127, ..Gems::empty() });
+        assert_eq!(high.checked_sub(&low), None);
+        assert_eq!(low.checked_sub(&high), None);
+    }
+
+    #[test]
+    fn test_difference_of_overlapping_bundles() {
+        let before = Gems::from_counts(&[(Gem::Ruby, 3), (Gem::Onyx, 1), (Gem::Gold, 1)]);
+        let after = Gems::from_counts(&[(Gem::Ruby, 1), (Gem::Onyx, 1), (Gem::Diamond, 2)]);
+        let (only_before, only_after) = before.difference(&after);
+        assert_eq!(only_before, Gems::from_counts(&[(Gem::Ruby, 2), (Gem::Gold, 1)]));
+        assert_eq!(only_after, Gems::from_counts(&[(Gem::Diamond, 2)]));
+
+        // Removing what is only on each side leaves the tokens they share
+        assert_eq!(before - only_before, after - only_after);
+        assert_eq!(after.difference(&before), (only_after, only_before));
+        assert_eq!(before.difference(&before), (Gems::empty(), Gems::empty()));
+    }
+
+    #[test]
+    fn test_count_of_and_contains() {
+        let gems = Gems::from_counts(&[(Gem::Ruby, 2), (Gem::Gold, 1)]);
+        assert_eq!(gems.count_of(Gem::Ruby), 2);
+        assert_eq!(gems.count_of(Gem::Gold), 1);
+        assert!(gems.contains(Gem::Ruby));
+        assert!(gems.contains(Gem::Gold));
+
+        assert_eq!(gems.count_of(Gem::Onyx), 0);
+        assert!(!gems.contains(Gem::Onyx));
+        assert!(!Gems::empty().contains(Gem::Diamond));
+
+        // Negative counts left behind by arithmetic are not held either
+        let owed = Gems::empty() - Gems::one(Gem::Emerald);
+        assert_eq!(owed.count_of(Gem::Emerald), -1);
+        assert!(!owed.contains(Gem::Emerald));
+    }
+
+    #[test]
+    fn test_classify_take_double() {
+        let gems = Gems::from_counts(&[(Gem::Emerald, 2)]);
+        assert_eq!(gems.classify_take(), Ok(Action::TakeDouble(Gem::Emerald)));
+    }
+
+    #[test]
+    fn test_classify_take_distinct() {
+        let gems = Gems::from_counts(&[(Gem::Onyx, 1), (Gem::Ruby, 1), (Gem::Diamond, 1)]);
+        let colors = [Gem::Onyx, Gem::Ruby, Gem::Diamond].into_iter().collect();
+        assert_eq!(gems.classify_take(), Ok(Action::TakeDistinct(colors)));
+
+        let one = Gems::one(Gem::Sapphire);
+        let colors = [Gem::Sapphire].into_iter().collect();
+        assert_eq!(one.classify_take(), Ok(Action::TakeDistinct(colors)));
+    }
+
+    #[test]
+    fn test_classify_take_invalid() {
+        let gold = Gems::from_counts(&[(Gem::Gold, 1), (Gem::Ruby, 1)]);
+        assert_eq!(gold.classify_take(), Err(TakeError::ContainsGold));
+
+        let four = Gems::from_counts(&[(Gem::Onyx, 2), (Gem::Ruby, 2)]);
+        assert_eq!(four.classify_take(), Err(TakeError::TooManyTokens));
+
+        let mixed = Gems::from_counts(&[(Gem::Onyx, 2), (Gem::Ruby, 1)]);
+        assert_eq!(mixed.classify_take(), Err(TakeError::Ambiguous));
+        let triple = Gems::from_counts(&[(Gem::Onyx, 3)]);
+        assert_eq!(triple.classify_take(), Err(TakeError::Ambiguous));
+
+        let negative = Gems::empty() - Gems::one(Gem::Ruby);
+        assert_eq!(negative.classify_take(), Err(TakeError::NegativeCount));
+    }
+}
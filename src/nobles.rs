@@ -1,8 +1,13 @@
 use crate::card::Cost;
 use crate::gems::Gems;
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 pub type NobleId = u8;
 
+/// The most nobles a game is dealt, one more than the largest number of players
+pub const MAX_NOBLES: usize = 5;
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Noble {
     pub points: u8,
@@ -10,6 +15,23 @@ pub struct Noble {
     requirements: Gems,
 }
 
+/// Nobles are serialized as their id and looked up again when deserialized
+impl Serialize for Noble {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.id.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Noble {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Noble, D::Error> {
+        let id = NobleId::deserialize(deserializer)?;
+        Noble::all()
+            .get(id as usize)
+            .cloned()
+            .ok_or_else(|| D::Error::custom(format!("Unknown noble id: {}", id)))
+    }
+}
+
 impl Noble {
     pub fn from_id(id: NobleId) -> Noble{
         Noble::all()[id as usize].clone()
@@ -139,6 +161,15 @@ impl Noble {
             ),
         ]
     }
+
+    /// Build a custom noble, e.g. for a variant noble set.
+    /// Nobles are attracted by developments only, so the requirements cannot include gold
+    pub fn from_requirements(points: u8, id: NobleId, requirements: Gems) -> Noble {
+        assert!(requirements.gold == 0, "Noble requirements cannot include gold");
+        assert!(requirements.legal(), "Noble requirements cannot be negative");
+        Noble::new(points, id, requirements)
+    }
+
     fn new(points: u8, id: NobleId, requirements: Gems) -> Noble {
         Noble {
             points,
@@ -165,3 +196,29 @@ impl Noble {
         &self.requirements
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gem::Gem;
+
+    #[test]
+    fn test_from_requirements() {
+        let requirements = Gems::from_counts(&[(Gem::Ruby, 2), (Gem::Onyx, 5)]);
+        let noble = Noble::from_requirements(4, 42, requirements);
+        assert_eq!(noble.points(), 4);
+        assert_eq!(noble.id(), 42);
+        assert_eq!(noble.requirements(), &requirements);
+
+        let developments = Gems::from_counts(&[(Gem::Ruby, 2), (Gem::Onyx, 4)]);
+        assert!(!noble.is_attracted_to(&developments));
+        let developments = developments + Gems::from_counts(&[(Gem::Onyx, 1), (Gem::Diamond, 3)]);
+        assert!(noble.is_attracted_to(&developments));
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_from_requirements_rejects_gold() {
+        Noble::from_requirements(3, 10, Gems::from_counts(&[(Gem::Gold, 1)]));
+    }
+}
@@ -1,17 +1,39 @@
 use crate::card::*;
 use crate::gem::*;
 use crate::gems::Gems;
+use derive_more::{Display, Error};
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::BTreeSet;
 
 use cached::proc_macro::cached;
 
+/// The most cards a player can hold in reserve at once
+pub const MAX_RESERVED: usize = 3;
+
+#[derive(Debug, Display, Error, PartialEq, Eq)]
+pub enum PaymentError {
+    #[display(fmt = "Player does not hold the gems offered as payment")]
+    InsufficientGems,
+    #[display(fmt = "Payment does not match the cost of the card")]
+    InvalidPayment,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PlayerPublicInfo {
     pub points: u8,
+    /// The part of `points` that came from attracting nobles
+    #[serde(default)]
+    pub noble_points: u8,
     pub num_reserved: usize,
+    /// The reserved cards that were reserved face up,
+    /// blind reserved cards stay hidden from other players
+    #[serde(default)]
+    pub public_reserved: Vec<CardId>,
     pub developments: Cost,
     pub gems: Gems,
+    /// The name the player's client connected with, if any
+    #[serde(default)]
+    pub username: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,18 +46,38 @@ pub struct Player {
     blind_reserved: Vec<CardId>,
 }
 
+/// Players are equal regardless of the order their cards were reserved in,
+/// but a card reserved face down is still distinct from one reserved face up
+impl PartialEq for Player {
+    fn eq(&self, other: &Self) -> bool {
+        let sorted = |cards: &Vec<CardId>| {
+            let mut cards = cards.clone();
+            cards.sort_unstable();
+            cards
+        };
+        self.points == other.points
+            && self.noble_points == other.noble_points
+            && self.gems == other.gems
+            && self.developments == other.developments
+            && sorted(&self.reserved) == sorted(&other.reserved)
+            && sorted(&self.blind_reserved) == sorted(&other.blind_reserved)
+    }
+}
+
+impl Eq for Player {}
+
 #[cached]
-fn gem_match(cost: Gems, gems: Gems, running_payment: Gems) -> HashSet<Gems> {
+fn gem_match(cost: Gems, gems: Gems, running_payment: Gems) -> BTreeSet<Gems> {
     if cost.total() == 0 {
-        return HashSet::from_iter(vec![running_payment]);
+        return BTreeSet::from_iter(vec![running_payment]);
     }
     if gems.total() == 0 {
-        return HashSet::new();
+        return BTreeSet::new();
     }
 
     // Take one token that satisfies the cost or a wild token and recurse
     let mut result = Vec::new();
-    for color in Gem::all() {
+    for color in Gem::ALL {
         if cost[color] > 0 {
             let new_cost = cost - Gems::one(color);
 
@@ -59,7 +101,7 @@ fn gem_match(cost: Gems, gems: Gems, running_payment: Gems) -> HashSet<Gems> {
         }
     }
 
-    HashSet::from_iter(result)
+    BTreeSet::from_iter(result)
 }
 
 impl Player {
@@ -74,12 +116,31 @@ impl Player {
         }
     }
 
+    /// Rebuild a player from the information visible to an opponent,
+    /// hidden_reserved stands in for the cards that were reserved face down
+    pub fn from_public(info: &PlayerPublicInfo, hidden_reserved: Vec<CardId>) -> Player {
+        debug_assert!(info.public_reserved.len() + hidden_reserved.len() == info.num_reserved);
+        let mut reserved = info.public_reserved.clone();
+        reserved.extend(hidden_reserved.iter());
+        Player {
+            points: info.points,
+            noble_points: info.noble_points,
+            reserved,
+            gems: info.gems,
+            developments: info.developments.to_gems(),
+            blind_reserved: hidden_reserved,
+        }
+    }
+
     pub fn to_public(&self) -> PlayerPublicInfo {
         PlayerPublicInfo {
             points: self.points,
+            noble_points: self.noble_points,
             num_reserved: self.reserved.len(),
+            public_reserved: self.public_reserved(),
             developments: Cost::from_gems(&self.developments),
             gems: self.gems.clone(),
+            username: None,
         }
     }
 
@@ -90,6 +151,21 @@ impl Player {
     pub fn noble_points(&self) -> u8 {
         self.noble_points
     }
+
+    /// The points that came from purchased cards rather than nobles
+    pub fn card_points(&self) -> u8 {
+        self.points - self.noble_points
+    }
+
+    /// Replace the player's gems, developments and points,
+    /// with none of the points coming from nobles
+    pub(crate) fn set_state(&mut self, gems: Gems, developments: Gems, points: u8) {
+        self.gems = gems;
+        self.developments = developments;
+        self.points = points;
+        self.noble_points = 0;
+    }
+
     pub fn add_points(&mut self, points: u8) {
         self.points += points;
     }
@@ -104,6 +180,11 @@ impl Player {
         self.reserved.len()
     }
 
+    /// Return how many more cards can be reserved before reaching `MAX_RESERVED`
+    pub fn reservations_remaining(&self) -> usize {
+        MAX_RESERVED.saturating_sub(self.reserved.len())
+    }
+
     /// Gets the list of reserved card ids that all players have perfect information of
     pub fn public_reserved(&self) -> Vec<CardId> {
         self.reserved
@@ -146,6 +227,12 @@ impl Player {
         self.reserved.contains(&card_id)
     }
 
+    /// Whether the card is reserved and was drawn blind from a deck, so its
+    /// identity is hidden from the other players until it is purchased
+    pub fn has_blind_reserved(&self, card_id: CardId) -> bool {
+        self.blind_reserved.contains(&card_id)
+    }
+
     pub fn purchase_card(&mut self, card: &Card, payment: &Gems) {
         debug_assert!(payment.legal());
         self.gems -= *payment;
@@ -156,32 +243,50 @@ impl Player {
         self.blind_reserved.retain(|&x| x != card.id());
     }
 
+    /// Purchase a card from an untrusted source, checking that the payment
+    /// is one of `payment_options_for` the card and that the player holds it.
+    /// The player is left untouched if the payment is rejected
+    pub fn try_purchase_card(&mut self, card: &Card, payment: &Gems) -> Result<(), PaymentError> {
+        if !payment.legal() || !(self.gems - *payment).legal() {
+            return Err(PaymentError::InsufficientGems);
+        }
+        let is_option = self
+            .payment_options_for(card)
+            .is_some_and(|options| options.contains(payment));
+        if !is_option {
+            return Err(PaymentError::InvalidPayment);
+        }
+        self.purchase_card(card, payment);
+        Ok(())
+    }
+
     pub fn reserve_card(&mut self, card_id: CardId) {
-        debug_assert!(self.reserved.len() < 3);
+        debug_assert!(self.reserved.len() < MAX_RESERVED);
         self.reserved.push(card_id);
     }
 
     pub fn blind_reserve_card(&mut self, card_id: CardId) {
-        debug_assert!(self.reserved.len() < 3);
+        debug_assert!(self.reserved.len() < MAX_RESERVED);
         self.reserved.push(card_id);
         self.blind_reserved.push(card_id);
     }
 
+    /// The colored gems the player is still missing for a card
+    /// after discounts, before spending any gold
+    pub fn gems_needed_for(&self, card: &Card) -> Gems {
+        let cost = card.cost().discounted_with(&self.developments).to_gems();
+        cost.saturating_sub(&self.gems)
+    }
+
     /// Returns the token spread that a player needs to afford
     /// a given card.
-    pub fn payment_options_for(&self, card: &Card) -> Option<HashSet<Gems>> {
+    pub fn payment_options_for(&self, card: &Card) -> Option<BTreeSet<Gems>> {
         let cost = card.cost();
         let cost = cost.discounted_with(&self.developments).to_gems();
-        let mut total_deficit = 0;
-        for color in Gems::all() {
-            let deficit = cost[color] - self.gems[color];
-            if deficit > 0 {
-                total_deficit += deficit;
-            }
-        }
+        let total_deficit = self.gems_needed_for(card).total();
 
         // Cannot pay off deficit with wild gems
-        if total_deficit > self.gems[Gem::Gold] {
+        if total_deficit > self.gems[Gem::Gold] as i32 {
             return None;
         }
         // Card is free!
@@ -191,6 +296,23 @@ impl Player {
         }
         Some(payments)
     }
+
+    /// The payment for a card that spends the fewest gold tokens,
+    /// None if the player cannot afford it
+    pub fn cheapest_payment_for(&self, card: &Card) -> Option<Gems> {
+        self.payment_options_for(card)?
+            .into_iter()
+            .min_by_key(|payment| (payment[Gem::Gold], *payment))
+    }
+
+    /// What buying a card with its cheapest payment would leave the player with,
+    /// without buying it: the payment, the gems left over and the developments
+    /// including the new card. None if the player cannot afford it
+    pub fn purchase_outcome(&self, card: &Card) -> Option<(Gems, Gems, Gems)> {
+        let payment = self.cheapest_payment_for(card)?;
+        let developments = self.developments + Gems::one(card.gem());
+        Some((payment, self.gems - payment, developments))
+    }
 }
 
 #[cfg(test)]
@@ -200,6 +322,87 @@ mod tests {
     use crate::gem::Gem;
     use crate::gems::Gems;
 
+    #[test]
+    fn test_eq_ignores_reserve_order() {
+        let mut a = Player::new();
+        a.reserve_card(3);
+        a.blind_reserve_card(50);
+        a.reserve_card(7);
+
+        let mut b = Player::new();
+        b.reserve_card(7);
+        b.reserve_card(3);
+        b.blind_reserve_card(50);
+        assert_eq!(a, b);
+
+        // The same cards, but a different one was reserved face down
+        let mut c = Player::new();
+        c.reserve_card(50);
+        c.blind_reserve_card(3);
+        c.reserve_card(7);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn test_try_purchase_valid() {
+        let mut player = Player::new();
+        player.add_gems(Gems { sapphire: 4, ..Gems::empty() });
+        player.reserve_card(7);
+
+        // Card 7 costs 4 sapphire for a single point
+        let card = Card::all()[7];
+        let payment = Gems { sapphire: 4, ..Gems::empty() };
+        assert_eq!(player.try_purchase_card(&card, &payment), Ok(()));
+        assert_eq!(player.total_points(), 1);
+        assert_eq!(*player.gems(), Gems::empty());
+        assert_eq!(player.developments()[card.gem()], 1);
+        assert!(!player.has_reserved_card(7));
+    }
+
+    #[test]
+    fn test_purchase_outcome_matches_purchase() {
+        let mut player = Player::new();
+        player.add_gems(Gems { sapphire: 3, onyx: 2, gold: 1, ..Gems::empty() });
+
+        // Card 7 costs 4 sapphire, the cheapest payment spends as little gold as possible
+        let card = Card::all()[7];
+        let (payment, remaining, developments) = player.purchase_outcome(&card).unwrap();
+        assert_eq!(payment, Gems { sapphire: 3, gold: 1, ..Gems::empty() });
+        assert_eq!(remaining, Gems { onyx: 2, ..Gems::empty() });
+        assert_eq!(developments, Gems::one(card.gem()));
+
+        // Nothing changed until the card is actually bought
+        assert_eq!(player.gems().total(), 6);
+        player.purchase_card(&card, &payment);
+        assert_eq!(*player.gems(), remaining);
+        assert_eq!(*player.developments(), developments);
+
+        // The remaining gems cannot pay for another
+        assert_eq!(player.purchase_outcome(&card), None);
+    }
+
+    #[test]
+    fn test_try_purchase_underfunded() {
+        let mut player = Player::new();
+        player.add_gems(Gems { sapphire: 3, ..Gems::empty() });
+        let before = player.clone();
+
+        let card = Card::all()[7];
+        let payment = Gems { sapphire: 4, ..Gems::empty() };
+        assert_eq!(
+            player.try_purchase_card(&card, &payment),
+            Err(PaymentError::InsufficientGems)
+        );
+
+        // Held gems that do not cover the cost are not a payment option either
+        let payment = Gems { sapphire: 3, ..Gems::empty() };
+        assert_eq!(
+            player.try_purchase_card(&card, &payment),
+            Err(PaymentError::InvalidPayment)
+        );
+        assert_eq!(player, before);
+    }
+
     /// Testing strategy:
     ///     payment_to_afford:
     ///         - has 0, 1, >1 wild (gold) gems
@@ -220,6 +423,24 @@ mod tests {
         assert_eq!(payment, None);
     }
 
+    #[test]
+    fn test_gems_needed_for_ignores_surplus() {
+        let mut player = Player::new();
+        player.add_gems(Gems::one(Gem::Ruby));
+        player.add_gems(Gems::one(Gem::Gold));
+        player.add_gems(Gems::one(Gem::Onyx));
+
+        let card = Card::all()[4];
+        let cost = card.cost().to_gems();
+        let needed = player.gems_needed_for(&card);
+        assert!(needed.legal());
+        assert_eq!(needed.gold, 0);
+        assert!(needed.total() > 1);
+        for color in Gem::all_expect_gold() {
+            assert_eq!(needed[color], (cost[color] - player.gems()[color]).max(0));
+        }
+    }
+
     #[test]
     fn test_cannot_afford_0_wild() {
         let mut player = Player::new();
@@ -353,7 +574,7 @@ mod tests {
                 gold: 0,
             },
         ];
-        let target = target.into_iter().collect::<std::collections::HashSet<_>>();
+        let target = target.into_iter().collect::<BTreeSet<_>>();
         assert_eq!(set, target);
     }
 
@@ -448,4 +669,81 @@ mod tests {
 
         assert_eq!(payment.len(), 5);
     }
+
+    #[test]
+    fn test_payment_options_iterate_in_order() {
+        let mut player = Player::new();
+        player.add_gems(Gems::one(Gem::Emerald));
+        player.add_gems(Gems::one(Gem::Emerald));
+        player.add_gems(Gems::one(Gem::Onyx));
+        player.add_gems(Gems::one(Gem::Gold));
+        player.add_gems(Gems::one(Gem::Gold));
+        player.add_gems(Gems::one(Gem::Gold));
+
+        let card = Card::all()[13];
+        let first: Vec<Gems> = player.payment_options_for(&card).unwrap().into_iter().collect();
+        let second: Vec<Gems> = player.payment_options_for(&card).unwrap().into_iter().collect();
+
+        let mut sorted = first.clone();
+        sorted.sort();
+        assert_eq!(first, sorted);
+        assert_eq!(first, second);
+
+        let mut bank = Gems::start(2);
+        let choices: Vec<Gems> = crate::game_logic::choose_gems(&mut bank, &mut Gems::empty(), 2)
+            .into_iter()
+            .collect();
+        let mut sorted = choices.clone();
+        sorted.sort();
+        assert_eq!(choices, sorted);
+    }
+
+    #[test]
+    fn test_public_info_hides_blind_reserved() {
+        let mut player = Player::new();
+        player.reserve_card(4);
+        player.blind_reserve_card(13);
+
+        let public = player.to_public();
+        assert_eq!(public.num_reserved, 2);
+        assert_eq!(public.public_reserved, vec![4]);
+    }
+
+    #[test]
+    fn test_blind_reserved_hidden_until_purchased() {
+        let card = Card::all()[7];
+        let mut player = Player::new();
+        player.blind_reserve_card(card.id());
+        assert!(player.has_blind_reserved(card.id()));
+        assert!(player.has_reserved_card(card.id()));
+
+        let public = player.to_public();
+        assert_eq!(public.num_reserved, 1);
+        assert!(public.public_reserved.is_empty());
+
+        player.purchase_card(&card, &Gems::empty());
+        assert!(!player.has_blind_reserved(card.id()));
+        assert!(!player.has_reserved_card(card.id()));
+
+        let public = player.to_public();
+        assert_eq!(public.num_reserved, 0);
+        assert_eq!(public.developments.to_gems(), Gems::one(card.gem()));
+        assert_eq!(public.points, card.points());
+    }
+
+    #[test]
+    fn test_point_split_after_noble() {
+        let mut player = Player::new();
+        player.purchase_card(&Card::all()[7], &Gems::empty());
+        player.add_noble_points();
+        assert_eq!(player.total_points(), 4);
+        assert_eq!(player.noble_points(), 3);
+        assert_eq!(player.card_points(), 1);
+
+        let public = player.to_public();
+        assert_eq!(public.noble_points, 3);
+        let rebuilt = Player::from_public(&public, vec![]);
+        assert_eq!(rebuilt.noble_points(), 3);
+        assert_eq!(rebuilt.card_points(), 1);
+    }
 }
@@ -1,10 +1,13 @@
 use lazy_static::lazy_static; 
 use pyo3::prelude::*;
 use crate::*;
-use tungstenite::{connect, Message};
 use url::Url;
 use serde::Deserialize;
-use std::time::Duration;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// How long a `time_remaining` read from the server is reused for
+const TIME_REMAINING_TTL: Duration = Duration::from_millis(50);
 
 lazy_static! {
     static ref CARD_LOOKUP: [Card; 90] = Card::all_const();
@@ -32,10 +35,8 @@ impl PyCard {
         }
     }
     pub fn from_id(card_id: CardId) -> Self {
-        if card_id >= CARD_LOOKUP.len() as CardId {
-            panic!("Invalid card id: [{}], card ids must be from 0-89", card_id);
-        }
-        let card = CARD_LOOKUP[card_id as usize];
+        let card = Card::from_id(card_id)
+            .unwrap_or_else(|| panic!("Invalid card id: [{}], card ids must be from 0-89", card_id));
         PyCard::from(&card)
     }
 }
@@ -58,7 +59,14 @@ impl PyCard {
     }
 
     pub fn __str__(&self) -> String {
-        format!("Card(id: {})", self.id)
+        format!(
+            "Card(id={}, tier={}, gem={}, points={}, cost=({}))",
+            self.id,
+            self.tier,
+            self.gem.__str__(),
+            self.points,
+            self.cost.__str__()
+        )
     }
 
     pub fn __repr__(&self) -> String {
@@ -118,6 +126,17 @@ impl PyGem {
             Gem::Gold => PyGem::Gold,
         }
     }
+
+    pub fn into_gem(&self) -> Gem {
+        match self {
+            PyGem::Onyx => Gem::Onyx,
+            PyGem::Sapphire => Gem::Sapphire,
+            PyGem::Emerald => Gem::Emerald,
+            PyGem::Ruby => Gem::Ruby,
+            PyGem::Diamond => Gem::Diamond,
+            PyGem::Gold => Gem::Gold,
+        }
+    }
 }
 
 #[pymethods]
@@ -189,6 +208,28 @@ impl PyGems {
             gold: self.gold,
         }
     }
+
+    /// `from_dict` before an unknown color is turned into a Python KeyError
+    fn try_from_dict(gems: HashMap<String, i8>) -> Result<PyGems, String> {
+        let mut py_gems = PyGems::new(None, None, None, None, None, None);
+        for (color, count) in gems {
+            match color.as_str() {
+                "onyx" => py_gems.onyx = count,
+                "sapphire" => py_gems.sapphire = count,
+                "emerald" => py_gems.emerald = count,
+                "ruby" => py_gems.ruby = count,
+                "diamond" => py_gems.diamond = count,
+                "gold" => py_gems.gold = count,
+                _ => {
+                    return Err(format!(
+                        "Unknown gem color: {}, expected one of onyx, sapphire, emerald, ruby, diamond, gold",
+                        color
+                    ))
+                }
+            }
+        }
+        Ok(py_gems)
+    }
 }
 
 #[pymethods]
@@ -212,6 +253,25 @@ impl PyGems {
         }
     }
 
+    /// Build gems from a dict of color names to counts,
+    /// colors that are left out default to 0
+    #[staticmethod]
+    pub fn from_dict(gems: HashMap<String, i8>) -> PyResult<PyGems> {
+        PyGems::try_from_dict(gems).map_err(PyErr::new::<pyo3::exceptions::PyKeyError, _>)
+    }
+
+    /// A dict of every color name to its count
+    pub fn to_dict(&self) -> HashMap<String, i8> {
+        HashMap::from([
+            ("onyx".to_string(), self.onyx),
+            ("sapphire".to_string(), self.sapphire),
+            ("emerald".to_string(), self.emerald),
+            ("ruby".to_string(), self.ruby),
+            ("diamond".to_string(), self.diamond),
+            ("gold".to_string(), self.gold),
+        ])
+    }
+
     pub fn __str__(&self) -> String {
         //TODO : perhaps we ignore the 0 values?
         format!(
@@ -226,7 +286,7 @@ impl PyGems {
 }
 
 #[pyclass]
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum PyActionType {
     TakeGems,
     ReserveFaceUp,
@@ -238,6 +298,13 @@ pub enum PyActionType {
     Continue,
 }
 
+#[pymethods]
+impl PyActionType {
+    pub fn __hash__(&self) -> u64 {
+        self.clone() as u64
+    }
+}
+
 #[pyclass]
 #[derive(Debug, Clone)]
 pub struct PyAction {
@@ -257,14 +324,14 @@ impl PyAction {
             Action::Reserve(_) => PyActionType::ReserveFaceUp,
             Action::ReserveHidden(_) => PyActionType::ReserveFaceDown,
             Action::Discard(_) => PyActionType::Discard,
-            Action::Purchase(_) => PyActionType::Purchase,
+            Action::Purchase { .. } => PyActionType::Purchase,
             Action::AttractNoble(_) => PyActionType::AttractNoble,
             Action::Pass => PyActionType::Pass,
             Action::Continue => PyActionType::Continue,
         };
         let card_id = match &action {
             Action::Reserve(card_id) => Some(*card_id),
-            Action::Purchase((card_id, _)) => Some(*card_id),
+            Action::Purchase { card, .. } => Some(*card),
             _ => None,
         };
 
@@ -283,7 +350,7 @@ impl PyAction {
             }
             Action::TakeDistinct(color_set) => Some(PyGems::from(Gems::from_set(color_set))),
             Action::Discard(gems) => Some(PyGems::from(*gems)),
-            Action::Purchase((_, gems)) => Some(PyGems::from(*gems)),
+            Action::Purchase { payment, .. } => Some(PyGems::from(*payment)),
             _ => None,
         };
 
@@ -301,34 +368,24 @@ impl PyAction {
         }
     }
 
-    pub fn into_action(self) -> Action {
-        match self.action_type {
-            PyActionType::TakeGems => {
-                let py_gems = self.gems();
-                let gems = py_gems.into_gems();
-                let is_double = gems.total() == 2 && gems.to_set().len() == 1;
-
-                match is_double {
-                    true => {
-                        let mut color = Gem::Gold;
-                        for c in gems.to_set() {
-                            color = c
-                        }
-                        Action::TakeDouble(color)
-                    }
-                    false => Action::TakeDistinct(gems.to_set()),
-                }
-            }
+    /// Fails if a take is neither 2 of a single color nor at most 3 distinct colors
+    pub fn into_action(self) -> Result<Action, TakeError> {
+        let action = match self.action_type {
+            PyActionType::TakeGems => self.gems().into_gems().classify_take()?,
             PyActionType::ReserveFaceUp => Action::Reserve(self.card_id()),
             PyActionType::ReserveFaceDown => Action::ReserveHidden(self.tier()),
             PyActionType::Discard => Action::Discard(self.gems().into_gems()),
             PyActionType::Purchase => {
-                Action::Purchase((self.card_id(), self.gems().into_gems()))
+                Action::Purchase {
+                    card: self.card_id(),
+                    payment: self.gems().into_gems(),
+                }
             }
             PyActionType::AttractNoble => Action::AttractNoble(self.noble_id()),
             PyActionType::Pass => Action::Pass,
             PyActionType::Continue => Action::Continue,
-        }
+        };
+        Ok(action)
     }
 }
 
@@ -545,6 +602,30 @@ impl PyAction {
             tier: None,
         }
     }
+
+    /// Named with a trailing underscore as `pass` is a Python keyword
+    #[staticmethod]
+    pub fn pass_() -> Self {
+        PyAction {
+            action_type: PyActionType::Pass,
+            card_id: None,
+            noble_id: None,
+            gems: None,
+            tier: None,
+        }
+    }
+
+    /// Named with a trailing underscore as `continue` is a Python keyword
+    #[staticmethod]
+    pub fn continue_() -> Self {
+        PyAction {
+            action_type: PyActionType::Continue,
+            card_id: None,
+            noble_id: None,
+            gems: None,
+            tier: None,
+        }
+    }
 }
 
 /// A Python wrapper for the `ClientInfo` struct
@@ -560,11 +641,18 @@ pub struct PyClientInfo {
     pub player_index: usize,
     #[pyo3(get)]
     pub legal_actions: Vec<PyAction>,
+    /// The number of cards this player can still reserve
+    #[pyo3(get)]
+    pub reservations_remaining: usize,
     time_endpoint_url: String,
+    client_info: ClientInfo,
+    /// The last time remaining fetched from the server and when it was fetched
+    last_time_remaining: Option<(Instant, Duration)>,
 }
 
 impl PyClientInfo {
     pub fn from_client_info(client_info: ClientInfo) -> Self {
+        let original = client_info.clone();
         // TODO: going to need to
         // make sure that the number of players
         // is conveyed to the python side in
@@ -592,9 +680,21 @@ impl PyClientInfo {
             current_player: py_current_player,
             player_index: client_info.current_player_num,
             legal_actions: py_legal_actions,
+            reservations_remaining: client_info.reservations_remaining,
             time_endpoint_url: client_info.time_endpoint_url,
+            client_info: original,
+            last_time_remaining: None,
         }
     }
+
+    /// `simulate` before the error is turned into a Python exception
+    fn try_simulate(&self, action: PyAction) -> Result<PyClientInfo, String> {
+        let action = action.into_action().map_err(|e| e.to_string())?;
+        self.client_info
+            .simulate(action)
+            .map(PyClientInfo::from_client_info)
+            .map_err(|e| e.to_string())
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -622,9 +722,57 @@ impl PyClientInfo {
         self.players.len()
     }
 
-    pub fn time_remaining(&self) -> f64 {
+    /// The kind of action the current phase requires: Discard when holding
+    /// too many gems, AttractNoble in the noble phase (Pass is only legal there
+    /// when no noble can be attracted) and Continue once the turn is over.
+    /// None at the start of a turn, when any kind of action may be chosen
+    pub fn required_action_type(&self) -> Option<PyActionType> {
+        match self.client_info.phase {
+            Phase::PlayerStart => None,
+            Phase::PlayerGemCapExceeded => Some(PyActionType::Discard),
+            Phase::NobleAction => Some(PyActionType::AttractNoble),
+            Phase::PlayerActionEnd => Some(PyActionType::Continue),
+        }
+    }
+
+    /// The legal actions grouped by their action type, types without
+    /// any legal actions are left out
+    pub fn actions_by_kind(&self) -> HashMap<PyActionType, Vec<PyAction>> {
+        let mut grouped: HashMap<PyActionType, Vec<PyAction>> = HashMap::new();
+        for action in &self.legal_actions {
+            grouped
+                .entry(action.action_type())
+                .or_default()
+                .push(action.clone());
+        }
+        grouped
+    }
+
+    /// Play an action on a local copy of the game and return the info that
+    /// the next player to act would see, the real game is unaffected.
+    /// Hidden information (deck order, opponents' face down reservations)
+    /// is filled in randomly
+    pub fn simulate(&self, action: PyAction) -> PyResult<PyClientInfo> {
+        match self.try_simulate(action) {
+            Ok(info) => Ok(info),
+            Err(e) => Err(PyErr::new::<pyo3::exceptions::PyValueError, _>(e)),
+        }
+    }
+
+    /// The milliseconds the player has left. Reads within a short window
+    /// reuse the last answer from the server (counted down locally)
+    /// unless `refresh` is given
+    #[pyo3(signature = (refresh = false))]
+    pub fn time_remaining(&mut self, refresh: bool) -> f64 {
+        if let Some((fetched_at, time_remaining)) = self.last_time_remaining {
+            let age = fetched_at.elapsed();
+            if !refresh && age < TIME_REMAINING_TTL {
+                return time_remaining.saturating_sub(age).as_millis() as f64;
+            }
+        }
         let response = reqwest::blocking::get(&self.time_endpoint_url).expect("Server did not response with time remaining");
         let response: TimeRemaining = response.json().expect("Could not parse time remaining response");
+        self.last_time_remaining = Some((Instant::now(), response.time_remaining));
         response.time_remaining.as_millis() as f64
     }
 }
@@ -636,10 +784,14 @@ pub struct PyPlayer {
     index: usize,
     #[pyo3(get)]
     total_points: u8,
+    /// The part of `total_points` that came from attracting nobles
+    #[pyo3(get)]
+    noble_points: u8,
     #[pyo3(get)]
     num_reserved_cards: usize,
     #[pyo3(get)]
     gems: PyGems,
+    /// Always built from a `Cost`, so developments never report gold
     #[pyo3(get)]
     developments: PyGems,
     reserved_cards: Option<Vec<PyCard>>,
@@ -650,6 +802,7 @@ impl PyPlayer {
         PyPlayer {
             index,
             total_points: player.total_points(),
+            noble_points: player.noble_points(),
             reserved_cards: Some(
                 player
                     .all_reserved()
@@ -659,18 +812,27 @@ impl PyPlayer {
             ),
             num_reserved_cards: player.num_reserved_cards(),
             gems: PyGems::from(*player.gems()),
-            developments: PyGems::from(*player.developments()),
+            developments: PyGems::from_cost(Cost::from_gems(player.developments())),
         }
     }
 
+    /// Only the cards an opponent reserved face up are visible
     pub fn from_public(player: &PlayerPublicInfo, index: usize) -> Self {
+        debug_assert!(player.public_reserved.len() <= player.num_reserved);
         PyPlayer {
             index,
             total_points: player.points,
-            reserved_cards: None,
+            noble_points: player.noble_points,
+            reserved_cards: Some(
+                player
+                    .public_reserved
+                    .iter()
+                    .map(|&id| PyCard::from_id(id))
+                    .collect(),
+            ),
             num_reserved_cards: player.num_reserved,
             gems: PyGems::from(player.gems),
-            developments: PyGems::from(player.developments.to_gems()),
+            developments: PyGems::from_cost(player.developments),
         }
     }
 }
@@ -686,6 +848,17 @@ impl PyPlayer {
         }
         Ok(self.reserved_cards.clone().unwrap())
     }
+
+    /// The points that came from purchased cards rather than nobles
+    #[getter]
+    pub fn card_points(&self) -> u8 {
+        self.total_points - self.noble_points
+    }
+
+    /// The number of developments of a given color, always 0 for gold
+    pub fn development_count(&self, gem: PyGem) -> i8 {
+        self.developments.clone().into_gems()[gem.into_gem()]
+    }
 }
 
 #[pyclass]
@@ -790,10 +963,8 @@ impl PyGameHistory {
             .group_by_player()
             .into_iter()
             .map(|turn_sequences| {
-                let actions = turn_sequences
-                    .iter()
+                let actions = GameHistory::meaningful_entries(&turn_sequences)
                     .map(|(_, action)| PyAction::from(action.clone()))
-                    .filter(|action| action.action_type != PyActionType::Continue)
                     .collect();
 
                 let player_index = turn_sequences[0].0;
@@ -870,9 +1041,11 @@ impl PyLog {
 pub fn run_python_bot(py: Python, bot_class: &PyAny) {
     let port = 3030;
 
-    let url = format!("ws://127.0.0.1:{}/game", port);
-    let url = Url::parse(&url).unwrap();
-    let (mut game_socket, _) = connect(url).expect("Can't connect to the game server");
+    let url = game_url(port, None);
+    let backoff = Backoff::default();
+    let mut game_socket = connect_with_retry(&url, backoff).expect("Can't connect to the game server");
+    let config = read_game_config(&mut game_socket)
+        .unwrap_or_else(|e| panic!("The game server did not send its config: {}", e));
 
     // Give the server a chance to start up
     std::thread::sleep(std::time::Duration::from_millis(100));
@@ -883,33 +1056,222 @@ pub fn run_python_bot(py: Python, bot_class: &PyAny) {
         .call1((py_log.try_borrow_mut().unwrap(),))
         .expect("Unable to launch bot, could not call __init__");
 
-    loop {
-        let msg = game_socket.read();
-        let msg = match msg {
-            Ok(msg) => msg,
-            Err(_) => {
-                break;
+    // Bots may optionally define on_error(self, error, log)
+    let report_error = |error: String| {
+        if bot_instance.hasattr("on_error").unwrap_or(false) {
+            bot_instance
+                .call_method1("on_error", (error, py_log.try_borrow_mut().unwrap()))
+                .expect("Error when calling method on_error()");
+        } else {
+            eprintln!("Error: {}", error);
+        }
+    };
+
+    play_game(game_socket, &rejoin_url(&url, &config), backoff, |event| match event {
+        ServerEvent::Turn(info) => {
+            let py_info = PyClientInfo::from_client_info(*info);
+            let result =
+                bot_instance.call_method1("take_action", (py_info, py_log.try_borrow_mut().unwrap()));
+            let py_action: PyAction = result
+                .expect("Error when calling method take_action()")
+                .extract()
+                .expect("Incorrect type returned by method take_action()");
+
+            // A malformed take is reported and the server plays for the bot once its time runs out
+            match py_action.into_action() {
+                Ok(action) => Some(action),
+                Err(e) => {
+                    report_error(format!("Invalid action returned by take_action(): {}", e));
+                    None
+                }
             }
+        }
+        ServerEvent::Error(error) => {
+            report_error(error);
+            None
+        }
+    });
+}
+
+// TODO: Clean up and make sure equality checking is not referential equality (python default) but instead value equality
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pass_and_continue_constructors() {
+        assert_eq!(PyAction::pass_().into_action().unwrap(), Action::Pass);
+        assert_eq!(PyAction::continue_().into_action().unwrap(), Action::Continue);
+        assert!(PyAction::from(Action::Pass).__eq__(&PyAction::pass_()));
+        assert!(PyAction::from(Action::Continue).__eq__(&PyAction::continue_()));
+    }
+
+    #[test]
+    fn test_take_gems_conversion() {
+        let double = PyAction::take_gems(Some(2), None, None, None, None);
+        assert_eq!(double.into_action().unwrap(), Action::TakeDouble(Gem::Onyx));
+        let distinct = PyAction::take_gems(Some(1), None, Some(1), None, None);
+        let colors = [Gem::Onyx, Gem::Emerald].into_iter().collect();
+        assert_eq!(distinct.into_action().unwrap(), Action::TakeDistinct(colors));
+    }
+
+    #[test]
+    fn test_simulate_leaves_info_untouched() {
+        let game = Game::new(2, std::sync::Arc::new(Card::all()));
+        let info = PyClientInfo::from_client_info(ClientInfo::from_game(&game, String::new()));
+        let take = info
+            .legal_actions
+            .iter()
+            .find(|action| matches!(PyAction::clone(action).into_action().unwrap(), Action::TakeDistinct(_)))
+            .unwrap()
+            .clone();
+
+        let next = info.try_simulate(take).unwrap();
+        assert_eq!(next.client_info.history.num_actions(), 1);
+        assert_eq!(next.client_info.current_player.gems().total(), 3);
+        assert_eq!(info.client_info.history.num_actions(), 0);
+        assert_eq!(
+            info.try_simulate(PyAction::continue_()).err(),
+            Some(ActionError::IllegalAction.to_string())
+        );
+    }
+
+    #[test]
+    fn test_malformed_take_is_an_error() {
+        let triple = PyAction::take_gems(None, None, None, Some(3), None);
+        assert_eq!(triple.clone().into_action(), Err(TakeError::Ambiguous));
+
+        let game = Game::new(2, std::sync::Arc::new(Card::all()));
+        let info = PyClientInfo::from_client_info(ClientInfo::from_game(&game, String::new()));
+        assert_eq!(info.try_simulate(triple).err(), Some(TakeError::Ambiguous.to_string()));
+    }
+
+    #[test]
+    fn test_required_action_type_through_a_forced_discard() {
+        let mut game = Game::new(2, std::sync::Arc::new(Card::all()));
+        let gems = Gems::from_counts(&[(Gem::Onyx, 3), (Gem::Sapphire, 3), (Gem::Emerald, 3)]);
+        game.setup_player(0, gems, Gems::empty(), 0).unwrap();
+        let required = |game: &Game| {
+            PyClientInfo::from_client_info(ClientInfo::from_game(game, String::new()))
+                .required_action_type()
         };
-        let msg = msg.to_text().expect("Error converting message to text");
-        let info: ClientInfo = serde_json::from_str(msg).expect("Error parsing message");
-        let py_info = PyClientInfo::from_client_info(info);
-        let result =
-            bot_instance.call_method1("take_action", (py_info, py_log.try_borrow_mut().unwrap()));
-        let py_action: PyAction = result
-            .expect("Error when calling method take_action()")
-            .extract()
-            .expect("Incorrect type returned by method take_action()");
+        assert_eq!(required(&game), None);
 
-        let action = py_action.into_action();
+        let take = game
+            .get_legal_actions()
+            .unwrap()
+            .into_iter()
+            .find(|action| matches!(action, Action::TakeDistinct(_)))
+            .unwrap();
+        game.play_action(take);
+        assert_eq!(game.phase(), Phase::PlayerGemCapExceeded);
+        assert_eq!(required(&game), Some(PyActionType::Discard));
+
+        let discard = game.get_legal_actions().unwrap()[0].clone();
+        game.play_action(discard);
+        assert_eq!(game.get_legal_actions(), Some(vec![Action::Pass]));
+        assert_eq!(required(&game), Some(PyActionType::AttractNoble));
+
+        game.play_action(Action::Pass);
+        assert_eq!(required(&game), Some(PyActionType::Continue));
+        game.play_action(Action::Continue);
+        assert_eq!(required(&game), None);
+    }
 
-        let msg = ClientMessage::Action(action);
-        let msg_str = serde_json::to_string(&msg).expect("Error converting action to string");
-        let game_send_result = game_socket.send(Message::Text(msg_str));
-        if game_send_result.is_err() {
-            break;
+    #[test]
+    fn test_developments_never_report_gold() {
+        let mut game = Game::new(2, std::sync::Arc::new(Card::all()));
+        let developments = Gems::from_counts(&[(Gem::Ruby, 2), (Gem::Diamond, 1)]);
+        game.setup_player(0, Gems::empty(), developments, 0).unwrap();
+
+        let player = &game.players()[0];
+        let private = PyPlayer::from(player, 0);
+        let public = PyPlayer::from_public(&player.to_public(), 0);
+        assert_eq!(private.developments, public.developments);
+        assert_eq!(private.developments.gold, 0);
+        for py_player in [private, public] {
+            assert_eq!(py_player.development_count(PyGem::Ruby), 2);
+            assert_eq!(py_player.development_count(PyGem::Diamond), 1);
+            assert_eq!(py_player.development_count(PyGem::Gold), 0);
         }
     }
-}
 
-// TODO: Clean up and make sure equality checking is not referential equality (python default) but instead value equality
+    #[test]
+    fn test_card_str_shows_tier_and_gem() {
+        let card = Card::all()[0];
+        let py_card = PyCard::from_id(card.id());
+        let text = py_card.__str__();
+        assert!(text.contains(&format!("tier={}", card.tier())));
+        assert!(text.contains(&format!("gem={}", PyGem::from(card.gem()).__str__())));
+        assert!(text.contains(&format!("points={}", card.points())));
+        assert_eq!(py_card.__repr__(), text);
+    }
+
+    #[test]
+    fn test_compact_gems_reach_python_as_zero() {
+        let mut game = Game::new(2, std::sync::Arc::new(Card::all()));
+        game.setup_player(0, Gems::from_counts(&[(Gem::Ruby, 2)]), Gems::empty(), 0).unwrap();
+        let json = serde_json::to_string(&ClientInfo::from_game(&game, String::new())).unwrap();
+        assert!(json.contains(r#""gems":{"ruby":2}"#));
+
+        let info = PyClientInfo::from_client_info(serde_json::from_str(&json).unwrap());
+        assert_eq!(info.current_player.gems, PyGems::new(None, None, None, Some(2), None, None));
+    }
+
+    #[test]
+    fn test_gems_dict_round_trip() {
+        let dict = HashMap::from([("ruby".to_string(), 2), ("gold".to_string(), 1)]);
+        let gems = PyGems::try_from_dict(dict).unwrap();
+        assert_eq!(gems, PyGems::new(None, None, None, Some(2), None, Some(1)));
+
+        let round_trip = PyGems::try_from_dict(gems.to_dict()).unwrap();
+        assert_eq!(round_trip, gems);
+        assert_eq!(gems.to_dict().len(), 6);
+
+        let unknown = HashMap::from([("rubies".to_string(), 1)]);
+        assert!(PyGems::try_from_dict(unknown).unwrap_err().contains("rubies"));
+    }
+
+    #[test]
+    fn test_time_remaining_is_cached() {
+        use std::io::{Read, Write};
+        use std::net::TcpListener;
+
+        // Answers every request with 5 seconds left and counts them
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            let body = r#"{"time_remaining":{"secs":5,"nanos":0}}"#;
+            let mut requests = 0;
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut buf = [0; 1024];
+                let read = stream.read(&mut buf).unwrap();
+                if buf[..read].starts_with(b"GET /stop") {
+                    return requests;
+                }
+                requests += 1;
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+            requests
+        });
+
+        let game = Game::new(2, std::sync::Arc::new(Card::all()));
+        let url = format!("http://127.0.0.1:{}/time", port);
+        let mut info = PyClientInfo::from_client_info(ClientInfo::from_game(&game, url));
+        for _ in 0..10 {
+            let time_remaining = info.time_remaining(false);
+            assert!(time_remaining > 4900.0 && time_remaining <= 5000.0);
+        }
+        assert_eq!(info.time_remaining(true), 5000.0);
+
+        let _ = reqwest::blocking::get(format!("http://127.0.0.1:{}/stop", port));
+        assert_eq!(server.join().unwrap(), 2);
+    }
+}
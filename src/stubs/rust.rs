@@ -15,6 +15,7 @@
 /// Changing this may break compatibility with the engine!
 
 use derive_more::{Display, Error};
+use std::collections::HashMap;
 use std::time::Duration;
 use serde::Deserialize;
 
@@ -56,7 +57,33 @@ pub enum Action {
     Continue,
 }
 
+/// The kind of an Action, without its payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ActionKind {
+    TakeGems,
+    ReserveFaceUp,
+    ReserveFaceDown,
+    Purchase,
+    DiscardGems,
+    AttractNoble,
+    Pass,
+    Continue,
+}
+
 impl Action {
+    pub fn kind(&self) -> ActionKind {
+        match self {
+            Action::TakeGems(_) => ActionKind::TakeGems,
+            Action::ReserveFaceUp(_) => ActionKind::ReserveFaceUp,
+            Action::ReserveFaceDown(_) => ActionKind::ReserveFaceDown,
+            Action::Purchase(_, _) => ActionKind::Purchase,
+            Action::DiscardGems(_) => ActionKind::DiscardGems,
+            Action::AttractNoble(_) => ActionKind::AttractNoble,
+            Action::Pass => ActionKind::Pass,
+            Action::Continue => ActionKind::Continue,
+        }
+    }
+
     /// Convert a splendor_tourney::Action into an Action
     fn from(action: splendor_tourney::Action) -> Self {
         match action {
@@ -64,7 +91,7 @@ impl Action {
             splendor_tourney::Action::TakeDistinct(gems) => Action::TakeGems(Gems::from_set(&gems)),
             splendor_tourney::Action::Reserve(card_id) => Action::ReserveFaceUp(card_id),
             splendor_tourney::Action::ReserveHidden(tier) => Action::ReserveFaceDown(tier),
-            splendor_tourney::Action::Purchase((card_id, gems)) => Action::Purchase(card_id, gems),
+            splendor_tourney::Action::Purchase { card, payment } => Action::Purchase(card, payment),
             splendor_tourney::Action::Discard(gems) => Action::DiscardGems(gems),
             splendor_tourney::Action::AttractNoble(noble_id) => Action::AttractNoble(Noble::from_id(noble_id)),
             splendor_tourney::Action::Pass => Action::Pass,
@@ -75,23 +102,7 @@ impl Action {
     /// Convert an Action into a splendor_tourney::Action
     fn to_splendor_tourney(&self) -> Result<splendor_tourney::Action, ModelError> {
         match self {
-            Action::TakeGems(gems) => {
-                // TODO: could validate gems here
-                let is_double = Gems::all().iter().any(|&gem| gems[gem] == 2);
-                if is_double {
-                    if gems.total() != 2 {
-                        return Err(ModelError::IllegalAction);
-                    }
-                    let doubled_gem = Gems::all().into_iter().find(|&gem| gems[gem] == 2).unwrap();
-                    Ok(splendor_tourney::Action::TakeDouble(doubled_gem))
-                } else {
-                    if gems.total() > 3 {
-                        return Err(ModelError::IllegalAction);
-                    }
-                    let set = gems.to_set();
-                    Ok(splendor_tourney::Action::TakeDistinct(set))
-                }
-            }
+            Action::TakeGems(gems) => gems.classify_take().map_err(|_| ModelError::IllegalAction),
             Action::ReserveFaceUp(card_id) => {
                 let reserve = splendor_tourney::Action::Reserve(*card_id);
                 Ok(reserve)
@@ -101,7 +112,10 @@ impl Action {
                 Ok(reserve_hidden)
             }
             Action::Purchase(card_id, gems) => {
-                let purchase = splendor_tourney::Action::Purchase((*card_id, *gems));
+                let purchase = splendor_tourney::Action::Purchase {
+                    card: *card_id,
+                    payment: *gems,
+                };
                 Ok(purchase)
             }
             Action::DiscardGems(gems) => {
@@ -203,14 +217,9 @@ impl Card {
 
     /// Given a CardId, return the corresponding Card
     pub fn from_id(id: CardId) -> Self {
-        let card = CARD_LOOKUP[id as usize];
-        Card {
-            points: card.points(),
-            cost: card.cost(),
-            gem: card.gem(),
-            id: card.id(),
-            tier: card.tier(),
-        }
+        let card = splendor_tourney::Card::from_id(id)
+            .unwrap_or_else(|| panic!("Invalid card id: [{}], card ids must be from 0-89", id));
+        Card::from(card)
     }
 
     /// Return all cards in the game
@@ -235,12 +244,10 @@ impl GameHistory {
     fn from(game_history: splendor_tourney::GameHistory) -> Self {
         let mut turns = Vec::new();
         for group in game_history.group_by_player() {
-            let mut actions = Vec::new();
-            let mut player_index = 5;
-            for (p, action) in group {
-                player_index = p;
-                actions.push(Action::from(action));
-            }
+            let player_index = group.last().map_or(5, |&(p, _)| p);
+            let actions = splendor_tourney::GameHistory::meaningful_entries(&group)
+                .map(|(_, action)| Action::from(action.clone()))
+                .collect();
             turns.push((player_index, actions));
         }
 
@@ -254,6 +261,8 @@ impl GameHistory {
 pub struct Player {
     pub index: usize,
     pub total_points: u8,
+    /// The part of `total_points` that came from attracting nobles
+    pub noble_points: u8,
     pub num_reserved_cards: usize,
     pub gems: Gems,
     pub developments: Gems,
@@ -265,6 +274,7 @@ impl Player {
         Player {
             index,
             total_points: player.total_points(),
+            noble_points: player.noble_points(),
             reserved_cards: Some(
                 player
                     .all_reserved()
@@ -278,16 +288,31 @@ impl Player {
         }
     }
 
+    /// Convert the public info of an opponent into a Player, only the cards
+    /// that were reserved face up are visible
     pub fn from_public(player: &splendor_tourney::PlayerPublicInfo, index: usize) -> Self {
+        debug_assert!(player.public_reserved.len() <= player.num_reserved);
         Player {
             index,
             total_points: player.points,
-            reserved_cards: None,
+            noble_points: player.noble_points,
+            reserved_cards: Some(
+                player
+                    .public_reserved
+                    .iter()
+                    .map(|&id| Card::from_id(id))
+                    .collect(),
+            ),
             num_reserved_cards: player.num_reserved,
             gems: Gems::from(player.gems),
             developments: Gems::from(player.developments.to_gems()),
         }
     }
+
+    /// The points that came from purchased cards rather than nobles
+    pub fn card_points(&self) -> u8 {
+        self.total_points - self.noble_points
+    }
 }
 
 /// Re-export the splendor_tourney module ClientInfo
@@ -301,6 +326,8 @@ pub struct GameInfo {
     pub player_index: usize,
     pub legal_actions: Vec<Action>,
     pub num_players: usize,
+    /// The number of cards this player can still reserve
+    pub reservations_remaining: usize,
     time_endpoint_url: String,
 }
 
@@ -341,6 +368,7 @@ impl GameInfo {
             player_index: client_info.current_player_num,
             legal_actions,
             num_players,
+            reservations_remaining: client_info.reservations_remaining,
             time_endpoint_url: client_info.time_endpoint_url,
         }
     }
@@ -353,6 +381,16 @@ impl GameInfo {
         self.num_players
     }
 
+    /// The legal actions grouped by their kind, kinds without
+    /// any legal actions are left out
+    pub fn actions_by_kind(&self) -> HashMap<ActionKind, Vec<Action>> {
+        let mut grouped: HashMap<ActionKind, Vec<Action>> = HashMap::new();
+        for action in &self.legal_actions {
+            grouped.entry(action.kind()).or_default().push(action.clone());
+        }
+        grouped
+    }
+
     pub fn time_remaining(&self) -> Duration {
         let url = &self.time_endpoint_url;
         let response = reqwest::blocking::get(url).expect("Could not contact game server");